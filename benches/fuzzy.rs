@@ -0,0 +1,107 @@
+//! Times matching a query against a few thousand application names, which the launcher does on
+//! every key press. Run with `cargo bench --bench fuzzy`, every query should stay below 1ms.
+
+#[allow(dead_code)]
+#[path = "../src/launcher/fuzzy.rs"]
+mod fuzzy;
+
+use fuzzy::Matcher;
+use std::time::{Duration, Instant};
+
+const NAMES: usize = 5000;
+const RUNS: u32 = 50;
+const BUDGET: Duration = Duration::from_millis(1);
+
+// Names made of words found in application names, such as "Gnome Disk Utility: New Window".
+fn names() -> Vec<String> {
+    const WORDS: [&str; 24] = [
+        "Firefox",
+        "Terminal",
+        "gnome",
+        "Disk",
+        "Utility",
+        "LibreOffice",
+        "Calc",
+        "Writer",
+        "Image",
+        "Viewer",
+        "System",
+        "Monitor",
+        "Settings",
+        "Text",
+        "Editor",
+        "Files",
+        "Music",
+        "Player",
+        "Web",
+        "Browser",
+        "Video",
+        "Mail",
+        "Calendar",
+        "Über",
+    ];
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut next = |bound: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % bound as u64) as usize
+    };
+    (0..NAMES)
+        .map(|_| {
+            let words = (0..1 + next(4))
+                .map(|_| WORDS[next(WORDS.len())])
+                .collect::<Vec<_>>();
+            let name = words.join(" ");
+            match next(4) {
+                0 => format!("{}: New Window", name),
+                _ => name,
+            }
+        })
+        .collect()
+}
+
+fn main() {
+    let names = names();
+    let queries = [
+        "f",
+        "fi",
+        "fir",
+        "fire",
+        "term",
+        "gdu",
+        "lo calc",
+        "SysMon",
+        "über",
+        "web browser",
+        "zzz",
+    ];
+    let mut over = false;
+    for query in &queries {
+        let mut matches = 0;
+        let mut slowest = Duration::from_secs(0);
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            let run = Instant::now();
+            let mut matcher = Matcher::new(query);
+            matches = names.iter().filter_map(|n| matcher.score(n)).count();
+            slowest = slowest.max(run.elapsed());
+        }
+        let mean = start.elapsed() / RUNS;
+        over |= mean > BUDGET;
+        println!(
+            "{:>14} {:>5} matches, {:>9.3?} mean, {:>9.3?} slowest{}",
+            format!("{:?}", query),
+            matches,
+            mean,
+            slowest,
+            if mean > BUDGET { "  over budget" } else { "" }
+        );
+    }
+    if over {
+        println!(
+            "\nSome queries took longer than {:?} for {} names",
+            BUDGET, NAMES
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wayland_scanner::Side;
+
+const SHADER_DIR: &str = "src/draw/shader";
+// Protocols that wayland-protocols doesn't have yet, generated into `$OUT_DIR/<name>.rs`.
+const PROTOCOLS: [(&str, &str); 2] = [
+    (
+        "protocols/fractional-scale-v1.xml",
+        "fractional_scale_v1.rs",
+    ),
+    ("protocols/xdg-activation-v1.xml", "xdg_activation_v1.rs"),
+];
+
+// Compiles every GLSL shader to `$OUT_DIR/<name>.spv` so they can be embedded with
+// `include_bytes!`. When no compiler is installed the checked in `<name>.spv` is used instead.
+// Also generates the client code of `PROTOCOLS`.
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+
+    for entry in fs::read_dir(SHADER_DIR).unwrap() {
+        let source = entry.unwrap().path();
+        match source.extension().and_then(|ext| ext.to_str()) {
+            Some("vert") | Some("frag") => {}
+            _ => continue,
+        }
+        println!("cargo:rerun-if-changed={}", source.display());
+
+        let prebuilt = source.with_extension("spv");
+        let target = out_dir.join(prebuilt.file_name().unwrap());
+        if !compile(&source, &target) {
+            fs::copy(&prebuilt, &target)
+                .unwrap_or_else(|e| panic!("Could not copy {}: {}", prebuilt.display(), e));
+        }
+    }
+
+    for (protocol, generated) in PROTOCOLS.iter() {
+        println!("cargo:rerun-if-changed={}", protocol);
+        wayland_scanner::generate_code(protocol, out_dir.join(generated), Side::Client);
+    }
+}
+
+// Returns false if neither glslc nor glslangValidator are installed.
+fn compile(source: &Path, target: &Path) -> bool {
+    let compilers: [(&str, &[&str]); 2] = [("glslc", &[]), ("glslangValidator", &["-V"])];
+    for (compiler, flags) in compilers.iter() {
+        let output = Command::new(compiler)
+            .args(flags.iter())
+            .arg(source)
+            .arg("-o")
+            .arg(target)
+            .output();
+        match output {
+            Ok(output) if output.status.success() => return true,
+            Ok(output) => panic!(
+                "{} failed to compile {}:\n{}{}",
+                compiler,
+                source.display(),
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => panic!("Could not run {}: {}", compiler, e),
+        }
+    }
+    false
+}
@@ -0,0 +1,283 @@
+use crate::config::Override;
+use std::fmt;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: infolauncher [OPTIONS] [COMMAND]
+
+Commands:
+    run             Show the status bar together with the launcher (default)
+    launch-only     Open the launcher and exit once an application is launched
+    bar-only        Show the status bar without the launcher
+    check-config    Validate the config file without opening a window
+    history list    Print launched applications, most frecent first (default)
+    history clear   Forget every launched application
+    version         Print version and build information
+
+Options:
+    -c, --config <PATH>      Read the config from PATH instead of searching for it
+    -s, --set <KEY=VALUE>    Override a config key, e.g. `--set window.width=800`
+    -g, --gpu <GPU>          Draw with the GPU of this index, UUID or part of its name
+        --list-gpus          Print the GPUs found and which one would be drawn with
+    -h, --help               Print this message
+    -V, --version            Print version and build information
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Full,
+    Launcher,
+    Bar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run(Mode),
+    CheckConfig,
+    ListGpus,
+    History(HistoryCommand),
+    Version,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryCommand {
+    List,
+    Clear,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub config: Option<PathBuf>,
+    pub overrides: Vec<Override>,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownCommand(String),
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidOverride(String),
+    UnexpectedArgument(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::UnknownCommand(cmd) => write!(f, "unknown command `{}`", cmd),
+            CliError::UnknownFlag(flag) => write!(f, "unknown option `{}`", flag),
+            CliError::MissingValue(flag) => write!(f, "option `{}` requires a value", flag),
+            CliError::InvalidOverride(e) => write!(f, "invalid --set: {}", e),
+            CliError::UnexpectedArgument(arg) => write!(f, "unexpected argument `{}`", arg),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Parses the arguments following the program name. Options may appear before or after the
+/// command, and both `--flag value` and `--flag=value` are accepted.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, CliError> {
+    let mut parsed = Args {
+        command: Command::Run(Mode::Full),
+        config: None,
+        overrides: Vec::new(),
+    };
+    let mut command = None;
+
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.find('=') {
+            Some(eq) if arg.starts_with("--") => (&arg[..eq], Some(arg[eq + 1..].to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.to_owned()))
+        };
+
+        match flag {
+            "-h" | "--help" => {
+                return Ok(Args {
+                    command: Command::Help,
+                    ..parsed
+                })
+            }
+            "-V" | "--version" => command = Some(Command::Version),
+            "-c" | "--config" => parsed.config = Some(PathBuf::from(value()?)),
+            "-g" | "--gpu" => parsed.overrides.push(Override {
+                key: "gpu.device".to_owned(),
+                value: value()?,
+            }),
+            "--list-gpus" => command = Some(Command::ListGpus),
+            "-s" | "--set" => parsed
+                .overrides
+                .push(value()?.parse().map_err(CliError::InvalidOverride)?),
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(CliError::UnknownFlag(flag.to_owned()))
+            }
+            _ if command.is_some() => return Err(CliError::UnexpectedArgument(arg)),
+            "run" => command = Some(Command::Run(Mode::Full)),
+            "launch-only" => command = Some(Command::Run(Mode::Launcher)),
+            "bar-only" => command = Some(Command::Run(Mode::Bar)),
+            "check-config" => command = Some(Command::CheckConfig),
+            "history" => {
+                let history = match args.peek().map(String::as_str) {
+                    Some("list") => HistoryCommand::List,
+                    Some("clear") => HistoryCommand::Clear,
+                    Some(sub) if !sub.starts_with('-') => {
+                        return Err(CliError::UnknownCommand(format!("history {}", sub)))
+                    }
+                    _ => {
+                        command = Some(Command::History(HistoryCommand::List));
+                        continue;
+                    }
+                };
+                args.next();
+                command = Some(Command::History(history));
+            }
+            "version" => command = Some(Command::Version),
+            _ => return Err(CliError::UnknownCommand(arg)),
+        }
+    }
+
+    if let Some(command) = command {
+        parsed.command = command;
+    }
+    Ok(parsed)
+}
+
+pub fn version() -> String {
+    format!(
+        "{} {} ({} build)",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Args, CliError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn command(args: &[&str]) -> Command {
+        parse_args(args).unwrap().command
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(command(&[]), Command::Run(Mode::Full));
+        assert_eq!(command(&["run"]), Command::Run(Mode::Full));
+        assert_eq!(command(&["launch-only"]), Command::Run(Mode::Launcher));
+        assert_eq!(command(&["bar-only"]), Command::Run(Mode::Bar));
+        assert_eq!(command(&["check-config"]), Command::CheckConfig);
+        assert_eq!(command(&["--list-gpus"]), Command::ListGpus);
+        assert_eq!(command(&["version"]), Command::Version);
+        assert_eq!(command(&["-V"]), Command::Version);
+        assert_eq!(command(&["bar-only", "--help"]), Command::Help);
+        assert!(matches!(
+            parse_args(&["launch"]),
+            Err(CliError::UnknownCommand(cmd)) if cmd == "launch"
+        ));
+        assert!(matches!(
+            parse_args(&["run", "bar-only"]),
+            Err(CliError::UnexpectedArgument(arg)) if arg == "bar-only"
+        ));
+    }
+
+    #[test]
+    fn history() {
+        assert_eq!(
+            command(&["history"]),
+            Command::History(HistoryCommand::List)
+        );
+        assert_eq!(
+            command(&["history", "list"]),
+            Command::History(HistoryCommand::List)
+        );
+        assert_eq!(
+            command(&["history", "clear"]),
+            Command::History(HistoryCommand::Clear)
+        );
+        assert_eq!(
+            command(&["history", "-c", "config.toml"]),
+            Command::History(HistoryCommand::List)
+        );
+        assert!(matches!(
+            parse_args(&["history", "forget"]),
+            Err(CliError::UnknownCommand(cmd)) if cmd == "history forget"
+        ));
+    }
+
+    #[test]
+    fn options() {
+        let args = parse_args(&[
+            "-c",
+            "a.toml",
+            "launch-only",
+            "--set=window.width=800",
+            "-s",
+            "font.family=Fira Code",
+            "--gpu",
+            "Intel",
+        ])
+        .unwrap();
+        assert_eq!(args.command, Command::Run(Mode::Launcher));
+        assert_eq!(args.config, Some(PathBuf::from("a.toml")));
+        let overrides = args
+            .overrides
+            .iter()
+            .map(|o| (o.key.as_str(), o.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            overrides,
+            [
+                ("window.width", "800"),
+                ("font.family", "Fira Code"),
+                ("gpu.device", "Intel")
+            ]
+        );
+
+        let args = parse_args(&["--config=b.toml"]).unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("b.toml")));
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert!(matches!(
+            parse_args(&["-c"]),
+            Err(CliError::MissingValue(flag)) if flag == "-c"
+        ));
+        assert!(matches!(
+            parse_args(&["run", "--set"]),
+            Err(CliError::MissingValue(flag)) if flag == "--set"
+        ));
+        assert!(matches!(
+            parse_args(&["--set", "window.width"]),
+            Err(CliError::InvalidOverride(_))
+        ));
+        assert!(matches!(
+            parse_args(&["--set", "window..width=3"]),
+            Err(CliError::InvalidOverride(_))
+        ));
+        assert!(matches!(
+            parse_args(&["--verbose"]),
+            Err(CliError::UnknownFlag(flag)) if flag == "--verbose"
+        ));
+        assert!(matches!(
+            parse_args(&["--colour=red"]),
+            Err(CliError::UnknownFlag(flag)) if flag == "--colour"
+        ));
+    }
+}
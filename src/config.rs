@@ -0,0 +1,550 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+const APP_DIR: &str = "infolauncher";
+const FILE_NAME: &str = "config.toml";
+const PATH_OVERRIDE_VAR: &str = "INFOLAUNCHER_CONFIG";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub bar: BarConfig,
+    pub gpu: GpuConfig,
+    pub colors: Colors,
+    pub font: FontConfig,
+    pub launcher: LauncherConfig,
+    pub bindings: BindingsConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    /// Zero stretches the window between the left and right edges, which it has to be anchored
+    /// to.
+    pub width: u32,
+    /// Zero stretches the window between the top and bottom edges.
+    pub height: u32,
+    pub anchor: Vec<Edge>,
+    pub margin: Margin,
+    pub layer: Layer,
+    /// Names or parts of descriptions of the outputs to open on. The bar opens on every one of
+    /// them, or on every output if this is empty. The launcher opens on the first one connected.
+    pub outputs: Vec<String>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 500,
+            height: 500,
+            anchor: vec![Edge::Top],
+            margin: Margin::default(),
+            layer: Layer::Top,
+            outputs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Margin {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+/// The status bar, spanning the whole width of the output. In `run` mode it expands into the
+/// launcher, which takes the height from `WindowConfig`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarConfig {
+    /// Top or bottom.
+    pub edge: Edge,
+    pub height: u32,
+    /// Whether windows are kept from covering the bar.
+    pub exclusive: bool,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        Self {
+            edge: Edge::Top,
+            height: 28,
+            exclusive: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuConfig {
+    /// The vulkan physical device to render with, or the best suited one if unset.
+    pub device: Option<GpuSelector>,
+    /// Directory of `.spv` files replacing the built-in shaders of the same name.
+    pub shader_dir: Option<PathBuf>,
+}
+
+/// A GPU by its index, or by part of its name or its UUID.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GpuSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for GpuSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpuSelector::Index(index) => write!(f, "{}", index),
+            GpuSelector::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub background: Color,
+    pub foreground: Color,
+    /// Characters of launcher results that matched the query.
+    pub highlight: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            background: Color([1.0, 1.0, 0.0, 1.0]),
+            foreground: Color([0.2, 0.2, 0.2, 1.0]),
+            highlight: Color([0.8, 0.2, 0.0, 1.0]),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontConfig {
+    /// Family name, resolved through fontconfig when available.
+    pub family: String,
+    /// Font file to use instead of looking up `family`.
+    pub path: Option<PathBuf>,
+    /// Size of the em square in pixels.
+    pub size: f32,
+    /// Families to take glyphs from when the main font lacks them.
+    pub fallback: Vec<String>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            family: String::from("monospace"),
+            path: None,
+            size: 16.0,
+            fallback: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LauncherConfig {
+    /// Command that runs the arguments after it in a terminal, for applications with
+    /// `Terminal=true`. Empty means `$TERMINAL -e`, or `xterm -e` without it.
+    pub terminal: Vec<String>,
+}
+
+/// Key sequences such as `"ctrl+x ctrl+c"` mapped to action names, on top of the built-in
+/// bindings of each mode. The action `none` removes a built-in binding.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BindingsConfig {
+    pub launcher: BTreeMap<String, String>,
+    pub command: BTreeMap<String, String>,
+    pub bar: BTreeMap<String, String>,
+}
+
+/// RGBA as written in the config file, `#rrggbb` or `#rrggbbaa`. The channels are sRGB-encoded
+/// and drawn as they are, without converting them to linear.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Color(pub [f32; 4]);
+
+impl Color {
+    pub fn rgb(self) -> [f32; 3] {
+        [self.0[0], self.0[1], self.0[2]]
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid color {:?}, expected #rrggbb or #rrggbbaa", s);
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut rgba = [1.0; 4];
+        for (i, channel) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+            let byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+            *channel = byte as f32 / 255.0;
+        }
+        Ok(Color(rgba))
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NotFound(PathBuf),
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        source: String,
+        error: toml::de::Error,
+    },
+    Override {
+        key: Option<String>,
+        message: String,
+    },
+    /// Values that parse, but don't make sense together.
+    Invalid {
+        key: &'static str,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NotFound(path) => {
+                write!(f, "config file {} does not exist", path.display())
+            }
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse {
+                path,
+                source,
+                error,
+            } => {
+                writeln!(f, "error: {}", error)?;
+                let (line, col) = match error.line_col() {
+                    Some(pos) => pos,
+                    None => return write!(f, " --> {}", path.display()),
+                };
+                let number = (line + 1).to_string();
+                let pad = " ".repeat(number.len());
+                writeln!(f, "{}--> {}:{}:{}", pad, path.display(), line + 1, col + 1)?;
+                writeln!(f, "{} |", pad)?;
+                writeln!(f, "{} | {}", number, source.lines().nth(line).unwrap_or(""))?;
+                write!(f, "{} | {}^", pad, " ".repeat(col))
+            }
+            ConfigError::Override {
+                key: Some(key),
+                message,
+            } => {
+                write!(f, "invalid override for `{}`: {}", key, message)
+            }
+            ConfigError::Override { key: None, message } => {
+                write!(f, "invalid override: {}", message)
+            }
+            ConfigError::Invalid { key, message } => {
+                write!(f, "invalid value for `{}`: {}", key, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads `path`, or the first config file found by `find_path` when none is given, and applies
+    /// `overrides` on top. Falls back to the defaults when the user has not written a config file.
+    pub fn load(path: Option<&Path>, overrides: &[Override]) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(path) if path.is_file() => Some(path.to_owned()),
+            Some(path) => return Err(ConfigError::NotFound(path.to_owned())),
+            None => find_path()?,
+        };
+
+        let (config, mut root) = match &path {
+            Some(path) => {
+                let source =
+                    fs::read_to_string(path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+                let parse_error = |error| ConfigError::Parse {
+                    path: path.clone(),
+                    source: source.clone(),
+                    error,
+                };
+                let root = toml::from_str::<Value>(&source).map_err(parse_error)?;
+                (
+                    toml::from_str::<Config>(&source).map_err(parse_error)?,
+                    root,
+                )
+            }
+            None => (Config::default(), Value::Table(Table::new())),
+        };
+        if overrides.is_empty() {
+            config.validate()?;
+            return Ok(config);
+        }
+
+        for o in overrides {
+            o.apply(&mut root)?;
+        }
+        let config: Config =
+            root.try_into()
+                .map_err(|e: toml::de::Error| ConfigError::Override {
+                    key: None,
+                    message: e.to_string(),
+                })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let anchored = |a, b| self.window.anchor.contains(&a) && self.window.anchor.contains(&b);
+        if self.window.width == 0 && !anchored(Edge::Left, Edge::Right) {
+            return Err(ConfigError::Invalid {
+                key: "window.width",
+                message: String::from(
+                    "0 stretches the window, which needs anchoring to both left and right",
+                ),
+            });
+        }
+        if self.window.height == 0 && !anchored(Edge::Top, Edge::Bottom) {
+            return Err(ConfigError::Invalid {
+                key: "window.height",
+                message: String::from(
+                    "0 stretches the window, which needs anchoring to both top and bottom",
+                ),
+            });
+        }
+        if self.bar.edge != Edge::Top && self.bar.edge != Edge::Bottom {
+            return Err(ConfigError::Invalid {
+                key: "bar.edge",
+                message: String::from("the bar goes along the top or bottom edge"),
+            });
+        }
+        if self.bar.height == 0 {
+            return Err(ConfigError::Invalid {
+                key: "bar.height",
+                message: String::from("the bar needs some height"),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A `key=value` pair given on the command line, where `key` is a dotted path such as
+/// `window.width`. The value is read as a TOML value, or as a plain string if it isn't one.
+#[derive(Debug, Clone)]
+pub struct Override {
+    pub key: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let eq = s
+            .find('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", s))?;
+        let (key, value) = (&s[..eq], &s[eq + 1..]);
+        if key.split('.').any(str::is_empty) {
+            return Err(format!("invalid config key {:?}", key));
+        }
+        Ok(Override {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+
+impl Override {
+    fn value(&self) -> Value {
+        toml::from_str::<Table>(&format!("v = {}", self.value))
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or_else(|| Value::String(self.value.clone()))
+    }
+
+    fn apply(&self, root: &mut Value) -> Result<(), ConfigError> {
+        let not_a_table = |key: &str| ConfigError::Override {
+            key: Some(self.key.clone()),
+            message: format!("`{}` is not a table", key),
+        };
+
+        let mut table = root.as_table_mut().ok_or_else(|| not_a_table(""))?;
+        let mut segments = self.key.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            if segments.peek().is_none() {
+                table.insert(segment.to_owned(), self.value());
+                break;
+            }
+            table = table
+                .entry(segment.to_owned())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| not_a_table(segment))?;
+        }
+        Ok(())
+    }
+}
+
+/// `$INFOLAUNCHER_CONFIG` if set, otherwise the first `infolauncher/config.toml` that exists in
+/// `$XDG_CONFIG_HOME` followed by `$XDG_CONFIG_DIRS`.
+pub fn find_path() -> Result<Option<PathBuf>, ConfigError> {
+    if let Some(path) = std::env::var_os(PATH_OVERRIDE_VAR) {
+        let path = PathBuf::from(path);
+        return if path.is_file() {
+            Ok(Some(path))
+        } else {
+            Err(ConfigError::NotFound(path))
+        };
+    }
+
+    Ok(config_dirs()
+        .into_iter()
+        .map(|dir| dir.join(APP_DIR).join(FILE_NAME))
+        .find(|path| path.is_file()))
+}
+
+fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    match non_empty_var("XDG_CONFIG_HOME") {
+        Some(home) => dirs.push(PathBuf::from(home)),
+        None => {
+            if let Some(home) = non_empty_var("HOME") {
+                dirs.push(Path::new(&home).join(".config"));
+            }
+        }
+    }
+    let system = non_empty_var("XDG_CONFIG_DIRS").unwrap_or_else(|| String::from("/etc/xdg"));
+    dirs.extend(
+        system
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+    );
+    dirs
+}
+
+fn non_empty_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A config file in the temporary directory, unique to the test that writes it.
+    fn write_config(name: &str, source: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("infolauncher-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn empty_file_is_the_defaults() {
+        let path = write_config("empty", "");
+        let config = Config::load(Some(&path), &[]).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.window.width, 500);
+        assert_eq!(config.window.anchor, vec![Edge::Top]);
+        assert_eq!(config.window.layer, Layer::Top);
+        assert_eq!(config.bar.height, 28);
+        assert_eq!(config.gpu.device, None);
+        assert_eq!(config.font.family, "monospace");
+        assert_eq!(config.colors.background, Color([1.0, 1.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn env_var_overrides_search() {
+        let path = write_config("env", "[window]\nwidth = 800\n");
+        std::env::set_var(PATH_OVERRIDE_VAR, &path);
+        let found = find_path();
+        let config = Config::load(None, &[]);
+        std::env::set_var(PATH_OVERRIDE_VAR, path.with_extension("missing"));
+        let missing = find_path();
+        std::env::remove_var(PATH_OVERRIDE_VAR);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(found.unwrap(), Some(path));
+        assert_eq!(config.unwrap().window.width, 800);
+        assert!(matches!(missing, Err(ConfigError::NotFound(_))));
+    }
+
+    #[test]
+    fn overrides_apply_on_top() {
+        let path = write_config("overrides", "[window]\nwidth = 800\n");
+        let overrides = [
+            "window.width=300".parse().unwrap(),
+            "font.family=Fira Code".parse().unwrap(),
+        ];
+        let config = Config::load(Some(&path), &overrides).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.window.width, 300);
+        assert_eq!(config.font.family, "Fira Code");
+    }
+
+    #[test]
+    fn parse_error_points_at_line_and_column() {
+        let path = write_config("parse", "[window]\nwidth = = 3\n");
+        let error = Config::load(Some(&path), &[]).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        let (line, col) = match &error {
+            ConfigError::Parse { error, .. } => error.line_col().unwrap(),
+            e => panic!("expected a parse error, got {:?}", e),
+        };
+        assert_eq!(line, 1);
+        let message = error.to_string();
+        let lines = message.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], format!(" --> {}:2:{}", path.display(), col + 1));
+        assert_eq!(lines[3], "2 | width = = 3");
+        assert_eq!(lines[4], format!("  | {}^", " ".repeat(col)));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            Color::try_from(String::from("#ff000080")),
+            Ok(Color([1.0, 0.0, 0.0, 128.0 / 255.0]))
+        );
+        assert_eq!(
+            Color::try_from(String::from("#336699")),
+            Ok(Color([0.2, 0.4, 0.6, 1.0]))
+        );
+        assert!(Color::try_from(String::from("336699")).is_err());
+        assert!(Color::try_from(String::from("#3369")).is_err());
+        assert!(Color::try_from(String::from("#33669g")).is_err());
+    }
+}
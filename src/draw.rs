@@ -1,187 +1,1163 @@
-use std::path::Path;
-use std::sync::Arc;
-use std::sync::Mutex;
-use vulkano::sync::GpuFuture;
-use wayland_client::protocol::{wl_keyboard, wl_seat};
-use wayland_client::Filter;
+use crate::cli::Mode;
+use crate::config::{BarConfig, Colors, Config, GpuConfig, WindowConfig};
+use crate::input::bindings::{Action, BindingMode, Bindings, Feed};
+use crate::input::clipboard::Clipboard;
+use crate::input::editor::Editor;
+use crate::input::keyboard::{KeyEvent, Keyboard, Repeat};
+use crate::input::pointer::{Cursor, Pointer, PointerEvent};
+use crate::launcher::{self, exec::Executor, history::History};
+use crate::ui::{self, Align, Edges, Length, Primitive, Widget};
+use event_loop::{EventLoop, Request};
+use state::{Controller, Event, State};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use vulkano::swapchain::{AcquireError, SwapchainCreationError};
+use vulkano::sync::{self, FlushError, GpuFuture};
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::protocol::{
+    wl_data_device_manager, wl_keyboard, wl_pointer, wl_seat, wl_shm, wl_touch,
+};
+use wayland_client::{Display, Filter};
+mod event_loop;
+mod state;
 mod vulkan;
 mod window;
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
 use vulkano::command_buffer::AutoCommandBufferBuilder;
-use vulkano::framebuffer::Subpass;
-use vulkano::pipeline::GraphicsPipeline;
 use vulkano::swapchain;
 
+mod rect;
 mod shader;
+pub mod text;
 
-#[derive(PartialEq, Eq)]
-pub enum Status {
-    Running,
-    Closing,
+const MAX_RESULTS: usize = 8;
+// Tag of the collapsed bar in `run` mode, which opens the launcher when clicked. Results are
+// tagged with their index.
+const OPEN_LAUNCHER: usize = usize::MAX;
+const CARET_BLINK: Duration = Duration::from_millis(530);
+// The caret stops blinking when nothing is typed for this long, so an idle launcher doesn't
+// keep redrawing
+const CARET_BLINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum DrawError {
+    EventLoop(io::Error),
+    Window(window::WindowError),
+    Vulkan(vulkan::VkSessionError),
+    Font(text::FontError),
+    Shader(shader::ShaderError),
+    /// The glyphs and icons of a frame don't fit in the largest texture.
+    AtlasFull,
 }
-lazy_static! {
-    pub static ref STATUS: Arc<Mutex<Status>> = Arc::new(Mutex::new(Status::Running));
+
+impl DrawError {
+    /// The status to exit with: 3 when the compositor is the problem, 4 when the GPU is, and 1
+    /// for anything else.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DrawError::Window(_) => 3,
+            DrawError::Vulkan(_) => 4,
+            DrawError::EventLoop(_)
+            | DrawError::Font(_)
+            | DrawError::Shader(_)
+            | DrawError::AtlasFull => 1,
+        }
+    }
 }
 
-pub struct Drawer {
-    vk: vulkan::VkSession,
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrawError::EventLoop(e) => write!(f, "could not set up the event loop: {}", e),
+            DrawError::Window(e) => e.fmt(f),
+            DrawError::Vulkan(e) => e.fmt(f),
+            DrawError::Font(e) => {
+                write!(f, "{}, pick another one with font.family or font.path", e)
+            }
+            DrawError::Shader(e) => e.fmt(f),
+            DrawError::AtlasFull => write!(f, "too much text to draw at once, use a smaller font"),
+        }
+    }
 }
 
-impl Drawer {
-    pub fn initialize() -> Drawer {
-        let window = window::Window::spawn(500, 500, 0, 0).unwrap();
+impl std::error::Error for DrawError {}
 
-        let physical_device_id = 0;
-        let vk = vulkan::VkSession::initialize(physical_device_id, window).unwrap();
+impl From<window::WindowError> for DrawError {
+    fn from(e: window::WindowError) -> Self {
+        Self::Window(e)
+    }
+}
 
-        Self { vk }
+impl From<vulkan::VkSessionError> for DrawError {
+    fn from(e: vulkan::VkSessionError) -> Self {
+        Self::Vulkan(e)
     }
+}
 
-    fn window(&self) -> &window::Window {
-        self.vk.draw_surface.window()
+impl From<text::FontError> for DrawError {
+    fn from(e: text::FontError) -> Self {
+        Self::Font(e)
     }
+}
 
-    pub fn listen_events(mut self) {
+impl From<shader::ShaderError> for DrawError {
+    fn from(e: shader::ShaderError) -> Self {
+        Self::Shader(e)
+    }
+}
+
+/// Prints every GPU, and the one `config` picks to draw with.
+pub fn list_gpus(config: &GpuConfig) -> Result<(), DrawError> {
+    let display = Display::connect_to_env().map_err(window::WindowError::from)?;
+    let instance = vulkan::create_instance().map_err(vulkan::VkSessionError::from)?;
+    let gpus = vulkan::gpus(&instance, &display);
+    for gpu in &gpus {
+        println!("{}", gpu);
+    }
+    let gpu = vulkan::choose(&gpus, config.device.as_ref())?;
+    println!("\nDrawing with {}: {}", gpu.index, gpu.name);
+    Ok(())
+}
+
+// A surface being drawn to.
+struct Screen {
+    /// Global name of the output the surface was put on, unless the compositor chose it.
+    output: Option<u32>,
+    target: vulkan::Target,
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    recreate_swapchain: bool,
+    /// Buffer size the swapchain couldn't be recreated at, which isn't tried again until the
+    /// compositor settles on another one.
+    unsupported_size: Option<[u32; 2]>,
+    dirty: bool,
+    /// Last, so that it is destroyed after everything drawing to it.
+    surface: window::LayerSurface,
+}
+
+impl Screen {
+    fn surface(&self) -> &window::LayerSurface {
+        &self.surface
+    }
+
+    // Whether the swapchain is out of date and can be recreated right away. Otherwise the frame
+    // callback or a configure event wake the loop up when it can.
+    fn can_recreate(&self) -> bool {
+        let [width, height] = self.surface().size.get();
+        self.recreate_swapchain
+            && !self.surface().frame_pending.get()
+            && width != 0
+            && height != 0
+            && self.unsupported_size != Some(self.surface().buffer_size())
+    }
+}
+
+pub struct Drawer {
+    event_loop: EventLoop,
+    window: window::Window,
+    window_config: WindowConfig,
+    bar_config: BarConfig,
+    vk: vulkan::VkSession,
+    screens: Vec<Screen>,
+    /// Outputs whose bar the compositor closed, which aren't opened again until they are
+    /// plugged in again.
+    closed_outputs: Vec<u32>,
+    colors: Colors,
+    shader_dir: Option<PathBuf>,
+    text: text::TextRenderer,
+    text_style: text::TextStyle,
+    rects: rect::RectRenderer,
+    mode: Mode,
+    launcher: Option<launcher::Index>,
+    search: launcher::Search,
+    history: History,
+    executor: Executor,
+    bindings: Bindings,
+    editor: Editor,
+    clipboard: Option<Clipboard>,
+    /// Whether the launcher is open, what is typed into it and which result is highlighted.
+    state: Controller,
+    /// Why the last launch failed, shown until the query changes.
+    error: Option<String>,
+    /// Keys pressed since the last time they were handled.
+    keys: Rc<RefCell<Vec<KeyEvent>>>,
+    repeat: Rc<RefCell<Repeat>>,
+    /// Pointer and touch input since the last time it was handled.
+    pointer_events: Rc<RefCell<Vec<PointerEvent>>>,
+    cursor: Rc<RefCell<Option<Cursor>>>,
+    /// The surface the pointer is over.
+    pointer_focus: Rc<RefCell<Option<WlSurface>>>,
+    seat: Option<wl_seat::WlSeat>,
+    /// Serial of the last key press, click or touch, and the surface it was on. Launched
+    /// applications are given the focus on its behalf.
+    last_input: Rc<RefCell<Option<(u32, WlSurface)>>>,
+    /// Whether the window has the keyboard focus, which is when the caret is shown.
+    focused: Rc<Cell<bool>>,
+    /// When the caret last started blinking, which restarts on every edit.
+    caret_since: Instant,
+    caret_shown: bool,
+    /// Set when the view changed, and every screen has to be drawn again.
+    dirty: bool,
+}
+
+impl Drawer {
+    pub fn initialize(
+        config: &Config,
+        mode: Mode,
+        bindings: Bindings,
+    ) -> Result<Drawer, DrawError> {
+        // First, since it has to come before any thread is started
+        let event_loop = EventLoop::new().map_err(DrawError::EventLoop)?;
+        let window = window::Window::connect()?;
+        let vk = vulkan::VkSession::initialize(&config.gpu, &window.display)?;
+        let fonts = text::FontSet::load(&config.font)?;
+        let launcher = match mode {
+            Mode::Bar => None,
+            Mode::Full | Mode::Launcher => Some(launcher::Index::load()),
+        };
+        let history = History::load();
+        let mut search = launcher::Search::default();
+        if let Some(index) = &launcher {
+            search.update(index, &history, "");
+        }
+
+        Ok(Self {
+            event_loop,
+            window,
+            window_config: config.window.clone(),
+            bar_config: config.bar.clone(),
+            vk,
+            screens: Vec::new(),
+            closed_outputs: Vec::new(),
+            colors: config.colors.clone(),
+            shader_dir: config.gpu.shader_dir.clone(),
+            text: text::TextRenderer::new(fonts),
+            text_style: text::TextStyle {
+                size: config.font.size,
+                color: config.colors.foreground.0,
+            },
+            rects: rect::RectRenderer::default(),
+            mode,
+            launcher,
+            search,
+            history,
+            executor: Executor::new(&config.launcher.terminal),
+            bindings,
+            editor: Editor::default(),
+            clipboard: None,
+            state: Controller::new(mode),
+            error: None,
+            keys: Rc::new(RefCell::new(Vec::new())),
+            repeat: Rc::new(RefCell::new(Repeat::default())),
+            pointer_events: Rc::new(RefCell::new(Vec::new())),
+            cursor: Rc::new(RefCell::new(None)),
+            pointer_focus: Rc::new(RefCell::new(None)),
+            seat: None,
+            last_input: Rc::new(RefCell::new(None)),
+            focused: Rc::new(Cell::new(false)),
+            caret_since: Instant::now(),
+            caret_shown: false,
+            dirty: true,
+        })
+    }
+
+    pub fn listen_events(mut self) -> Result<(), DrawError> {
+        let keys = self.keys.clone();
+        let repeat = self.repeat.clone();
+        let focused = self.focused.clone();
+        let keyboard = RefCell::new(Keyboard::default());
+        let pointer_events = self.pointer_events.clone();
+        let cursor = self.cursor.clone();
+        let pointer = RefCell::new(Pointer::default());
+        let last_input = self.last_input.clone();
+        let keyboard_focus: RefCell<Option<WlSurface>> = RefCell::new(None);
+        let pointer_focus = self.pointer_focus.clone();
         let common_filter = Filter::new(move |event, _| match event {
             Events::Keyboard { event, .. } => match event {
-                wl_keyboard::Event::Enter { .. } => println!("Gained keyboard focus"),
-                wl_keyboard::Event::Leave { .. } => println!("Lost keyboard focus"),
-                wl_keyboard::Event::Key { key, state, .. } => {
-                    if key == 1 && state == wl_keyboard::KeyState::Pressed {
-                        println!("Setting closing status");
-                        *STATUS.lock().unwrap() = Status::Closing;
-                    } else {
-                        print!("{} ", key);
+                wl_keyboard::Event::Keymap { format, fd, size } => {
+                    let xkb_v1 = format == wl_keyboard::KeymapFormat::XkbV1;
+                    if let Err(e) = keyboard.borrow_mut().set_keymap(xkb_v1, fd, size) {
+                        eprintln!("Ignoring keymap: {}", e);
                     }
                 }
+                wl_keyboard::Event::Modifiers {
+                    mods_depressed,
+                    mods_latched,
+                    mods_locked,
+                    group,
+                    ..
+                } => keyboard.borrow_mut().set_modifiers(
+                    mods_depressed,
+                    mods_latched,
+                    mods_locked,
+                    group,
+                ),
+                wl_keyboard::Event::Key {
+                    serial, key, state, ..
+                } => match state {
+                    wl_keyboard::KeyState::Pressed => {
+                        if let Some(surface) = &*keyboard_focus.borrow() {
+                            *last_input.borrow_mut() = Some((serial, surface.clone()));
+                        }
+                        let mut keyboard = keyboard.borrow_mut();
+                        if let Some(event) = keyboard.press(key) {
+                            if keyboard.repeats(key) {
+                                repeat.borrow_mut().press(key, event.clone());
+                            }
+                            keys.borrow_mut().push(event);
+                        }
+                    }
+                    _ => repeat.borrow_mut().release(key),
+                },
+                wl_keyboard::Event::RepeatInfo { rate, delay } => {
+                    repeat.borrow_mut().set_info(rate, delay)
+                }
+                wl_keyboard::Event::Enter { surface, .. } => {
+                    *keyboard_focus.borrow_mut() = Some(surface);
+                    focused.set(true);
+                }
+                wl_keyboard::Event::Leave { .. } => {
+                    *keyboard_focus.borrow_mut() = None;
+                    focused.set(false);
+                    repeat.borrow_mut().cancel();
+                }
                 _ => (),
             },
+            Events::Pointer { event, object } => {
+                match &event {
+                    wl_pointer::Event::Enter { surface, .. } => {
+                        *pointer_focus.borrow_mut() = Some(surface.clone())
+                    }
+                    wl_pointer::Event::Leave { .. } => *pointer_focus.borrow_mut() = None,
+                    wl_pointer::Event::Button { serial, .. } => {
+                        if let Some(surface) = &*pointer_focus.borrow() {
+                            *last_input.borrow_mut() = Some((*serial, surface.clone()));
+                        }
+                    }
+                    _ => {}
+                }
+                if let Some(cursor) = cursor.borrow_mut().as_mut() {
+                    match event {
+                        wl_pointer::Event::Enter { serial, .. } => cursor.enter(&object, serial),
+                        wl_pointer::Event::Leave { .. } => cursor.leave(),
+                        _ => {}
+                    }
+                }
+                let event = pointer.borrow_mut().pointer(event);
+                pointer_events.borrow_mut().extend(event);
+            }
+            Events::Touch { event, .. } => {
+                if let wl_touch::Event::Down {
+                    serial, surface, ..
+                } = &event
+                {
+                    *last_input.borrow_mut() = Some((*serial, surface.clone()));
+                }
+                let event = pointer.borrow_mut().touch(event);
+                pointer_events.borrow_mut().extend(event);
+            }
         });
-        let mut keyboard_created = false;
-        self.window()
+        let mut keyboard_created = self.mode == Mode::Bar;
+        let mut pointer_created = false;
+        let mut touch_created = false;
+        let seat = self
+            .window
             .globals
             .instantiate_exact::<wl_seat::WlSeat>(1)
-            .unwrap()
-            .assign_mono(move |seat, event| {
-                use wayland_client::protocol::wl_seat::{Capability, Event as SeatEvent};
-                if let SeatEvent::Capabilities { capabilities } = event {
-                    if !keyboard_created && capabilities.contains(Capability::Keyboard) {
-                        keyboard_created = true;
-                        seat.get_keyboard().assign(common_filter.clone())
-                    }
+            .map_err(|e| window::WindowError::MissingGlobal("wl_seat", e))?;
+        // Pasting is a convenience, so a compositor without data devices only goes without it
+        if self.launcher.is_some() {
+            self.clipboard = self
+                .window
+                .globals
+                .instantiate_exact::<wl_data_device_manager::WlDataDeviceManager>(1)
+                .map(|manager| Clipboard::new(&manager, &seat))
+                .map_err(|e| eprintln!("Pasting is unavailable: {:?}", e))
+                .ok();
+        }
+        let shm = self
+            .window
+            .globals
+            .instantiate_exact::<wl_shm::WlShm>(1)
+            .map_err(|e| window::WindowError::MissingGlobal("wl_shm", e))?;
+        *self.cursor.borrow_mut() = Some(Cursor::new(&self.window.compositor, &shm));
+        self.seat = Some(seat.detach());
+        seat.assign_mono(move |seat, event| {
+            use wayland_client::protocol::wl_seat::{Capability, Event as SeatEvent};
+            if let SeatEvent::Capabilities { capabilities } = event {
+                if !keyboard_created && capabilities.contains(Capability::Keyboard) {
+                    keyboard_created = true;
+                    seat.get_keyboard().assign(common_filter.clone())
                 }
-            });
-        self.test_draw();
-        /*
-        std::thread::spawn(|| {
-            std::thread::sleep_ms(6000);
-            *STATUS.lock().unwrap() = Status::Closing;
+                if !pointer_created && capabilities.contains(Capability::Pointer) {
+                    pointer_created = true;
+                    seat.get_pointer().assign(common_filter.clone())
+                }
+                if !touch_created && capabilities.contains(Capability::Touch) {
+                    touch_created = true;
+                    seat.get_touch().assign(common_filter.clone())
+                }
+            }
         });
-        */
         loop {
-            self.window()
-                .events
-                .borrow_mut()
-                .dispatch(|_, _| {})
-                .unwrap();
-            eprintln!("Event dispatch!");
-            if *STATUS.lock().unwrap() == Status::Closing {
-                println!(
-                    "{}",
-                    self.window()
-                        .events
-                        .borrow_mut()
-                        .sync_roundtrip(|_, _| {})
-                        .unwrap()
-                );
-                return;
-            }
-        }
-    }
-
-    fn test_draw(&mut self) {
-        let (image_num, acquire_future) =
-            swapchain::acquire_next_image(self.vk.swapchain.clone(), None).unwrap();
-        eprintln!("Got swapchain image");
-        let clear = vec![[1.0, 1.0, 0.0, 1.0].into()];
-
-        let vertex_buffer = {
-            CpuAccessibleBuffer::from_iter(
-                self.vk.device.clone(),
-                BufferUsage::all(),
-                [
-                    shader::Vertex {
-                        position: [-0.5, -0.25],
-                        color: [0.2, 0.2, 0.2],
-                    },
-                    shader::Vertex {
-                        position: [0.0, 0.5],
-                        color: [0.2, 0.2, 0.2],
-                    },
-                    shader::Vertex {
-                        position: [0.25, -0.1],
-                        color: [0.2, 0.2, 0.2],
-                    },
-                ]
-                .iter()
-                .cloned(),
-            )
-            .unwrap()
+            self.sync_screens()?;
+            let (caret_shown, caret_changes) = self.caret(Instant::now());
+            if caret_shown != self.caret_shown {
+                self.caret_shown = caret_shown;
+                self.dirty = true;
+            }
+            self.redraw()?;
+            // A swapchain that couldn't be recreated yet is retried without waiting for events
+            // when nothing else holds it up, and a held key or the blinking caret wake the loop
+            // up when it is time
+            let deadline = if self.screens.iter().any(Screen::can_recreate) {
+                Some(Instant::now())
+            } else {
+                let deadline = self.repeat.borrow().deadline();
+                match (deadline, caret_changes) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                }
+            };
+            let requests = self
+                .event_loop
+                .wait(&self.window, deadline)
+                .map_err(window::WindowError::Connection)?;
+            for request in requests {
+                self.handle_request(request);
+            }
+            let repeated = self.repeat.borrow_mut().due(Instant::now());
+            self.keys.borrow_mut().extend(repeated);
+            self.handle_keys();
+            self.handle_pointer();
+            if *self.state.state() == State::Closing {
+                // Destroys the surfaces, which the compositor is told about before exiting
+                self.screens.clear();
+                if let Err(e) = self.window.display.flush() {
+                    eprintln!("Could not say goodbye to the compositor: {}", e);
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) {
+        match request {
+            Request::Open => self.transition(Event::Open),
+            Request::Close => self.transition(Event::Close),
+            Request::Toggle if self.state.state().is_expanded() => self.transition(Event::Close),
+            Request::Toggle => self.transition(Event::Open),
+            Request::Reload => self.reload(),
+            Request::Quit => self.transition(Event::Quit),
+        }
+    }
+
+    // Reads the installed applications and the history again, such as after installing
+    // something.
+    fn reload(&mut self) {
+        self.history = History::load();
+        let index = match &mut self.launcher {
+            Some(index) => index,
+            None => return,
         };
-        let vs = shader::open(
-            Path::new("src/draw/shader/test_vert.spv"),
-            self.vk.device.clone(),
-        );
-        let fs = shader::open(
-            Path::new("src/draw/shader/test_frag.spv"),
-            self.vk.device.clone(),
-        );
-        eprintln!("Loaded shaders");
-
-        let (framebuffers, render_pass) = self.vk.new_framebuffers();
-        eprintln!("Generated framebuffer and renderpass");
-        let pipeline = Arc::new(
-            GraphicsPipeline::start()
-                .vertex_input_single_buffer()
-                .vertex_shader(shader::get_entry_vertex(&vs), ())
-                .triangle_list()
-                .viewports_dynamic_scissors_irrelevant(1)
-                .fragment_shader(shader::get_entry_fragment(&fs), ())
-                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-                .build(self.vk.device.clone())
-                .unwrap(),
-        );
-        eprintln!("Generated pipeline");
+        *index = launcher::Index::load();
+        // The results refer to the old index, so they are ranked from scratch
+        self.search = launcher::Search::default();
+        let query = match self.state.state() {
+            State::LauncherOpen { query, .. } => query.as_str(),
+            _ => "",
+        };
+        self.search.update(index, &self.history, query);
+        self.transition(Event::Select(0));
+        self.dirty = true;
+    }
+
+    // Runs the bound actions of the keys pressed since the last call, and types the rest into
+    // the query.
+    fn handle_keys(&mut self) {
+        let keys = std::mem::take(&mut *self.keys.borrow_mut());
+        for key in keys {
+            let mode = self.binding_mode();
+            match self.bindings.feed(mode, &key) {
+                Feed::Action(action) if key.repeated && !action.repeats() => {}
+                Feed::Action(action) => self.perform(action),
+                Feed::Pending => {}
+                Feed::Unbound => {
+                    if let Some(text) = &key.text {
+                        self.editor.insert(text);
+                        self.edited();
+                    }
+                }
+            }
+        }
+    }
+
+    // Hovering a result selects it, clicking launches it, and the wheel over the results moves
+    // the selection.
+    fn handle_pointer(&mut self) {
+        let events = std::mem::take(&mut *self.pointer_events.borrow_mut());
+        // The cursor is drawn at the scale of the screen it is over
+        if let Some(screen) = self.pointer_screen() {
+            let scale = screen.surface().scale().ceil() as u32;
+            if let Some(cursor) = self.cursor.borrow_mut().as_mut() {
+                cursor.set_scale(scale);
+            }
+        }
+        for event in events {
+            match event {
+                PointerEvent::Motion(at) => {
+                    let hit = self.hit(at);
+                    if let Some(cursor) = self.cursor.borrow_mut().as_mut() {
+                        cursor.set_shape(if hit.is_some() { "hand2" } else { "left_ptr" });
+                    }
+                    if let Some(result) = hit.filter(|&tag| tag != OPEN_LAUNCHER) {
+                        self.transition(Event::Select(result));
+                    }
+                }
+                PointerEvent::Leave => {}
+                PointerEvent::Click(at) => match self.hit(at) {
+                    Some(OPEN_LAUNCHER) => self.transition(Event::Open),
+                    Some(result) => {
+                        self.transition(Event::Select(result));
+                        self.perform(Action::Launch);
+                    }
+                    None => {}
+                },
+                PointerEvent::Scroll { at, steps } => {
+                    match self.hit(at) {
+                        Some(OPEN_LAUNCHER) | None => continue,
+                        Some(_) => {}
+                    }
+                    let action = if steps > 0 {
+                        Action::SelectNext
+                    } else {
+                        Action::SelectPrevious
+                    };
+                    for _ in 0..steps.abs() {
+                        self.perform(action);
+                    }
+                }
+            }
+        }
+    }
+
+    // The tag under `at`, in surface coordinates. Only `run` and `launch-only` mode have anything
+    // to hit, and they are only ever on one screen.
+    fn hit(&self, at: [f32; 2]) -> Option<usize> {
+        let [width, height] = self.screens.first()?.surface().size.get();
+        let bounds = ui::Rect::new(0.0, 0.0, width as f32, height as f32);
+        self.view().hit(bounds, &self.text, at)
+    }
+
+    fn pointer_screen(&self) -> Option<&Screen> {
+        let focus = self.pointer_focus.borrow();
+        let focus = focus.as_ref()?;
+        self.screens
+            .iter()
+            .find(|screen| screen.surface().surface.as_ref().equals(focus.as_ref()))
+    }
+
+    fn binding_mode(&self) -> BindingMode {
+        match self.state.state() {
+            State::LauncherOpen { .. } => BindingMode::Launcher,
+            State::CommandMode { .. } => BindingMode::Command,
+            _ => BindingMode::Bar,
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        let editor = &mut self.editor;
+        match action {
+            Action::Close => return self.transition(Event::Close),
+            Action::Launch => {
+                return match self.state.state() {
+                    State::CommandMode { .. } => self.run_command(),
+                    _ => self.launch_selected(),
+                }
+            }
+            Action::SelectNext => {
+                let shown = self.search.results().len().min(MAX_RESULTS);
+                return self.transition(Event::SelectNext { shown });
+            }
+            Action::SelectPrevious => {
+                let shown = self.search.results().len().min(MAX_RESULTS);
+                return self.transition(Event::SelectPrevious { shown });
+            }
+            Action::CursorLeft => editor.move_left(false),
+            Action::CursorRight => editor.move_right(false),
+            Action::WordLeft => editor.word_left(false),
+            Action::WordRight => editor.word_right(false),
+            Action::LineStart => editor.line_start(false),
+            Action::LineEnd => editor.line_end(false),
+            Action::SelectLeft => editor.move_left(true),
+            Action::SelectRight => editor.move_right(true),
+            Action::SelectWordLeft => editor.word_left(true),
+            Action::SelectWordRight => editor.word_right(true),
+            Action::SelectToStart => editor.line_start(true),
+            Action::SelectToEnd => editor.line_end(true),
+            Action::SelectAll => editor.select_all(),
+            Action::DeleteBackward => editor.delete_backward(),
+            Action::DeleteForward => editor.delete_forward(),
+            Action::DeleteWord => editor.delete_word_backward(),
+            Action::KillToStart => editor.kill_to_start(),
+            Action::KillToEnd => editor.kill_to_end(),
+            Action::ClearLine => editor.clear(),
+            Action::Yank => editor.yank(),
+            Action::Undo => editor.undo(),
+            Action::Redo => editor.redo(),
+            Action::Paste => self.paste(),
+            Action::CommandMode => return self.transition(Event::CommandMode),
+            Action::LauncherMode => return self.transition(Event::LauncherMode),
+        }
+        self.edited();
+    }
+
+    fn paste(&mut self) {
+        let clipboard = match &self.clipboard {
+            Some(clipboard) => clipboard,
+            None => return,
+        };
+        match clipboard.read(&self.window.display) {
+            Ok(Some(text)) => self.editor.paste(&text),
+            Ok(None) => {}
+            Err(e) => eprintln!("Could not paste: {}", e),
+        }
+    }
+
+    fn edited(&mut self) {
+        self.caret_since = Instant::now();
+        self.dirty = true;
+        let text = self.editor.text().to_owned();
+        self.transition(Event::Edited(text));
+    }
+
+    // Moves the state along, and catches everything else up with it. Opening and closing the
+    // launcher, and switching between searching and commands, start over with an empty line.
+    fn transition(&mut self, event: Event) {
+        let before = self.state.state().clone();
+        if !self.state.handle(event) {
+            return;
+        }
+        self.dirty = true;
+        let state = self.state.state();
+        let command_mode = |state: &State| matches!(state, State::CommandMode { .. });
+        if state.is_expanded() != before.is_expanded()
+            || command_mode(state) != command_mode(&before)
+        {
+            self.bindings.reset();
+            self.editor.clear();
+            self.caret_since = Instant::now();
+        }
+        // Only the launcher in `run` mode opens below the bar, in other modes the surfaces stay
+        // as they are
+        if state.is_expanded() != before.is_expanded() && self.mode == Mode::Full {
+            let placement = self.placement();
+            for screen in &self.screens {
+                screen.surface().place(&placement);
+            }
+        }
+        if state.query() != before.query() {
+            self.error = None;
+        }
+        // Results are only ranked while searching
+        if let (Some(index), State::LauncherOpen { query, .. }) = (&self.launcher, state) {
+            if query != self.search.query() {
+                self.search.update(index, &self.history, query);
+            }
+        }
+    }
+
+    // Whether the caret is shown at `now`, and when that changes next. It blinks while the
+    // keyboard focus is here, is shown right away after every edit so that it can be followed
+    // while typing, and stops blinking after a while to save redrawing.
+    fn caret(&self, now: Instant) -> (bool, Option<Instant>) {
+        if !self.state.state().is_expanded() || !self.focused.get() {
+            return (false, None);
+        }
+        let elapsed = now.saturating_duration_since(self.caret_since);
+        if elapsed >= CARET_BLINK_TIMEOUT {
+            return (true, None);
+        }
+        let phase = (elapsed.as_millis() / CARET_BLINK.as_millis()) as u32;
+        (
+            phase % 2 == 0,
+            Some(self.caret_since + CARET_BLINK * (phase + 1)),
+        )
+    }
+
+    // Starts the selected result and remembers it. In launcher mode that is all there is to do,
+    // so the window closes afterwards.
+    fn launch_selected(&mut self) {
+        let selection = self.state.state().selection();
+        let (index, ranked) = match (&self.launcher, self.search.results().get(selection)) {
+            (Some(index), Some(ranked)) => (index, ranked),
+            _ => return,
+        };
+        let item = index.item(ranked.item);
+        let token = self.activation_token();
+        if let Err(e) = self.executor.launch(&item, token.as_deref()) {
+            eprintln!("Could not launch {}: {}", item.name(), e);
+            self.error = Some(format!("{}: {}", item.name(), e));
+            self.dirty = true;
+            return;
+        }
+        if let Err(e) = self.history.record(&item.id(), self.search.query()) {
+            eprintln!("Could not save history: {}", e);
+        }
+        self.launched();
+    }
+
+    fn run_command(&mut self) {
+        let token = self.activation_token();
+        if let Err(e) = self.executor.run(self.editor.text(), token.as_deref()) {
+            eprintln!("Could not run {:?}: {}", self.editor.text(), e);
+            self.error = Some(e.to_string());
+            self.dirty = true;
+            return;
+        }
+        self.launched();
+    }
+
+    // A token that lets the application about to be launched take the focus, when the compositor
+    // hands them out.
+    fn activation_token(&self) -> Option<String> {
+        let seat = self.seat.as_ref()?;
+        let (serial, surface) = self.last_input.borrow().clone()?;
+        self.window
+            .activation_token(seat, serial, &surface)
+            .unwrap_or_else(|e| {
+                eprintln!("Could not get an activation token: {}", e);
+                None
+            })
+    }
+
+    fn launched(&mut self) {
+        self.transition(Event::Launched);
+    }
+
+    fn placement(&self) -> window::Placement {
+        let bar = |height| window::Placement::bar(&self.bar_config, &self.window_config, height);
+        match self.mode {
+            Mode::Launcher => window::Placement::window(&self.window_config),
+            Mode::Bar => bar(self.bar_config.height),
+            Mode::Full if self.state.state().is_expanded() => window::Placement {
+                keyboard: true,
+                ..bar(self.window_config.height)
+            },
+            Mode::Full => bar(self.bar_config.height),
+        }
+    }
+
+    fn view(&self) -> Widget {
+        if !self.state.state().is_expanded() {
+            return self.bar();
+        }
+        let style = self.text_style;
+        let mut panel = self.colors.foreground.0;
+        panel[3] = 0.15;
+
+        let title = match (&self.launcher, self.state.state()) {
+            (Some(_), State::CommandMode { .. }) => self.input_line("$ ", "Type a command"),
+            (Some(_), _) => self.input_line("", "Type to search"),
+            (None, _) => Widget::text("infolauncher", style),
+        };
+        let header = Widget::stack(vec![
+            Widget::rect(panel, 6.0),
+            Widget::row(vec![
+                title,
+                Widget::row(Vec::new()).width(Length::Fill(1)),
+                Widget::text(self.mode_name(), style),
+            ])
+            .width(Length::Fill(1))
+            .spacing(8.0)
+            .padding(Edges::symmetric(4.0, 8.0)),
+        ])
+        .width(Length::Fill(1))
+        .align(Align::Center, Align::Start);
+
+        let mut rows = vec![header];
+        if let Some(error) = &self.error {
+            let style = text::TextStyle {
+                color: self.colors.highlight.0,
+                ..style
+            };
+            rows.push(Widget::text(error.as_str(), style).padding(Edges::symmetric(0.0, 8.0)));
+        }
+        if let (Some(index), State::LauncherOpen { selection, .. }) =
+            (&self.launcher, self.state.state())
+        {
+            let results = self.search.results().iter().take(MAX_RESULTS);
+            rows.extend(results.enumerate().map(|(i, ranked)| {
+                let mut layers = vec![self.result_row(index, ranked)];
+                if i == *selection {
+                    layers.insert(0, Widget::rect(panel, 6.0));
+                }
+                Widget::stack(layers).width(Length::Fill(1)).tag(i)
+            }));
+        }
+        Widget::column(rows)
+            .spacing(6.0)
+            .width(Length::Fill(1))
+            .height(Length::Fill(1))
+            .padding(Edges::all(10.0))
+    }
+
+    // The edited text with the selection behind it and the caret at the cursor, or the faded
+    // placeholder while there is no text.
+    fn input_line(&self, prefix: &str, placeholder: &str) -> Widget {
+        let style = self.text_style;
+        let caret = if self.caret_shown {
+            Widget::rect(style.color, 0.0)
+        } else {
+            Widget::row(Vec::new())
+        }
+        .width(Length::Fixed(2.0));
+
+        let text = self.editor.text();
+        if text.is_empty() {
+            let mut faded = style;
+            faded.color[3] *= 0.5;
+            return Widget::row(vec![
+                Widget::text(prefix, style),
+                caret,
+                Widget::text(placeholder, faded),
+            ]);
+        }
+        let cursor = self.editor.cursor();
+        let selection = self.editor.selection().unwrap_or(cursor..cursor);
+        let mut selected = self.colors.highlight.0;
+        selected[3] = 0.35;
+
+        let mut pieces = vec![Widget::text(
+            format!("{}{}", prefix, &text[..selection.start]),
+            style,
+        )];
+        if cursor == selection.start {
+            pieces.push(caret.clone());
+        }
+        if !selection.is_empty() {
+            pieces.push(Widget::stack(vec![
+                Widget::rect(selected, 2.0),
+                Widget::text(&text[selection.clone()], style),
+            ]));
+            if cursor == selection.end {
+                pieces.push(caret);
+            }
+        }
+        pieces.push(Widget::text(&text[selection.end..], style));
+        Widget::row(pieces)
+    }
+
+    // The collapsed bar, which opens the launcher when clicked in `run` mode.
+    fn bar(&self) -> Widget {
+        let style = self.text_style;
+        let bar = Widget::stack(vec![Widget::row(vec![
+            Widget::text("infolauncher", style),
+            Widget::row(Vec::new()).width(Length::Fill(1)),
+            Widget::text(self.mode_name(), style),
+        ])
+        .width(Length::Fill(1))
+        .spacing(8.0)
+        .padding(Edges::symmetric(0.0, 8.0))
+        .align(Align::Start, Align::Center)])
+        .width(Length::Fill(1))
+        .height(Length::Fill(1));
+        match self.mode {
+            Mode::Full => bar.tag(OPEN_LAUNCHER),
+            _ => bar,
+        }
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.mode {
+            Mode::Full => "run",
+            Mode::Launcher => "launch-only",
+            Mode::Bar => "bar-only",
+        }
+    }
+
+    fn result_row(&self, index: &launcher::Index, ranked: &launcher::Ranked) -> Widget {
+        let style = self.text_style;
+        let item = index.item(ranked.item);
+        let icon_size = (style.size * 1.5).round();
+        let icon = match item.icon().and_then(|icon| index.icon_path(icon)) {
+            Some(path) => Widget::icon(path, icon_size),
+            None => Widget::row(Vec::new()).width(Length::Fixed(icon_size)),
+        };
+        Widget::row(vec![
+            icon,
+            self.highlighted(item.name(), &ranked.positions)
+                .align(Align::Start, Align::Center),
+        ])
+        .height(Length::Fixed(icon_size))
+        .spacing(8.0)
+        .padding(Edges::symmetric(0.0, 8.0))
+    }
+
+    // Splits `text` into runs of matched and unmatched characters, drawing the matched ones in
+    // the highlight color.
+    fn highlighted(&self, text: &str, positions: &[usize]) -> Widget {
+        let normal = self.text_style;
+        let highlight = text::TextStyle {
+            color: self.colors.highlight.0,
+            ..normal
+        };
+        let mut runs: Vec<(bool, String)> = Vec::new();
+        for (offset, c) in text.char_indices() {
+            let matched = positions.binary_search(&offset).is_ok();
+            match runs.last_mut() {
+                Some((last, run)) if *last == matched => run.push(c),
+                _ => runs.push((matched, c.to_string())),
+            }
+        }
+        Widget::row(
+            runs.into_iter()
+                .map(|(matched, run)| Widget::text(run, if matched { highlight } else { normal }))
+                .collect(),
+        )
+    }
+
+    // Keeps a bar on every selected output as they are plugged in and out, or the single
+    // launcher surface open.
+    fn sync_screens(&mut self) -> Result<(), DrawError> {
+        if self.mode != Mode::Bar {
+            if self.screens.is_empty() {
+                let output = self.selected_outputs().into_iter().next();
+                if output.is_none() && !self.window_config.outputs.is_empty() {
+                    eprintln!("None of the configured outputs are connected, using any output");
+                }
+                self.open_screen(output)?;
+                self.transition(Event::Shown);
+            } else if self.screens[0].surface().closed.get() {
+                self.transition(Event::Hidden);
+            }
+            return Ok(());
+        }
+
+        let selected = self.selected_outputs();
+        let connected = self.window.outputs.borrow();
+        self.closed_outputs
+            .retain(|&id| connected.iter().any(|output| output.id == id));
+        drop(connected);
+        let closed_outputs = &mut self.closed_outputs;
+        self.screens.retain(|screen| {
+            let closed = screen.surface().closed.get();
+            if closed {
+                closed_outputs.extend(screen.output);
+            }
+            !closed && selected.iter().any(|(id, _)| screen.output == Some(*id))
+        });
+        for (id, output) in selected {
+            let open = self.screens.iter().any(|screen| screen.output == Some(id));
+            if !open && !self.closed_outputs.contains(&id) {
+                self.open_screen(Some((id, output)))?;
+            }
+        }
+        self.transition(if self.screens.is_empty() {
+            Event::Hidden
+        } else {
+            Event::Shown
+        });
+        Ok(())
+    }
+
+    // The connected outputs named in the configuration, in its order, or all of them if it names
+    // none.
+    fn selected_outputs(&self) -> Vec<(u32, WlOutput)> {
+        let outputs = self.window.outputs.borrow();
+        let ready = || outputs.iter().filter(|output| output.info.borrow().done);
+        let selectors = &self.window_config.outputs;
+        let mut selected: Vec<(u32, WlOutput)> = Vec::new();
+        if selectors.is_empty() {
+            selected.extend(ready().map(|output| (output.id, output.output.clone())));
+        }
+        for selector in selectors {
+            for output in ready().filter(|output| output.matches(selector)) {
+                if selected.iter().all(|(id, _)| *id != output.id) {
+                    selected.push((output.id, output.output.clone()));
+                }
+            }
+        }
+        selected
+    }
+
+    fn open_screen(&mut self, output: Option<(u32, WlOutput)>) -> Result<(), DrawError> {
+        let surface = self
+            .window
+            .create_surface(&self.placement(), output.as_ref().map(|(_, output)| output))?;
+        let target = self.vk.target(&self.window.display, &surface)?;
+        self.screens.push(Screen {
+            output: output.map(|(id, _)| id),
+            target,
+            previous_frame_end: None,
+            recreate_swapchain: false,
+            unsupported_size: None,
+            dirty: true,
+            surface,
+        });
+        Ok(())
+    }
+
+    /// Draws a new frame on every screen where something changed and the compositor is ready for
+    /// one.
+    fn redraw(&mut self) -> Result<(), DrawError> {
+        let dirty = std::mem::replace(&mut self.dirty, false);
+        let mut view = None;
+        for index in 0..self.screens.len() {
+            let screen = &mut self.screens[index];
+            screen.dirty |= dirty;
+            if screen.surface().buffer_size() != screen.target.swapchain.dimensions() {
+                screen.recreate_swapchain = true;
+                screen.dirty = true;
+            }
+            // Nothing can be drawn before the compositor has given a stretched side its size
+            let [width, height] = screen.surface().size.get();
+            if !screen.dirty || screen.surface().frame_pending.get() || width == 0 || height == 0 {
+                continue;
+            }
+            let view = view.get_or_insert_with(|| self.view());
+            self.draw_frame(index, view)?;
+        }
+        Ok(())
+    }
+
+    /// Lays out `root` over the whole screen and draws it. Rectangles are drawn below all text and
+    /// icons, which is all backgrounds and highlights need. Leaves the screen dirty if no frame
+    /// could be presented.
+    ///
+    /// Layout happens in surface coordinates, and everything is scaled to pixels when drawn so
+    /// that text and icons are rasterized at the size they are shown at.
+    fn draw_frame(&mut self, index: usize, root: &Widget) -> Result<(), DrawError> {
+        let screen = &mut self.screens[index];
+        if let Some(previous_frame_end) = &mut screen.previous_frame_end {
+            previous_frame_end.cleanup_finished();
+        }
+        if screen.recreate_swapchain {
+            let size = screen.surface().buffer_size();
+            match screen.target.recreate_swapchain(size) {
+                Ok(()) => {
+                    screen.recreate_swapchain = false;
+                    screen.unsupported_size = None;
+                }
+                // The surface is being resized, try again once it settles
+                Err(vulkan::VkSessionError::Swapchain(
+                    SwapchainCreationError::UnsupportedDimensions,
+                )) => {
+                    screen.unsupported_size = Some(size);
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        self.text.begin_frame();
+        self.rects.begin_frame();
+        let [width, height] = screen.surface().size.get();
+        let scale = screen.surface().scale() as f32;
+        let bounds = ui::Rect::new(0.0, 0.0, width as f32, height as f32);
+        for primitive in root.layout(bounds, &self.text) {
+            match primitive {
+                Primitive::Rect {
+                    rect,
+                    color,
+                    radius,
+                } => self
+                    .rects
+                    .draw_rect(rect.scaled(scale), color, radius * scale),
+                Primitive::Text { pos, text, style } => {
+                    let style = text::TextStyle {
+                        size: style.size * scale,
+                        ..style
+                    };
+                    self.text
+                        .draw_text([pos[0] * scale, pos[1] * scale], &text, &style)?
+                }
+                Primitive::Icon { rect, path } => {
+                    let rect = rect.scaled(scale);
+                    self.text
+                        .draw_icon([rect.x, rect.y], [rect.width, rect.height], &path)?
+                }
+                Primitive::Area { .. } => {}
+            }
+        }
+
+        let (image_num, acquire_future) =
+            match swapchain::acquire_next_image(screen.target.swapchain.clone(), None) {
+                Ok(acquired) => acquired,
+                Err(AcquireError::OutOfDate) => {
+                    screen.recreate_swapchain = true;
+                    return Ok(());
+                }
+                Err(AcquireError::SurfaceLost) => {
+                    return Err(vulkan::VkSessionError::SurfaceLost.into())
+                }
+                Err(e) => return Err(frame_error(e)),
+            };
+        let clear = vec![self.colors.background.0.into()];
+        let shader_dir = self.shader_dir.as_deref();
 
         let cb = AutoCommandBufferBuilder::primary_one_time_submit(
             self.vk.device.clone(),
             self.vk.queue.family(),
         )
-        .unwrap()
-        .begin_render_pass(framebuffers[image_num].clone(), false, clear)
-        .unwrap()
-        .draw(
-            pipeline.clone(),
-            &self.vk.dynamic_state,
-            vertex_buffer.clone(),
-            (),
-            (),
-        )
-        .unwrap()
-        .end_render_pass()
-        .unwrap()
-        .build()
-        .unwrap();
-        eprintln!("Built command_buffer");
-
-        let _frame_end = acquire_future
+        .map_err(frame_error)?
+        .begin_render_pass(screen.target.framebuffers[image_num].clone(), false, clear)
+        .map_err(frame_error)?;
+        let cb = self
+            .rects
+            .render(&self.vk, &screen.target, cb, shader_dir)?;
+        let (cb, atlas_upload) = self.text.render(&self.vk, &screen.target, cb, shader_dir)?;
+        let cb = cb
+            .end_render_pass()
+            .map_err(frame_error)?
+            .build()
+            .map_err(frame_error)?;
+
+        let previous_frame_end = screen
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| Box::new(sync::now(self.vk.device.clone())));
+        let mut before_render: Box<dyn GpuFuture> =
+            Box::new(previous_frame_end.join(acquire_future));
+        if let Some(upload) = atlas_upload {
+            before_render = Box::new(before_render.join(upload));
+        }
+
+        screen.surface().apply_scale();
+        screen.surface().request_frame();
+        let frame_end = before_render
             .then_execute(self.vk.queue.clone(), cb)
-            .unwrap()
-            .then_swapchain_present(self.vk.queue.clone(), self.vk.swapchain.clone(), image_num);
-        eprintln!("Presented swapchain");
+            .map_err(frame_error)?
+            .then_swapchain_present(
+                self.vk.queue.clone(),
+                screen.target.swapchain.clone(),
+                image_num,
+            )
+            .then_signal_fence_and_flush();
+        match frame_end {
+            Ok(future) => {
+                screen.previous_frame_end = Some(Box::new(future));
+                screen.dirty = false;
+                return Ok(());
+            }
+            Err(FlushError::OutOfDate) => screen.recreate_swapchain = true,
+            Err(e) => return Err(frame_error(e)),
+        }
+        // Nothing was committed, so the frame callback will never fire
+        screen.surface().frame_pending.set(false);
+        screen.previous_frame_end = Some(Box::new(sync::now(self.vk.device.clone())));
+        Ok(())
     }
 }
 
+// Recording and submitting a frame only fails when the GPU is in trouble.
+fn frame_error<E: fmt::Display>(e: E) -> DrawError {
+    vulkan::VkSessionError::Draw(e.to_string()).into()
+}
+
 event_enum!(
-    Events | Keyboard => wl_keyboard::WlKeyboard
+    Events | Keyboard => wl_keyboard::WlKeyboard | Pointer => wl_pointer::WlPointer | Touch => wl_touch::WlTouch
 );
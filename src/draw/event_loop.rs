@@ -0,0 +1,318 @@
+use super::window::Window;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// What became ready, as told by epoll
+const WAYLAND: u64 = 0;
+const TIMER: u64 = 1;
+const SIGNALS: u64 = 2;
+const IPC: u64 = 3;
+// A client that doesn't send its command in this long is hung up on, so that it can't stall the
+// launcher
+const IPC_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Something asked of the launcher from outside, with a signal or over the IPC socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Open,
+    Close,
+    Toggle,
+    /// Reads the installed applications and the history again.
+    Reload,
+    Quit,
+}
+
+impl Request {
+    fn parse(command: &str) -> Option<Request> {
+        match command {
+            "open" => Some(Request::Open),
+            "close" => Some(Request::Close),
+            "toggle" => Some(Request::Toggle),
+            "reload" => Some(Request::Reload),
+            "quit" => Some(Request::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Waits for the Wayland connection together with a timer, signals and the IPC socket, so that
+/// something can happen between Wayland events.
+pub struct EventLoop {
+    epoll: RawFd,
+    timer: RawFd,
+    signals: RawFd,
+    /// The listening socket and its path, unless another instance has it.
+    ipc: Option<(UnixListener, PathBuf)>,
+    wayland: Option<RawFd>,
+}
+
+impl EventLoop {
+    /// Blocks the signals that are handled here, so it has to be made before any other thread is
+    /// started, or the signals may go to one of those instead.
+    pub fn new() -> io::Result<EventLoop> {
+        let signals = unsafe {
+            let mut mask = std::mem::zeroed();
+            libc::sigemptyset(&mut mask);
+            for &signal in &[libc::SIGTERM, libc::SIGINT, libc::SIGUSR1, libc::SIGHUP] {
+                libc::sigaddset(&mut mask, signal);
+            }
+            match libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut()) {
+                0 => {}
+                e => return Err(io::Error::from_raw_os_error(e)),
+            }
+            check(libc::signalfd(
+                -1,
+                &mask,
+                libc::SFD_CLOEXEC | libc::SFD_NONBLOCK,
+            ))?
+        };
+        let timer = check(unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+            )
+        })?;
+        let event_loop = EventLoop {
+            epoll: check(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?,
+            timer,
+            signals,
+            ipc: listen(),
+            wayland: None,
+        };
+        event_loop.watch(timer, TIMER)?;
+        event_loop.watch(signals, SIGNALS)?;
+        if let Some((listener, _)) = &event_loop.ipc {
+            event_loop.watch(listener.as_raw_fd(), IPC)?;
+        }
+        Ok(event_loop)
+    }
+
+    /// Dispatches the events of `window`, waiting for them until `deadline`, or for as long as it
+    /// takes if there is none. Returns what was requested with signals and over the socket in
+    /// the meantime.
+    pub fn wait(&mut self, window: &Window, deadline: Option<Instant>) -> io::Result<Vec<Request>> {
+        let mut requests = Vec::new();
+        window.dispatch(|fd| {
+            if self.wayland != Some(fd) {
+                self.watch(fd, WAYLAND)?;
+                self.wayland = Some(fd);
+            }
+            self.set_timer(deadline)?;
+
+            let mut ready = [libc::epoll_event { events: 0, u64: 0 }; 4];
+            let count =
+                unsafe { libc::epoll_wait(self.epoll, ready.as_mut_ptr(), ready.len() as i32, -1) };
+            if count < 0 {
+                let e = io::Error::last_os_error();
+                return match e.kind() {
+                    io::ErrorKind::Interrupted => Ok(false),
+                    _ => Err(e),
+                };
+            }
+            let mut readable = false;
+            for event in &ready[..count as usize] {
+                match event.u64 {
+                    WAYLAND => readable = true,
+                    TIMER => self.clear_timer(),
+                    SIGNALS => requests.extend(self.read_signals()),
+                    IPC => requests.extend(self.accept()),
+                    _ => {}
+                }
+            }
+            Ok(readable)
+        })?;
+        Ok(requests)
+    }
+
+    fn watch(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        check(unsafe { libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_ADD, fd, &mut event) })?;
+        Ok(())
+    }
+
+    // Has the timer go off at `deadline`, or never if there is none.
+    fn set_timer(&self, deadline: Option<Instant>) -> io::Result<()> {
+        let value = timer_value(deadline, Instant::now());
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: value.as_secs() as libc::time_t,
+                tv_nsec: value.subsec_nanos() as libc::c_long,
+            },
+        };
+        check(unsafe { libc::timerfd_settime(self.timer, 0, &spec, std::ptr::null_mut()) })?;
+        Ok(())
+    }
+
+    fn clear_timer(&self) {
+        let mut expirations = 0u64;
+        unsafe {
+            libc::read(
+                self.timer,
+                &mut expirations as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+
+    fn read_signals(&self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        loop {
+            let mut info: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+            let size = std::mem::size_of::<libc::signalfd_siginfo>();
+            let read = unsafe {
+                libc::read(
+                    self.signals,
+                    &mut info as *mut libc::signalfd_siginfo as *mut libc::c_void,
+                    size,
+                )
+            };
+            if read != size as isize {
+                return requests;
+            }
+            requests.push(match info.ssi_signo as i32 {
+                libc::SIGUSR1 => Request::Toggle,
+                libc::SIGHUP => Request::Reload,
+                _ => Request::Quit,
+            });
+        }
+    }
+
+    // Takes one command from every client that connected.
+    fn accept(&self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        let listener = match &self.ipc {
+            Some((listener, _)) => listener,
+            None => return requests,
+        };
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => match serve(stream) {
+                    Ok(request) => requests.extend(request),
+                    Err(e) => eprintln!("IPC client failed: {}", e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return requests,
+                Err(e) => {
+                    eprintln!("Could not accept IPC client: {}", e);
+                    return requests;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for EventLoop {
+    fn drop(&mut self) {
+        if let Some((_, path)) = self.ipc.take() {
+            let _ = fs::remove_file(path);
+        }
+        unsafe {
+            libc::close(self.epoll);
+            libc::close(self.timer);
+            libc::close(self.signals);
+        }
+    }
+}
+
+// Listens on `$XDG_RUNTIME_DIR/infolauncher.sock`, unless another instance already does.
+fn listen() -> Option<(UnixListener, PathBuf)> {
+    let path = PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?).join("infolauncher.sock");
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => Ok(listener),
+        // Left behind by an instance that didn't exit cleanly
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(&path).is_err() => {
+            fs::remove_file(&path).and_then(|()| UnixListener::bind(&path))
+        }
+        Err(e) => Err(e),
+    };
+    match listener.and_then(|listener| listener.set_nonblocking(true).map(|()| listener)) {
+        Ok(listener) => Some((listener, path)),
+        Err(e) => {
+            eprintln!("Not listening on {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Reads a command line from `stream` and answers `ok`, or what was wrong with it.
+fn serve(stream: UnixStream) -> io::Result<Option<Request>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let command = line.trim();
+    let request = Request::parse(command);
+    let mut stream = &stream;
+    match request {
+        Some(_) => writeln!(stream, "ok")?,
+        None => writeln!(
+            stream,
+            "unknown command {:?}, expected open, close, toggle, reload or quit",
+            command
+        )?,
+    }
+    Ok(request)
+}
+
+// How long from `now` the timer goes off at `deadline`, where zero disarms it.
+fn timer_value(deadline: Option<Instant>, now: Instant) -> Duration {
+    match deadline {
+        // A deadline that passed already goes off right away instead
+        Some(at) => at
+            .saturating_duration_since(now)
+            .max(Duration::from_nanos(1)),
+        None => Duration::from_secs(0),
+    }
+}
+
+fn check(result: i32) -> io::Result<RawFd> {
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let commands = [
+            ("open", Some(Request::Open)),
+            ("close", Some(Request::Close)),
+            ("toggle", Some(Request::Toggle)),
+            ("reload", Some(Request::Reload)),
+            ("quit", Some(Request::Quit)),
+            ("", None),
+            ("Open", None),
+            ("open now", None),
+        ];
+        for (command, request) in commands.iter() {
+            assert_eq!(Request::parse(command), *request, "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn timer() {
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(timer_value(None, now), Duration::from_secs(0));
+        assert_eq!(timer_value(Some(now + second), now), second);
+        assert_eq!(timer_value(Some(now), now), Duration::from_nanos(1));
+        assert_eq!(
+            timer_value(Some(now), now + second),
+            Duration::from_nanos(1)
+        );
+    }
+}
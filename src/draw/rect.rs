@@ -0,0 +1,143 @@
+use super::shader::{self, ShaderError};
+use super::vulkan::{Target, VkSession};
+use super::{frame_error, DrawError};
+use crate::ui::Rect;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+
+#[derive(Default, Copy, Clone)]
+pub struct RectVertex {
+    pub position: [f32; 2],
+    /// Offset from the center of the rectangle, in pixels.
+    pub local: [f32; 2],
+    pub half_size: [f32; 2],
+    pub radius: f32,
+    pub color: [f32; 4],
+}
+
+vulkano::impl_vertex!(RectVertex, position, local, half_size, radius, color);
+
+struct Fill {
+    rect: Rect,
+    color: [f32; 4],
+    radius: f32,
+}
+
+/// Collects rectangles for the current frame and draws them in a single draw call. Rounded
+/// corners are computed per pixel in the fragment shader.
+#[derive(Default)]
+pub struct RectRenderer {
+    fills: Vec<Fill>,
+    pipeline: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+}
+
+impl RectRenderer {
+    pub fn begin_frame(&mut self) {
+        self.fills.clear();
+    }
+
+    pub fn draw_rect(&mut self, rect: Rect, color: [f32; 4], radius: f32) {
+        if rect.width > 0.0 && rect.height > 0.0 {
+            self.fills.push(Fill {
+                rect,
+                color,
+                radius: radius.min(rect.width / 2.0).min(rect.height / 2.0).max(0.0),
+            });
+        }
+    }
+
+    /// Records the rectangles queued this frame into `builder`, which must be inside the render
+    /// pass of `target`.
+    pub fn render(
+        &mut self,
+        vk: &VkSession,
+        target: &Target,
+        builder: AutoCommandBufferBuilder,
+        shader_dir: Option<&Path>,
+    ) -> Result<AutoCommandBufferBuilder, DrawError> {
+        if self.fills.is_empty() {
+            return Ok(builder);
+        }
+        let pipeline = match &self.pipeline {
+            Some(pipeline) => pipeline.clone(),
+            None => {
+                let pipeline = build_pipeline(vk, target.render_pass.clone(), shader_dir)?;
+                self.pipeline = Some(pipeline.clone());
+                pipeline
+            }
+        };
+
+        let [screen_width, screen_height] = target.swapchain.dimensions();
+        let ndc = |x: f32, y: f32| {
+            [
+                x / screen_width as f32 * 2.0 - 1.0,
+                y / screen_height as f32 * 2.0 - 1.0,
+            ]
+        };
+
+        let mut vertices = Vec::with_capacity(self.fills.len() * 6);
+        for fill in &self.fills {
+            let Rect {
+                x,
+                y,
+                width,
+                height,
+            } = fill.rect;
+            let half_size = [width / 2.0, height / 2.0];
+            let corner = |dx: f32, dy: f32| RectVertex {
+                position: ndc(x + half_size[0] + dx, y + half_size[1] + dy),
+                local: [dx, dy],
+                half_size,
+                radius: fill.radius,
+                color: fill.color,
+            };
+            let [w, h] = half_size;
+            vertices.extend_from_slice(&[
+                corner(-w, -h),
+                corner(w, -h),
+                corner(-w, h),
+                corner(-w, h),
+                corner(w, -h),
+                corner(w, h),
+            ]);
+        }
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            vk.device.clone(),
+            BufferUsage::vertex_buffer(),
+            vertices.into_iter(),
+        )
+        .map_err(frame_error)?;
+        builder
+            .draw(pipeline, &target.dynamic_state, vec![vertex_buffer], (), ())
+            .map_err(frame_error)
+    }
+}
+
+fn build_pipeline(
+    vk: &VkSession,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    shader_dir: Option<&Path>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, ShaderError> {
+    let vs = shader::load(&shader::RECT_VERT, shader_dir, vk.device.clone())?;
+    let fs = shader::load(&shader::RECT_FRAG, shader_dir, vk.device.clone())?;
+    let subpass = Subpass::from(render_pass, 0)
+        .ok_or_else(|| ShaderError::Pipeline(String::from("the render pass has no subpass")))?;
+
+    Ok(Arc::new(
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<RectVertex>()
+            .vertex_shader(vs.vertex_entry::<RectVertex>()?, ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.fragment_entry()?, ())
+            .blend_alpha_blending()
+            .render_pass(subpass)
+            .build(vk.device.clone())
+            .map_err(|e| ShaderError::Pipeline(e.to_string()))?,
+    ))
+}
@@ -1,280 +1,142 @@
-use std::borrow::Cow;
-use std::ffi::CStr;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use vulkano::descriptor::descriptor::{DescriptorDesc, ShaderStages};
-use vulkano::descriptor::pipeline_layout::{PipelineLayoutDesc, PipelineLayoutDescPcRange};
 use vulkano::device::Device;
-use vulkano::format::Format;
-use vulkano::pipeline::shader::{
-    GraphicsEntryPoint, GraphicsShaderType, ShaderInterfaceDef, ShaderInterfaceDefEntry,
-    ShaderModule,
-};
+use vulkano::pipeline::shader::{GraphicsEntryPoint, GraphicsShaderType, ShaderModule};
+use vulkano::pipeline::vertex;
+use vulkano::OomError;
 
-#[derive(Default, Copy, Clone)]
-pub struct Vertex {
-    pub position: [f32; 2],
-    pub color: [f32; 3],
-}
-
-vulkano::impl_vertex!(Vertex, position, color);
+mod reflect;
+pub use reflect::{Interface, Layout, ReflectError, Stage};
 
-pub fn open(path: &Path, device: Arc<Device>) -> Arc<ShaderModule> {
-    let mut f = File::open(path).expect(&format!("Shader not found: {:#?}", path));
-    let mut buf = Vec::with_capacity(50);
-    f.read_to_end(&mut buf).unwrap();
-
-    unsafe { ShaderModule::new(device, &buf) }.unwrap()
+/// A SPIR-V module compiled by `build.rs` and embedded in the binary.
+pub struct Source {
+    pub name: &'static str,
+    spirv: &'static [u8],
 }
 
-pub struct VertInput;
-
-#[derive(Debug, Copy, Clone)]
-pub struct VertInputIter(u16);
-
-unsafe impl ShaderInterfaceDef for VertInput {
-    type Iter = VertInputIter;
-
-    fn elements(&self) -> VertInputIter {
-        VertInputIter(0)
-    }
-}
-
-impl Iterator for VertInputIter {
-    type Item = ShaderInterfaceDefEntry;
-
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        // There are things to consider when giving out entries:
-        // * There must be only one entry per one location, you can't have
-        //   `color' and `position' entries both at 0..1 locations.  They also
-        //   should not overlap.
-        // * Format of each element must be no larger than 128 bits.
-        if self.0 == 0 {
-            self.0 += 1;
-            return Some(ShaderInterfaceDefEntry {
-                location: 1..2,
-                format: Format::R32G32B32Sfloat,
-                name: Some(Cow::Borrowed("color")),
-            });
+macro_rules! embed {
+    ($name:literal) => {
+        Source {
+            name: $name,
+            spirv: include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv")),
         }
-        if self.0 == 1 {
-            self.0 += 1;
-            return Some(ShaderInterfaceDefEntry {
-                location: 0..1,
-                format: Format::R32G32Sfloat,
-                name: Some(Cow::Borrowed("position")),
-            });
-        }
-        None
-    }
-
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        // We must return exact number of entries left in iterator.
-        let len = (2 - self.0) as usize;
-        (len, Some(len))
-    }
+    };
 }
 
-impl ExactSizeIterator for VertInputIter {}
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct VertOutput;
-
-unsafe impl ShaderInterfaceDef for VertOutput {
-    type Iter = VertOutputIter;
-
-    fn elements(&self) -> VertOutputIter {
-        VertOutputIter(0)
-    }
+pub const RECT_VERT: Source = embed!("rect_vert");
+pub const RECT_FRAG: Source = embed!("rect_frag");
+pub const TEXT_VERT: Source = embed!("text_vert");
+pub const TEXT_FRAG: Source = embed!("text_frag");
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, io::Error),
+    InvalidSpirv(String),
+    Creation(String, OomError),
+    Reflect(String, ReflectError),
+    /// The shaders don't make a pipeline together with the render pass.
+    Pipeline(String),
 }
 
-// This structure will tell Vulkan how output entries (those passed to next
-// stage) of our vertex shader look like.
-#[derive(Debug, Copy, Clone)]
-pub struct VertOutputIter(u16);
-
-impl Iterator for VertOutputIter {
-    type Item = ShaderInterfaceDefEntry;
-
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.0 == 0 {
-            self.0 += 1;
-            return Some(ShaderInterfaceDefEntry {
-                location: 0..1,
-                format: Format::R32G32B32Sfloat,
-                name: Some(Cow::Borrowed("v_color")),
-            });
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ShaderError::InvalidSpirv(origin) => write!(f, "{} is not a SPIR-V module", origin),
+            ShaderError::Creation(origin, e) => {
+                write!(f, "could not create shader from {}: {}", origin, e)
+            }
+            ShaderError::Reflect(origin, e) => write!(f, "{}: {}", origin, e),
+            ShaderError::Pipeline(e) => write!(f, "could not build a pipeline: {}", e),
         }
-        None
-    }
-
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = (1 - self.0) as usize;
-        (len, Some(len))
-    }
-}
-
-impl ExactSizeIterator for VertOutputIter {}
-
-// This structure describes layout of this stage.
-#[derive(Debug, Copy, Clone)]
-pub struct VertLayout(ShaderStages);
-unsafe impl PipelineLayoutDesc for VertLayout {
-    // Number of descriptor sets it takes.
-    fn num_sets(&self) -> usize {
-        0
-    }
-    // Number of entries (bindings) in each set.
-    fn num_bindings_in_set(&self, _set: usize) -> Option<usize> {
-        None
-    }
-    // Descriptor descriptions.
-    fn descriptor(&self, _set: usize, _binding: usize) -> Option<DescriptorDesc> {
-        None
-    }
-    // Number of push constants ranges (think: number of push constants).
-    fn num_push_constants_ranges(&self) -> usize {
-        0
-    }
-    // Each push constant range in memory.
-    fn push_constants_range(&self, _num: usize) -> Option<PipelineLayoutDescPcRange> {
-        None
     }
 }
 
-// Same as with our vertex shader, but for fragment one instead.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct FragInput;
-unsafe impl ShaderInterfaceDef for FragInput {
-    type Iter = FragInputIter;
+impl std::error::Error for ShaderError {}
 
-    fn elements(&self) -> FragInputIter {
-        FragInputIter(0)
-    }
+/// A shader module together with the interface reflected from its SPIR-V.
+pub struct Shader {
+    pub module: Arc<ShaderModule>,
+    pub reflection: reflect::Reflection,
+    origin: String,
 }
-#[derive(Debug, Copy, Clone)]
-pub struct FragInputIter(u16);
-
-impl Iterator for FragInputIter {
-    type Item = ShaderInterfaceDefEntry;
 
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.0 == 0 {
-            self.0 += 1;
-            return Some(ShaderInterfaceDefEntry {
-                location: 0..1,
-                format: Format::R32G32B32Sfloat,
-                name: Some(Cow::Borrowed("v_color")),
-            });
+/// Creates a shader module from `source`, unless `override_dir` contains a `<name>.spv` which is
+/// then used instead.
+pub fn load(
+    source: &Source,
+    override_dir: Option<&Path>,
+    device: Arc<Device>,
+) -> Result<Shader, ShaderError> {
+    let user_spirv;
+    let (origin, spirv) = match override_dir.map(|dir| dir.join(source.name).with_extension("spv"))
+    {
+        Some(path) if path.exists() => {
+            user_spirv = fs::read(&path).map_err(|e| ShaderError::Io(path.clone(), e))?;
+            (path.display().to_string(), &user_spirv[..])
         }
-        None
-    }
-
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = (1 - self.0) as usize;
-        (len, Some(len))
-    }
+        _ => (format!("embedded shader {}", source.name), source.spirv),
+    };
+
+    let magic = spirv
+        .get(..4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+    if spirv.len() % 4 != 0 || magic != Some(reflect::MAGIC) {
+        return Err(ShaderError::InvalidSpirv(origin));
+    }
+
+    let reflection =
+        reflect::reflect(spirv).map_err(|e| ShaderError::Reflect(origin.clone(), e))?;
+    let module = unsafe { ShaderModule::new(device, spirv) }
+        .map_err(|e| ShaderError::Creation(origin.clone(), e))?;
+    Ok(Shader {
+        module,
+        reflection,
+        origin,
+    })
 }
 
-impl ExactSizeIterator for FragInputIter {}
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct FragOutput;
-unsafe impl ShaderInterfaceDef for FragOutput {
-    type Iter = FragOutputIter;
+pub type EntryPoint<'a> = GraphicsEntryPoint<'a, (), Interface, Interface, Layout>;
 
-    fn elements(&self) -> FragOutputIter {
-        FragOutputIter(0)
+impl Shader {
+    /// The entry point of a vertex shader whose inputs are fed from buffers of `V`.
+    pub fn vertex_entry<V: vertex::Vertex>(&self) -> Result<EntryPoint<'_>, ShaderError> {
+        self.expect_stage(Stage::Vertex)?;
+        self.reflection
+            .inputs
+            .check_vertex::<V>()
+            .map_err(|e| ShaderError::Reflect(self.origin.clone(), e))?;
+        Ok(self.entry(GraphicsShaderType::Vertex))
     }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub struct FragOutputIter(u16);
-
-impl Iterator for FragOutputIter {
-    type Item = ShaderInterfaceDefEntry;
 
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        // Note that color fragment color entry will be determined
-        // automatically by Vulkano.
-        if self.0 == 0 {
-            self.0 += 1;
-            return Some(ShaderInterfaceDefEntry {
-                location: 0..1,
-                format: Format::R32G32B32A32Sfloat,
-                name: Some(Cow::Borrowed("f_color")),
-            });
-        }
-        None
-    }
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = (1 - self.0) as usize;
-        (len, Some(len))
+    pub fn fragment_entry(&self) -> Result<EntryPoint<'_>, ShaderError> {
+        self.expect_stage(Stage::Fragment)?;
+        Ok(self.entry(GraphicsShaderType::Fragment))
     }
-}
-
-impl ExactSizeIterator for FragOutputIter {}
 
-// Layout same as with vertex shader.
-#[derive(Debug, Copy, Clone)]
-pub struct FragLayout(ShaderStages);
-unsafe impl PipelineLayoutDesc for FragLayout {
-    fn num_sets(&self) -> usize {
-        0
-    }
-    fn num_bindings_in_set(&self, _set: usize) -> Option<usize> {
-        None
-    }
-    fn descriptor(&self, _set: usize, _binding: usize) -> Option<DescriptorDesc> {
-        None
-    }
-    fn num_push_constants_ranges(&self) -> usize {
-        0
-    }
-    fn push_constants_range(&self, _num: usize) -> Option<PipelineLayoutDescPcRange> {
-        None
+    fn expect_stage(&self, expected: Stage) -> Result<(), ShaderError> {
+        let found = self.reflection.stage;
+        if found == expected {
+            Ok(())
+        } else {
+            let e = ReflectError::WrongStage { expected, found };
+            Err(ShaderError::Reflect(self.origin.clone(), e))
+        }
     }
-}
 
-pub fn get_entry_fragment(
-    shader: &ShaderModule,
-) -> GraphicsEntryPoint<'_, (), FragInput, FragOutput, FragLayout> {
-    unsafe {
-        shader.graphics_entry_point(
-            CStr::from_bytes_with_nul_unchecked(b"main\0"),
-            FragInput,
-            FragOutput,
-            FragLayout(ShaderStages {
-                fragment: true,
-                ..ShaderStages::none()
-            }),
-            GraphicsShaderType::Fragment,
-        )
-    }
-}
-pub fn get_entry_vertex(
-    shader: &ShaderModule,
-) -> GraphicsEntryPoint<'_, (), VertInput, VertOutput, VertLayout> {
-    unsafe {
-        shader.graphics_entry_point(
-            CStr::from_bytes_with_nul_unchecked(b"main\0"),
-            VertInput,
-            VertOutput,
-            VertLayout(ShaderStages {
-                vertex: true,
-                ..ShaderStages::none()
-            }),
-            GraphicsShaderType::Vertex,
-        )
+    fn entry(&self, ty: GraphicsShaderType) -> EntryPoint<'_> {
+        let reflection = &self.reflection;
+        unsafe {
+            self.module.graphics_entry_point(
+                &reflection.entry_point,
+                reflection.inputs.clone(),
+                reflection.outputs.clone(),
+                reflection.layout.clone(),
+                ty,
+            )
+        }
     }
 }
@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 v_local;
+layout(location = 1) in vec2 v_half_size;
+layout(location = 2) in float v_radius;
+layout(location = 3) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    // Signed distance from the edge of the rounded rectangle, negative inside
+    vec2 q = abs(v_local) - v_half_size + v_radius;
+    float distance = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - v_radius;
+    float coverage = clamp(0.5 - distance, 0.0, 1.0);
+    f_color = vec4(v_color.rgb, v_color.a * coverage);
+}
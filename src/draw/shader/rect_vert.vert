@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 local;
+layout(location = 2) in vec2 half_size;
+layout(location = 3) in float radius;
+layout(location = 4) in vec4 color;
+
+layout(location = 0) out vec2 v_local;
+layout(location = 1) out vec2 v_half_size;
+layout(location = 2) out float v_radius;
+layout(location = 3) out vec4 v_color;
+
+void main() {
+    v_local = local;
+    v_half_size = half_size;
+    v_radius = radius;
+    v_color = color;
+
+    gl_Position = vec4(position, 0.0, 1.0);
+}
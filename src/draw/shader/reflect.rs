@@ -0,0 +1,788 @@
+//! Reads the entry point interface and resource bindings straight out of a SPIR-V module, so the
+//! pipeline description can never drift from what the GLSL declares.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::ops::Range;
+use vulkano::descriptor::descriptor::{
+    DescriptorBufferDesc, DescriptorDesc, DescriptorDescTy, DescriptorImageDesc,
+    DescriptorImageDescArray, DescriptorImageDescDimensions, ShaderStages,
+};
+use vulkano::descriptor::pipeline_layout::{PipelineLayoutDesc, PipelineLayoutDescPcRange};
+use vulkano::format::Format;
+use vulkano::pipeline::shader::{ShaderInterfaceDef, ShaderInterfaceDefEntry};
+use vulkano::pipeline::vertex::Vertex;
+
+pub(super) const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+mod op {
+    pub const NAME: u16 = 5;
+    pub const ENTRY_POINT: u16 = 15;
+    pub const TYPE_INT: u16 = 21;
+    pub const TYPE_FLOAT: u16 = 22;
+    pub const TYPE_VECTOR: u16 = 23;
+    pub const TYPE_MATRIX: u16 = 24;
+    pub const TYPE_IMAGE: u16 = 25;
+    pub const TYPE_SAMPLER: u16 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u16 = 27;
+    pub const TYPE_ARRAY: u16 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u16 = 29;
+    pub const TYPE_STRUCT: u16 = 30;
+    pub const TYPE_POINTER: u16 = 32;
+    pub const CONSTANT: u16 = 43;
+    pub const VARIABLE: u16 = 59;
+    pub const DECORATE: u16 = 71;
+    pub const MEMBER_DECORATE: u16 = 72;
+}
+
+mod decoration {
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const OUTPUT: u32 = 3;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+    Other(u32),
+}
+
+impl Stage {
+    fn from_execution_model(model: u32) -> Stage {
+        match model {
+            0 => Stage::Vertex,
+            4 => Stage::Fragment,
+            other => Stage::Other(other),
+        }
+    }
+
+    fn shader_stages(self) -> ShaderStages {
+        match self {
+            Stage::Vertex => ShaderStages {
+                vertex: true,
+                ..ShaderStages::none()
+            },
+            Stage::Fragment => ShaderStages {
+                fragment: true,
+                ..ShaderStages::none()
+            },
+            Stage::Other(_) => ShaderStages::all(),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Vertex => write!(f, "vertex"),
+            Stage::Fragment => write!(f, "fragment"),
+            Stage::Other(model) => write!(f, "execution model {}", model),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReflectError {
+    Malformed(&'static str),
+    NoEntryPoint,
+    UnsupportedType(String),
+    WrongStage { expected: Stage, found: Stage },
+    MissingVertexMember(String),
+    VertexMismatch { name: String, format: Format },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflectError::Malformed(what) => write!(f, "malformed SPIR-V: {}", what),
+            ReflectError::NoEntryPoint => write!(f, "module has no entry point"),
+            ReflectError::UnsupportedType(name) => {
+                write!(
+                    f,
+                    "`{}` has a type that can not be described to vulkan",
+                    name
+                )
+            }
+            ReflectError::WrongStage { expected, found } => {
+                write!(
+                    f,
+                    "expected a {} shader but found a {} shader",
+                    expected, found
+                )
+            }
+            ReflectError::MissingVertexMember(name) => write!(
+                f,
+                "shader input `{}` has no member of the same name in the vertex type",
+                name
+            ),
+            ReflectError::VertexMismatch { name, format } => write!(
+                f,
+                "vertex member `{}` does not match the shader input format {:?}",
+                name, format
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+/// The inputs or outputs of a shader stage.
+#[derive(Debug, Clone)]
+pub struct Interface(Vec<ShaderInterfaceDefEntry>);
+
+unsafe impl ShaderInterfaceDef for Interface {
+    type Iter = std::vec::IntoIter<ShaderInterfaceDefEntry>;
+
+    fn elements(&self) -> Self::Iter {
+        self.0.clone().into_iter()
+    }
+}
+
+impl Interface {
+    /// Fails with the first entry that `V` is missing or has laid out differently.
+    pub fn check_vertex<V: Vertex>(&self) -> Result<(), ReflectError> {
+        for entry in &self.0 {
+            let name = entry.name.as_deref().unwrap_or("");
+            let member = V::member(name)
+                .ok_or_else(|| ReflectError::MissingVertexMember(name.to_owned()))?;
+            let locations = entry.location.end - entry.location.start;
+            if !member
+                .ty
+                .matches(member.array_size, entry.format, locations)
+            {
+                return Err(ReflectError::VertexMismatch {
+                    name: name.to_owned(),
+                    format: entry.format,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The descriptor sets and push constants used by a shader stage.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    sets: Vec<Vec<Option<DescriptorDesc>>>,
+    push_constants: Vec<PipelineLayoutDescPcRange>,
+}
+
+unsafe impl PipelineLayoutDesc for Layout {
+    fn num_sets(&self) -> usize {
+        self.sets.len()
+    }
+    fn num_bindings_in_set(&self, set: usize) -> Option<usize> {
+        self.sets.get(set).map(Vec::len)
+    }
+    fn descriptor(&self, set: usize, binding: usize) -> Option<DescriptorDesc> {
+        self.sets.get(set)?.get(binding)?.clone()
+    }
+    fn num_push_constants_ranges(&self) -> usize {
+        self.push_constants.len()
+    }
+    fn push_constants_range(&self, num: usize) -> Option<PipelineLayoutDescPcRange> {
+        self.push_constants.get(num).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reflection {
+    pub stage: Stage,
+    pub entry_point: CString,
+    pub inputs: Interface,
+    pub outputs: Interface,
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+    Float,
+    Int,
+    Uint,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Scalar(Scalar, u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image {
+        dim: u32,
+        arrayed: bool,
+        multisampled: bool,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage(u32),
+    Array(u32, Option<u32>),
+    Struct(Vec<u32>),
+    Pointer(u32, u32),
+}
+
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(u32, CString, Vec<u32>)>,
+    names: HashMap<u32, String>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    // (id, pointer type, storage class) in declaration order
+    variables: Vec<(u32, u32, u32)>,
+}
+
+pub fn reflect(spirv: &[u8]) -> Result<Reflection, ReflectError> {
+    if spirv.len() % 4 != 0 {
+        return Err(ReflectError::Malformed("length is not a multiple of 4"));
+    }
+    let words = spirv
+        .chunks(4)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        .collect::<Vec<_>>();
+    if words.len() < HEADER_WORDS || words[0] != MAGIC {
+        return Err(ReflectError::Malformed("missing header"));
+    }
+
+    let module = Module::parse(&words[HEADER_WORDS..])?;
+    let (model, entry_point, interface) = module
+        .entry_point
+        .clone()
+        .ok_or(ReflectError::NoEntryPoint)?;
+    let stage = Stage::from_execution_model(model);
+
+    Ok(Reflection {
+        stage,
+        entry_point,
+        inputs: module.interface(&interface, storage::INPUT)?,
+        outputs: module.interface(&interface, storage::OUTPUT)?,
+        layout: module.layout(stage.shader_stages())?,
+    })
+}
+
+impl Module {
+    fn parse(mut words: &[u32]) -> Result<Module, ReflectError> {
+        let mut module = Module::default();
+
+        while let Some(&first) = words.first() {
+            let (count, opcode) = ((first >> 16) as usize, first as u16);
+            if count == 0 || count > words.len() {
+                return Err(ReflectError::Malformed("instruction overruns the module"));
+            }
+            let operands = &words[1..count];
+            words = &words[count..];
+
+            let arg = |i: usize| {
+                operands
+                    .get(i)
+                    .copied()
+                    .ok_or(ReflectError::Malformed("instruction is missing operands"))
+            };
+            let ty = match opcode {
+                op::ENTRY_POINT if module.entry_point.is_none() => {
+                    let (name, rest) = string(operands.get(2..).unwrap_or(&[]))?;
+                    module.entry_point = Some((arg(0)?, name, rest.to_vec()));
+                    continue;
+                }
+                op::NAME => {
+                    let (name, _) = string(operands.get(1..).unwrap_or(&[]))?;
+                    module
+                        .names
+                        .insert(arg(0)?, name.to_string_lossy().into_owned());
+                    continue;
+                }
+                op::DECORATE => {
+                    let value = operands.get(2).copied().unwrap_or(0);
+                    module.decorations.insert((arg(0)?, arg(1)?), value);
+                    continue;
+                }
+                op::MEMBER_DECORATE => {
+                    let value = operands.get(3).copied().unwrap_or(0);
+                    module
+                        .member_decorations
+                        .insert((arg(0)?, arg(1)?, arg(2)?), value);
+                    continue;
+                }
+                op::CONSTANT => {
+                    module.constants.insert(arg(1)?, arg(2)?);
+                    continue;
+                }
+                op::VARIABLE => {
+                    module.variables.push((arg(1)?, arg(0)?, arg(2)?));
+                    continue;
+                }
+                op::TYPE_INT if arg(2)? == 0 => Type::Scalar(Scalar::Uint, arg(1)?),
+                op::TYPE_INT => Type::Scalar(Scalar::Int, arg(1)?),
+                op::TYPE_FLOAT => Type::Scalar(Scalar::Float, arg(1)?),
+                op::TYPE_VECTOR => Type::Vector(arg(1)?, arg(2)?),
+                op::TYPE_MATRIX => Type::Matrix(arg(1)?, arg(2)?),
+                op::TYPE_IMAGE => Type::Image {
+                    dim: arg(2)?,
+                    arrayed: arg(4)? != 0,
+                    multisampled: arg(5)? != 0,
+                    sampled: arg(6)?,
+                },
+                op::TYPE_SAMPLER => Type::Sampler,
+                op::TYPE_SAMPLED_IMAGE => Type::SampledImage(arg(1)?),
+                op::TYPE_ARRAY => Type::Array(arg(1)?, Some(arg(2)?)),
+                op::TYPE_RUNTIME_ARRAY => Type::Array(arg(1)?, None),
+                op::TYPE_STRUCT => Type::Struct(operands.get(1..).unwrap_or(&[]).to_vec()),
+                op::TYPE_POINTER => Type::Pointer(arg(1)?, arg(2)?),
+                _ => continue,
+            };
+            module.types.insert(arg(0)?, ty);
+        }
+
+        Ok(module)
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| format!("%{}", id))
+    }
+
+    fn unsupported(&self, id: u32) -> ReflectError {
+        ReflectError::UnsupportedType(self.name(id))
+    }
+
+    fn pointee(&self, pointer: u32) -> Option<u32> {
+        match self.types.get(&pointer) {
+            Some(Type::Pointer(_, pointee)) => Some(*pointee),
+            _ => None,
+        }
+    }
+
+    fn is_builtin(&self, variable: u32, ty: u32) -> bool {
+        self.decorations
+            .contains_key(&(variable, decoration::BUILT_IN))
+            || self
+                .member_decorations
+                .contains_key(&(ty, 0, decoration::BUILT_IN))
+    }
+
+    fn interface(&self, ids: &[u32], class: u32) -> Result<Interface, ReflectError> {
+        let mut entries = Vec::new();
+        for &(id, pointer, storage_class) in &self.variables {
+            if storage_class != class || !ids.contains(&id) {
+                continue;
+            }
+            let ty = self.pointee(pointer).ok_or_else(|| self.unsupported(id))?;
+            if self.is_builtin(id, ty) {
+                continue;
+            }
+            let location = *self.decorations.get(&(id, decoration::LOCATION)).ok_or(
+                ReflectError::Malformed("interface variable without a location"),
+            )?;
+            let (format, locations) = self.format(ty).ok_or_else(|| self.unsupported(id))?;
+            entries.push(ShaderInterfaceDefEntry {
+                location: location..location + locations,
+                format,
+                name: self.names.get(&id).cloned().map(Cow::Owned),
+            });
+        }
+        entries.sort_by_key(|entry| entry.location.start);
+        Ok(Interface(entries))
+    }
+
+    // The format of a single location and how many locations the type takes up.
+    fn format(&self, ty: u32) -> Option<(Format, u32)> {
+        let (scalar, width, components) = match self.types.get(&ty)? {
+            Type::Scalar(scalar, width) => (*scalar, *width, 1),
+            Type::Vector(component, count) => match self.types.get(component)? {
+                Type::Scalar(scalar, width) => (*scalar, *width, *count),
+                _ => return None,
+            },
+            Type::Matrix(column, count) => {
+                let (format, locations) = self.format(*column)?;
+                return Some((format, locations * count));
+            }
+            Type::Array(element, Some(length)) => {
+                let (format, locations) = self.format(*element)?;
+                return Some((format, locations * self.constants.get(length)?));
+            }
+            _ => return None,
+        };
+        if width != 32 {
+            return None;
+        }
+        let format = match (scalar, components) {
+            (Scalar::Float, 1) => Format::R32Sfloat,
+            (Scalar::Float, 2) => Format::R32G32Sfloat,
+            (Scalar::Float, 3) => Format::R32G32B32Sfloat,
+            (Scalar::Float, 4) => Format::R32G32B32A32Sfloat,
+            (Scalar::Int, 1) => Format::R32Sint,
+            (Scalar::Int, 2) => Format::R32G32Sint,
+            (Scalar::Int, 3) => Format::R32G32B32Sint,
+            (Scalar::Int, 4) => Format::R32G32B32A32Sint,
+            (Scalar::Uint, 1) => Format::R32Uint,
+            (Scalar::Uint, 2) => Format::R32G32Uint,
+            (Scalar::Uint, 3) => Format::R32G32B32Uint,
+            (Scalar::Uint, 4) => Format::R32G32B32A32Uint,
+            _ => return None,
+        };
+        Some((format, 1))
+    }
+
+    fn layout(&self, stages: ShaderStages) -> Result<Layout, ReflectError> {
+        let mut layout = Layout {
+            sets: Vec::new(),
+            push_constants: Vec::new(),
+        };
+
+        for &(id, pointer, class) in &self.variables {
+            let ty = match class {
+                storage::UNIFORM_CONSTANT | storage::UNIFORM | storage::STORAGE_BUFFER => {
+                    self.pointee(pointer).ok_or_else(|| self.unsupported(id))?
+                }
+                storage::PUSH_CONSTANT => {
+                    let ty = self.pointee(pointer).ok_or_else(|| self.unsupported(id))?;
+                    let range = self.struct_range(ty).ok_or_else(|| self.unsupported(id))?;
+                    layout.push_constants.push(PipelineLayoutDescPcRange {
+                        offset: range.start as usize,
+                        size: (range.end - range.start) as usize,
+                        stages,
+                    });
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let (ty, array_count) = match self.types.get(&ty) {
+                Some(Type::Array(element, Some(length))) => {
+                    let length = self.constants.get(length).copied();
+                    (*element, length.ok_or_else(|| self.unsupported(id))?)
+                }
+                _ => (ty, 1),
+            };
+            let (ty, readonly) = self
+                .descriptor_ty(ty, class == storage::STORAGE_BUFFER)
+                .ok_or_else(|| self.unsupported(id))?;
+
+            let set = self.decorations.get(&(id, decoration::DESCRIPTOR_SET));
+            let binding = self.decorations.get(&(id, decoration::BINDING));
+            let (set, binding) = match (set, binding) {
+                (Some(&set), Some(&binding)) => (set as usize, binding as usize),
+                _ => {
+                    return Err(ReflectError::Malformed(
+                        "resource without a set and binding",
+                    ))
+                }
+            };
+            if layout.sets.len() <= set {
+                layout.sets.resize(set + 1, Vec::new());
+            }
+            let bindings = &mut layout.sets[set];
+            if bindings.len() <= binding {
+                bindings.resize(binding + 1, None);
+            }
+            bindings[binding] = Some(DescriptorDesc {
+                ty,
+                array_count,
+                stages,
+                readonly,
+            });
+        }
+
+        Ok(layout)
+    }
+
+    fn descriptor_ty(&self, ty: u32, storage_buffer: bool) -> Option<(DescriptorDescTy, bool)> {
+        Some(match self.types.get(&ty)? {
+            Type::Sampler => (DescriptorDescTy::Sampler, true),
+            Type::SampledImage(image) => match self.descriptor_ty(*image, false)? {
+                (DescriptorDescTy::Image(desc), _) => {
+                    (DescriptorDescTy::CombinedImageSampler(desc), true)
+                }
+                _ => return None,
+            },
+            Type::Image {
+                dim,
+                arrayed,
+                multisampled,
+                sampled,
+            } => {
+                let array_layers = if *arrayed {
+                    DescriptorImageDescArray::Arrayed { max_layers: None }
+                } else {
+                    DescriptorImageDescArray::NonArrayed
+                };
+                let dimensions = match dim {
+                    0 => DescriptorImageDescDimensions::OneDimensional,
+                    1 | 4 => DescriptorImageDescDimensions::TwoDimensional,
+                    2 => DescriptorImageDescDimensions::ThreeDimensional,
+                    3 => DescriptorImageDescDimensions::Cube,
+                    6 => {
+                        let ty = DescriptorDescTy::InputAttachment {
+                            multisampled: *multisampled,
+                            array_layers,
+                        };
+                        return Some((ty, true));
+                    }
+                    _ => return None,
+                };
+                let storage = *sampled == 2;
+                let desc = DescriptorImageDesc {
+                    sampled: !storage,
+                    dimensions,
+                    format: None,
+                    multisampled: *multisampled,
+                    array_layers,
+                };
+                (DescriptorDescTy::Image(desc), !storage)
+            }
+            Type::Struct(_) => {
+                let storage = storage_buffer
+                    || self
+                        .decorations
+                        .contains_key(&(ty, decoration::BUFFER_BLOCK));
+                if !storage && !self.decorations.contains_key(&(ty, decoration::BLOCK)) {
+                    return None;
+                }
+                let desc = DescriptorBufferDesc {
+                    dynamic: Some(false),
+                    storage,
+                };
+                (DescriptorDescTy::Buffer(desc), !storage)
+            }
+            _ => return None,
+        })
+    }
+
+    // The byte range covered by the members of a struct.
+    fn struct_range(&self, ty: u32) -> Option<Range<u32>> {
+        let members = match self.types.get(&ty)? {
+            Type::Struct(members) => members,
+            _ => return None,
+        };
+        let mut range: Option<Range<u32>> = None;
+        for (i, &member) in members.iter().enumerate() {
+            let offset = *self
+                .member_decorations
+                .get(&(ty, i as u32, decoration::OFFSET))?;
+            let end = offset + self.size(member)?;
+            range = Some(match range {
+                Some(r) => r.start.min(offset)..r.end.max(end),
+                None => offset..end,
+            });
+        }
+        range
+    }
+
+    fn size(&self, ty: u32) -> Option<u32> {
+        match self.types.get(&ty)? {
+            Type::Scalar(_, width) => Some(width / 8),
+            Type::Vector(component, count) => Some(self.size(*component)? * count),
+            Type::Matrix(column, count) => Some(self.size(*column)? * count),
+            Type::Array(element, Some(length)) => {
+                let stride = match self.decorations.get(&(ty, decoration::ARRAY_STRIDE)) {
+                    Some(&stride) => stride,
+                    None => self.size(*element)?,
+                };
+                Some(stride * self.constants.get(length)?)
+            }
+            Type::Struct(_) => Some(self.struct_range(ty)?.end),
+            _ => None,
+        }
+    }
+}
+
+// A nul terminated literal string followed by whatever operands come after it.
+fn string(words: &[u32]) -> Result<(CString, &[u32]), ReflectError> {
+    let mut bytes = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        for &byte in word.to_le_bytes().iter() {
+            if byte == 0 {
+                let name = CString::new(bytes)
+                    .map_err(|_| ReflectError::Malformed("string with a nul in it"))?;
+                return Ok((name, &words[i + 1..]));
+            }
+            bytes.push(byte);
+        }
+    }
+    Err(ReflectError::Malformed("unterminated string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw::rect::RectVertex;
+    use crate::draw::text::TextVertex;
+
+    const RECT_VERT: &[u8] = include_bytes!("rect_vert.spv");
+    const RECT_FRAG: &[u8] = include_bytes!("rect_frag.spv");
+    const TEXT_VERT: &[u8] = include_bytes!("text_vert.spv");
+    const TEXT_FRAG: &[u8] = include_bytes!("text_frag.spv");
+
+    // A vertex whose `radius` is one float too many for the rectangle shader.
+    #[derive(Default, Debug, Clone, Copy)]
+    struct WideRadius {
+        position: [f32; 2],
+        local: [f32; 2],
+        half_size: [f32; 2],
+        radius: [f32; 2],
+        color: [f32; 4],
+    }
+
+    vulkano::impl_vertex!(WideRadius, position, local, half_size, radius, color);
+
+    fn entries(interface: &Interface) -> Vec<(Range<u32>, Format, &str)> {
+        interface
+            .0
+            .iter()
+            .map(|entry| {
+                let name = entry.name.as_deref().unwrap_or("");
+                (entry.location.clone(), entry.format, name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rect_shaders() {
+        let vert = reflect(RECT_VERT).unwrap();
+        assert_eq!(vert.stage, Stage::Vertex);
+        assert_eq!(vert.entry_point.to_str(), Ok("main"));
+        assert_eq!(
+            entries(&vert.inputs),
+            [
+                (0..1, Format::R32G32Sfloat, "position"),
+                (1..2, Format::R32G32Sfloat, "local"),
+                (2..3, Format::R32G32Sfloat, "half_size"),
+                (3..4, Format::R32Sfloat, "radius"),
+                (4..5, Format::R32G32B32A32Sfloat, "color"),
+            ]
+        );
+        // `gl_Position` is built in, so it is left out
+        assert_eq!(
+            entries(&vert.outputs),
+            [
+                (0..1, Format::R32G32Sfloat, "v_local"),
+                (1..2, Format::R32G32Sfloat, "v_half_size"),
+                (2..3, Format::R32Sfloat, "v_radius"),
+                (3..4, Format::R32G32B32A32Sfloat, "v_color"),
+            ]
+        );
+        assert_eq!(vert.layout.num_sets(), 0);
+        assert_eq!(vert.layout.num_push_constants_ranges(), 0);
+        assert!(vert.inputs.check_vertex::<RectVertex>().is_ok());
+
+        let frag = reflect(RECT_FRAG).unwrap();
+        assert_eq!(frag.stage, Stage::Fragment);
+        assert_eq!(entries(&frag.inputs), entries(&vert.outputs));
+        assert_eq!(
+            entries(&frag.outputs),
+            [(0..1, Format::R32G32B32A32Sfloat, "f_color")]
+        );
+        assert_eq!(frag.layout.num_sets(), 0);
+    }
+
+    #[test]
+    fn text_shaders() {
+        let vert = reflect(TEXT_VERT).unwrap();
+        assert_eq!(vert.stage, Stage::Vertex);
+        assert_eq!(
+            entries(&vert.inputs),
+            [
+                (0..1, Format::R32G32Sfloat, "position"),
+                (1..2, Format::R32G32Sfloat, "tex_coords"),
+                (2..3, Format::R32G32B32A32Sfloat, "color"),
+            ]
+        );
+        assert!(vert.inputs.check_vertex::<TextVertex>().is_ok());
+
+        let frag = reflect(TEXT_FRAG).unwrap();
+        assert_eq!(frag.stage, Stage::Fragment);
+        assert_eq!(entries(&frag.inputs), entries(&vert.outputs));
+        assert_eq!(frag.layout.num_sets(), 1);
+        assert_eq!(frag.layout.num_bindings_in_set(0), Some(2));
+        let atlas = frag.layout.descriptor(0, 0).unwrap();
+        assert!(matches!(
+            atlas.ty,
+            DescriptorDescTy::Image(DescriptorImageDesc {
+                sampled: true,
+                dimensions: DescriptorImageDescDimensions::TwoDimensional,
+                multisampled: false,
+                array_layers: DescriptorImageDescArray::NonArrayed,
+                ..
+            })
+        ));
+        let sampler = frag.layout.descriptor(0, 1).unwrap();
+        assert!(matches!(sampler.ty, DescriptorDescTy::Sampler));
+        for desc in &[atlas, sampler] {
+            assert_eq!(desc.array_count, 1);
+            assert!(desc.readonly);
+            assert!(desc.stages.fragment && !desc.stages.vertex);
+        }
+    }
+
+    #[test]
+    fn vertex_mismatch() {
+        let rect = reflect(RECT_VERT).unwrap();
+        match rect.inputs.check_vertex::<WideRadius>() {
+            Err(ReflectError::VertexMismatch { name, format }) => {
+                assert_eq!((name.as_str(), format), ("radius", Format::R32Sfloat))
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+        match rect.inputs.check_vertex::<TextVertex>() {
+            Err(ReflectError::MissingVertexMember(name)) => assert_eq!(name, "local"),
+            other => panic!("expected a missing member, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let header = |words: &[u32]| {
+            [MAGIC, 0x0001_0000, 0, 1, 0]
+                .iter()
+                .chain(words)
+                .flat_map(|word| word.to_le_bytes().to_vec())
+                .collect::<Vec<u8>>()
+        };
+        // OpEntryPoint Vertex %1 "main" with the string cut off
+        let unterminated = header(&[4 << 16 | op::ENTRY_POINT as u32, 0, 1, 0x6e69_616d]);
+        let cases = [
+            (Vec::new(), "missing header"),
+            (
+                RECT_VERT[..RECT_VERT.len() - 1].to_vec(),
+                "length is not a multiple of 4",
+            ),
+            (RECT_VERT[4..].to_vec(), "missing header"),
+            (header(&[]), "module has no entry point"),
+            (header(&[0]), "instruction overruns the module"),
+            (
+                header(&[5 << 16 | op::NAME as u32, 1]),
+                "instruction overruns the module",
+            ),
+            (
+                header(&[2 << 16 | op::TYPE_FLOAT as u32, 1]),
+                "instruction is missing operands",
+            ),
+            (unterminated, "unterminated string"),
+        ];
+        for (spirv, message) in cases.iter() {
+            let message = message.to_string();
+            match reflect(spirv) {
+                Err(e) => assert!(e.to_string().ends_with(&message), "{} for {:?}", e, message),
+                Ok(_) => panic!("reflected a module with {}", message),
+            }
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D atlas;
+layout(set = 0, binding = 1) uniform sampler atlas_sampler;
+
+void main() {
+    // Outline glyphs are white, so this tints them while color glyphs are multiplied by white
+    f_color = v_color * texture(sampler2D(atlas, atlas_sampler), v_tex_coords);
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 tex_coords;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec2 v_tex_coords;
+layout(location = 1) out vec4 v_color;
+
+void main() {
+    v_tex_coords = tex_coords;
+    v_color = color;
+
+    gl_Position = vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,366 @@
+use crate::cli::Mode;
+
+/// What is shown, and where input goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    /// Nothing is on screen yet, or every output showing the bar went away.
+    Hidden,
+    /// Just the bar, which in `run` mode opens the launcher when clicked.
+    BarOnly,
+    /// Searching for an application. `selection` indexes the results of `query`.
+    LauncherOpen { query: String, selection: usize },
+    /// Typing a shell command to run.
+    CommandMode { command: String },
+    /// On the way out, after which nothing happens anymore.
+    Closing,
+}
+
+/// Everything that moves the application from one `State` to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A surface was put on screen.
+    Shown,
+    /// The last surface was taken away by the compositor.
+    Hidden,
+    /// The bar was clicked.
+    Open,
+    /// `Action::Close`, such as escape being pressed.
+    Close,
+    /// An application or command was started.
+    Launched,
+    /// The text typed into the launcher or command line changed.
+    Edited(String),
+    Select(usize),
+    /// Moves the selection through the first `shown` results, wrapping around.
+    SelectNext {
+        shown: usize,
+    },
+    SelectPrevious {
+        shown: usize,
+    },
+    CommandMode,
+    LauncherMode,
+    /// Asked to exit, by a signal or over the IPC socket.
+    Quit,
+}
+
+/// Owns the `State` and moves it along as events come in.
+#[derive(Debug)]
+pub struct Controller {
+    mode: Mode,
+    state: State,
+}
+
+impl Controller {
+    pub fn new(mode: Mode) -> Controller {
+        Controller {
+            mode,
+            state: State::Hidden,
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Applies `event`, returning whether the state changed.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let next = transition(self.mode, &self.state, event);
+        if next == self.state {
+            return false;
+        }
+        self.state = next;
+        true
+    }
+}
+
+impl State {
+    /// Whether the launcher is open, rather than just the bar.
+    pub fn is_expanded(&self) -> bool {
+        matches!(self, State::LauncherOpen { .. } | State::CommandMode { .. })
+    }
+
+    /// The text of the launcher or command line.
+    pub fn query(&self) -> &str {
+        match self {
+            State::LauncherOpen { query, .. } => query,
+            State::CommandMode { command } => command,
+            _ => "",
+        }
+    }
+
+    pub fn selection(&self) -> usize {
+        match self {
+            State::LauncherOpen { selection, .. } => *selection,
+            _ => 0,
+        }
+    }
+}
+
+/// The state that `event` leads to from `state` in `mode`. Events that don't apply to a state
+/// leave it as it is.
+pub fn transition(mode: Mode, state: &State, event: Event) -> State {
+    let launcher = State::LauncherOpen {
+        query: String::new(),
+        selection: 0,
+    };
+    // What closing the launcher leads to, the bar stays in `run` mode
+    let closed = match mode {
+        Mode::Full => State::BarOnly,
+        Mode::Launcher | Mode::Bar => State::Closing,
+    };
+
+    match (state, event) {
+        (State::Closing, _) | (_, Event::Quit) => State::Closing,
+
+        (State::Hidden, Event::Shown) if mode == Mode::Launcher => launcher,
+        (State::Hidden, Event::Shown) => State::BarOnly,
+        // Only the bar outlives its surfaces, since they come back with their outputs
+        (_, Event::Hidden) if mode == Mode::Bar => State::Hidden,
+        (_, Event::Hidden) => State::Closing,
+
+        (State::BarOnly, Event::Open) if mode == Mode::Full => launcher,
+        (State::BarOnly, Event::Close) if mode == Mode::Bar => State::Closing,
+
+        (State::LauncherOpen { .. }, Event::Close) | (State::CommandMode { .. }, Event::Close) => {
+            closed
+        }
+        (State::LauncherOpen { .. }, Event::Launched)
+        | (State::CommandMode { .. }, Event::Launched) => closed,
+
+        (State::LauncherOpen { query, selection }, Event::Edited(text)) => {
+            State::LauncherOpen {
+                // A different query has different results
+                selection: if text == *query { *selection } else { 0 },
+                query: text,
+            }
+        }
+        (State::CommandMode { .. }, Event::Edited(command)) => State::CommandMode { command },
+
+        (State::LauncherOpen { query, .. }, Event::Select(selection)) => State::LauncherOpen {
+            query: query.clone(),
+            selection,
+        },
+        (State::LauncherOpen { query, selection }, Event::SelectNext { shown }) if shown > 0 => {
+            State::LauncherOpen {
+                query: query.clone(),
+                selection: (selection + 1) % shown,
+            }
+        }
+        (State::LauncherOpen { query, selection }, Event::SelectPrevious { shown })
+            if shown > 0 =>
+        {
+            State::LauncherOpen {
+                query: query.clone(),
+                selection: (selection + shown - 1) % shown,
+            }
+        }
+
+        (State::LauncherOpen { .. }, Event::CommandMode) => State::CommandMode {
+            command: String::new(),
+        },
+        (State::CommandMode { .. }, Event::LauncherMode) => launcher,
+
+        (state, _) => state.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [Mode; 3] = [Mode::Full, Mode::Launcher, Mode::Bar];
+
+    fn open(query: &str, selection: usize) -> State {
+        State::LauncherOpen {
+            query: query.to_owned(),
+            selection,
+        }
+    }
+
+    fn command(command: &str) -> State {
+        State::CommandMode {
+            command: command.to_owned(),
+        }
+    }
+
+    fn check(table: &[(Mode, State, Event, State)]) {
+        for (mode, state, event, expected) in table {
+            assert_eq!(
+                transition(*mode, state, event.clone()),
+                *expected,
+                "{:?} in {:?} mode, after {:?}",
+                state,
+                mode,
+                event
+            );
+        }
+    }
+
+    #[test]
+    fn shown_and_hidden() {
+        check(&[
+            (Mode::Full, State::Hidden, Event::Shown, State::BarOnly),
+            (Mode::Launcher, State::Hidden, Event::Shown, open("", 0)),
+            (Mode::Bar, State::Hidden, Event::Shown, State::BarOnly),
+            (Mode::Full, State::BarOnly, Event::Shown, State::BarOnly),
+            (Mode::Bar, State::BarOnly, Event::Hidden, State::Hidden),
+            (Mode::Full, State::BarOnly, Event::Hidden, State::Closing),
+            (Mode::Full, open("a", 1), Event::Hidden, State::Closing),
+            (Mode::Launcher, open("a", 1), Event::Hidden, State::Closing),
+            (Mode::Launcher, command("ls"), Event::Hidden, State::Closing),
+        ]);
+    }
+
+    #[test]
+    fn open_close_and_launch() {
+        check(&[
+            (Mode::Full, State::BarOnly, Event::Open, open("", 0)),
+            (Mode::Bar, State::BarOnly, Event::Open, State::BarOnly),
+            (Mode::Full, State::BarOnly, Event::Close, State::BarOnly),
+            (Mode::Bar, State::BarOnly, Event::Close, State::Closing),
+            (Mode::Full, open("a", 1), Event::Close, State::BarOnly),
+            (Mode::Full, command("ls"), Event::Close, State::BarOnly),
+            (Mode::Launcher, open("a", 1), Event::Close, State::Closing),
+            (Mode::Launcher, command("ls"), Event::Close, State::Closing),
+            (Mode::Full, open("a", 1), Event::Launched, State::BarOnly),
+            (Mode::Full, command("ls"), Event::Launched, State::BarOnly),
+            (
+                Mode::Launcher,
+                open("a", 1),
+                Event::Launched,
+                State::Closing,
+            ),
+            (
+                Mode::Launcher,
+                command("ls"),
+                Event::Launched,
+                State::Closing,
+            ),
+            (Mode::Full, State::BarOnly, Event::Launched, State::BarOnly),
+        ]);
+    }
+
+    #[test]
+    fn selection() {
+        let shown = 3;
+        check(&[
+            (Mode::Full, open("a", 0), Event::Select(2), open("a", 2)),
+            (
+                Mode::Full,
+                open("a", 1),
+                Event::SelectNext { shown },
+                open("a", 2),
+            ),
+            (
+                Mode::Full,
+                open("a", 2),
+                Event::SelectNext { shown },
+                open("a", 0),
+            ),
+            (
+                Mode::Full,
+                open("a", 1),
+                Event::SelectPrevious { shown },
+                open("a", 0),
+            ),
+            (
+                Mode::Full,
+                open("a", 0),
+                Event::SelectPrevious { shown },
+                open("a", 2),
+            ),
+            (
+                Mode::Full,
+                open("a", 0),
+                Event::SelectNext { shown: 0 },
+                open("a", 0),
+            ),
+            (
+                Mode::Full,
+                open("a", 0),
+                Event::SelectPrevious { shown: 0 },
+                open("a", 0),
+            ),
+            (
+                Mode::Launcher,
+                command("ls"),
+                Event::SelectNext { shown },
+                command("ls"),
+            ),
+            (Mode::Full, State::BarOnly, Event::Select(1), State::BarOnly),
+        ]);
+    }
+
+    #[test]
+    fn editing() {
+        check(&[
+            (
+                Mode::Full,
+                open("fire", 2),
+                Event::Edited("fire".into()),
+                open("fire", 2),
+            ),
+            (
+                Mode::Full,
+                open("fire", 2),
+                Event::Edited("firef".into()),
+                open("firef", 0),
+            ),
+            (
+                Mode::Full,
+                open("fire", 2),
+                Event::Edited("".into()),
+                open("", 0),
+            ),
+            (
+                Mode::Full,
+                command("l"),
+                Event::Edited("ls".into()),
+                command("ls"),
+            ),
+            (
+                Mode::Full,
+                State::BarOnly,
+                Event::Edited("a".into()),
+                State::BarOnly,
+            ),
+            (Mode::Full, open("fire", 2), Event::CommandMode, command("")),
+            (
+                Mode::Launcher,
+                command("ls"),
+                Event::LauncherMode,
+                open("", 0),
+            ),
+            (
+                Mode::Launcher,
+                command("ls"),
+                Event::CommandMode,
+                command("ls"),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn quit_from_everywhere() {
+        let states = [
+            State::Hidden,
+            State::BarOnly,
+            open("a", 1),
+            command("ls"),
+            State::Closing,
+        ];
+        for &mode in MODES.iter() {
+            for state in states.iter() {
+                check(&[(mode, state.clone(), Event::Quit, State::Closing)]);
+            }
+            check(&[(mode, State::Closing, Event::Shown, State::Closing)]);
+        }
+
+        let mut controller = Controller::new(Mode::Bar);
+        assert!(controller.handle(Event::Shown));
+        assert!(!controller.handle(Event::Open));
+        assert!(controller.handle(Event::Quit));
+        assert_eq!(*controller.state(), State::Closing);
+    }
+}
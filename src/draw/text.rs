@@ -0,0 +1,498 @@
+use super::shader::{self, ShaderError};
+use super::vulkan::{Target, VkSession};
+use super::{frame_error, DrawError};
+use crate::ui::Measure;
+use ab_glyph::{point, v2::GlyphImage, Font as _, GlyphId, GlyphImageFormat, ScaleFont};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::format::Format;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::{Dimensions, ImmutableImage};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::GpuFuture;
+
+mod atlas;
+mod font;
+mod shape;
+use atlas::{Atlas, Bitmap, GlyphKey, Key};
+pub use font::{FontError, FontSet};
+
+const ATLAS_SIZE: u32 = 1024;
+// The largest 2D image every Vulkan implementation supports
+const MAX_ATLAS_SIZE: u32 = 4096;
+
+#[derive(Default, Copy, Clone)]
+pub struct TextVertex {
+    pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+}
+
+vulkano::impl_vertex!(TextVertex, position, tex_coords, color);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+struct Quad {
+    key: Key,
+    pen: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Collects text and icons for the current frame and draws them from a shared atlas in a single
+/// draw call.
+pub struct TextRenderer {
+    fonts: FontSet,
+    atlas: Atlas,
+    icons: Vec<PathBuf>,
+    quads: Vec<Quad>,
+    pipeline: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+    sampler: Option<Arc<Sampler>>,
+    texture: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+}
+
+impl TextRenderer {
+    pub fn new(fonts: FontSet) -> TextRenderer {
+        TextRenderer {
+            fonts,
+            atlas: Atlas::new(ATLAS_SIZE, ATLAS_SIZE, MAX_ATLAS_SIZE),
+            icons: Vec::new(),
+            quads: Vec::new(),
+            pipeline: None,
+            sampler: None,
+            texture: None,
+        }
+    }
+
+    /// Forgets the text queued for the previous frame.
+    pub fn begin_frame(&mut self) {
+        self.quads.clear();
+        self.atlas.begin_frame();
+    }
+
+    /// Queues `text` to be drawn with its top-left corner at `pos`, in pixels. Fails when the
+    /// glyphs of the frame don't fit in the atlas even at its largest.
+    pub fn draw_text(
+        &mut self,
+        pos: [f32; 2],
+        text: &str,
+        style: &TextStyle,
+    ) -> Result<(), DrawError> {
+        let mut glyphs = Vec::new();
+        self.layout(pos, text, style, |key, pen| glyphs.push((key, pen)));
+
+        for (key, pen) in glyphs {
+            if self.atlas.get(&Key::Glyph(key)).is_none() {
+                let bitmap = rasterize(&self.fonts, key);
+                if self.atlas.insert(Key::Glyph(key), &bitmap).is_none() {
+                    return Err(DrawError::AtlasFull);
+                }
+            }
+            self.quads.push(Quad {
+                key: Key::Glyph(key),
+                pen,
+                color: style.color,
+            });
+        }
+        Ok(())
+    }
+
+    /// Queues the image at `path` to be drawn scaled to `size` with its top-left corner at `pos`.
+    /// Images that can't be loaded are skipped.
+    pub fn draw_icon(
+        &mut self,
+        pos: [f32; 2],
+        size: [f32; 2],
+        path: &Path,
+    ) -> Result<(), DrawError> {
+        let icon = match self.icons.iter().position(|p| p == path) {
+            Some(icon) => icon,
+            None => {
+                self.icons.push(path.to_owned());
+                self.icons.len() - 1
+            }
+        };
+        let size = [size[0].round() as u32, size[1].round() as u32];
+        let key = Key::Icon { icon, size };
+        if self.atlas.get(&key).is_none() {
+            // A failed load is cached as an empty bitmap so the file isn't read every frame
+            let bitmap = load_icon(path, size);
+            if self.atlas.insert(key, &bitmap).is_none() {
+                return Err(DrawError::AtlasFull);
+            }
+        }
+        self.quads.push(Quad {
+            key,
+            pen: [pos[0].round(), pos[1].round()],
+            color: [1.0; 4],
+        });
+        Ok(())
+    }
+
+    /// The size `text` takes up when drawn.
+    pub fn measure(&self, text: &str, style: &TextStyle) -> [f32; 2] {
+        self.layout([0.0, 0.0], text, style, |_, _| {})
+    }
+
+    fn layout<F>(&self, pos: [f32; 2], text: &str, style: &TextStyle, mut emit: F) -> [f32; 2]
+    where
+        F: FnMut(GlyphKey, [f32; 2]),
+    {
+        let primary = self
+            .fonts
+            .primary()
+            .as_scaled(self.fonts.scale(0, style.size));
+        let line_height = primary.height() + primary.line_gap();
+        let size = (style.size * 64.0).round() as u32;
+
+        let mut pen = [pos[0], pos[1] + primary.ascent()];
+        let mut width: f32 = 0.0;
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                pen = [pos[0], pen[1] + line_height];
+            }
+            for glyph in shape::shape_line(&self.fonts, line, style.size) {
+                let key = GlyphKey {
+                    font: glyph.font,
+                    glyph: glyph.glyph,
+                    size,
+                };
+                let at = [pen[0] + glyph.offset[0], pen[1] + glyph.offset[1]];
+                emit(key, [at[0].round(), at[1].round()]);
+                pen[0] += glyph.advance;
+            }
+            width = width.max(pen[0] - pos[0]);
+        }
+        [width, pen[1] - primary.descent() - pos[1]]
+    }
+
+    /// Records the text queued this frame into `builder`, which must be inside the render pass of
+    /// `target`. The returned future uploads the glyph atlas and has to complete before the commands execute.
+    pub fn render(
+        &mut self,
+        vk: &VkSession,
+        target: &Target,
+        builder: AutoCommandBufferBuilder,
+        shader_dir: Option<&Path>,
+    ) -> Result<(AutoCommandBufferBuilder, Option<Box<dyn GpuFuture>>), DrawError> {
+        if self.quads.is_empty() {
+            return Ok((builder, None));
+        }
+        let pipeline = match &self.pipeline {
+            Some(pipeline) => pipeline.clone(),
+            None => {
+                let pipeline = build_pipeline(vk, target.render_pass.clone(), shader_dir)?;
+                self.pipeline = Some(pipeline.clone());
+                pipeline
+            }
+        };
+
+        let mut upload = None;
+        let dirty = self.atlas.take_dirty();
+        let texture = match &self.texture {
+            Some(texture) if !dirty => texture.clone(),
+            _ => {
+                let [width, height] = self.atlas.size();
+                let (image, future) = ImmutableImage::from_iter(
+                    self.atlas.pixels().iter().cloned(),
+                    Dimensions::Dim2d { width, height },
+                    Format::R8G8B8A8Unorm,
+                    vk.queue.clone(),
+                )
+                .map_err(frame_error)?;
+                let sampler = match &self.sampler {
+                    Some(sampler) => sampler.clone(),
+                    None => {
+                        let sampler = Sampler::new(
+                            vk.device.clone(),
+                            Filter::Linear,
+                            Filter::Linear,
+                            MipmapMode::Nearest,
+                            SamplerAddressMode::ClampToEdge,
+                            SamplerAddressMode::ClampToEdge,
+                            SamplerAddressMode::ClampToEdge,
+                            0.0,
+                            1.0,
+                            0.0,
+                            0.0,
+                        )
+                        .map_err(frame_error)?;
+                        self.sampler = Some(sampler.clone());
+                        sampler
+                    }
+                };
+                let set = PersistentDescriptorSet::start(pipeline.clone(), 0)
+                    .add_image(image)
+                    .map_err(frame_error)?
+                    .add_sampler(sampler)
+                    .map_err(frame_error)?
+                    .build()
+                    .map_err(frame_error)?;
+                let texture = Arc::new(set) as Arc<dyn DescriptorSet + Send + Sync>;
+                self.texture = Some(texture.clone());
+                upload = Some(Box::new(future) as Box<dyn GpuFuture>);
+                texture
+            }
+        };
+
+        let [screen_width, screen_height] = target.swapchain.dimensions();
+        let ndc = |x: f32, y: f32| {
+            [
+                x / screen_width as f32 * 2.0 - 1.0,
+                y / screen_height as f32 * 2.0 - 1.0,
+            ]
+        };
+        let [atlas_width, atlas_height] = self.atlas.size();
+        let uv = |x: u32, y: u32| {
+            [
+                x as f32 / atlas_width as f32,
+                y as f32 / atlas_height as f32,
+            ]
+        };
+
+        let mut vertices = Vec::with_capacity(self.quads.len() * 6);
+        for quad in &self.quads {
+            let entry = match self.atlas.get(&quad.key) {
+                Some(entry) if entry.width > 0 && entry.height > 0 => entry,
+                _ => continue,
+            };
+            let left = quad.pen[0] + entry.bearing[0];
+            let top = quad.pen[1] + entry.bearing[1];
+            let (right, bottom) = (left + entry.width as f32, top + entry.height as f32);
+            let (u0, u1) = (entry.x, entry.x + entry.width);
+            let (v0, v1) = (entry.y, entry.y + entry.height);
+
+            // Colored glyphs keep their own colors and only take the alpha of the style
+            let color = if entry.colored {
+                [1.0, 1.0, 1.0, quad.color[3]]
+            } else {
+                quad.color
+            };
+            let corner = |x, y, u, v| TextVertex {
+                position: ndc(x, y),
+                tex_coords: uv(u, v),
+                color,
+            };
+            vertices.extend_from_slice(&[
+                corner(left, top, u0, v0),
+                corner(right, top, u1, v0),
+                corner(left, bottom, u0, v1),
+                corner(left, bottom, u0, v1),
+                corner(right, top, u1, v0),
+                corner(right, bottom, u1, v1),
+            ]);
+        }
+        if vertices.is_empty() {
+            return Ok((builder, upload));
+        }
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            vk.device.clone(),
+            BufferUsage::vertex_buffer(),
+            vertices.into_iter(),
+        )
+        .map_err(frame_error)?;
+        let builder = builder
+            .draw(
+                pipeline,
+                &target.dynamic_state,
+                vec![vertex_buffer],
+                texture,
+                (),
+            )
+            .map_err(frame_error)?;
+
+        Ok((builder, upload))
+    }
+}
+
+impl Measure for TextRenderer {
+    fn measure(&self, text: &str, style: &TextStyle) -> [f32; 2] {
+        TextRenderer::measure(self, text, style)
+    }
+}
+
+fn build_pipeline(
+    vk: &VkSession,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    shader_dir: Option<&Path>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, ShaderError> {
+    let vs = shader::load(&shader::TEXT_VERT, shader_dir, vk.device.clone())?;
+    let fs = shader::load(&shader::TEXT_FRAG, shader_dir, vk.device.clone())?;
+    let subpass = Subpass::from(render_pass, 0)
+        .ok_or_else(|| ShaderError::Pipeline(String::from("the render pass has no subpass")))?;
+
+    Ok(Arc::new(
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<TextVertex>()
+            .vertex_shader(vs.vertex_entry::<TextVertex>()?, ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.fragment_entry()?, ())
+            .blend_alpha_blending()
+            .render_pass(subpass)
+            .build(vk.device.clone())
+            .map_err(|e| ShaderError::Pipeline(e.to_string()))?,
+    ))
+}
+
+// Whitespace has no outline and results in an empty bitmap.
+fn rasterize(fonts: &FontSet, key: GlyphKey) -> Bitmap {
+    let size = key.size as f32 / 64.0;
+    let font = fonts.get(key.font);
+    if let Some(bitmap) = font
+        .glyph_raster_image2(GlyphId(key.glyph), size.round() as u16)
+        .and_then(|image| rasterize_image(image, size))
+    {
+        return bitmap;
+    }
+
+    let glyph =
+        GlyphId(key.glyph).with_scale_and_position(fonts.scale(key.font, size), point(0.0, 0.0));
+    let outline = match font.outline_glyph(glyph) {
+        Some(outline) => outline,
+        None => {
+            return Bitmap {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+                bearing: [0.0, 0.0],
+                colored: false,
+            }
+        }
+    };
+
+    let bounds = outline.px_bounds();
+    let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+    let mut pixels = vec![255; (width * height) as usize * 4];
+    for alpha in pixels.chunks_exact_mut(4).map(|p| &mut p[3]) {
+        *alpha = 0;
+    }
+    outline.draw(|x, y, coverage| {
+        if x < width && y < height {
+            pixels[(y * width + x) as usize * 4 + 3] = (coverage * 255.0).round() as u8;
+        }
+    });
+    Bitmap {
+        width,
+        height,
+        pixels,
+        bearing: [bounds.min.x, bounds.min.y],
+        colored: false,
+    }
+}
+
+// Color emoji fonts such as Noto Color Emoji store their glyphs as PNG strikes at a fixed size,
+// which are scaled down to the requested size here.
+fn rasterize_image(image: GlyphImage, size: f32) -> Option<Bitmap> {
+    if !matches!(image.format, GlyphImageFormat::Png) {
+        return None;
+    }
+    let (rgba, [png_width, png_height]) = decode_png(image.data)?;
+
+    let scale = size / image.pixels_per_em as f32;
+    let width = ((png_width as f32 * scale).round() as u32).max(1);
+    let height = ((png_height as f32 * scale).round() as u32).max(1);
+    Some(Bitmap {
+        width,
+        height,
+        pixels: resize(&rgba, [png_width, png_height], [width, height]),
+        bearing: [
+            image.origin.x * scale,
+            -(image.origin.y + png_height as f32) * scale,
+        ],
+        colored: true,
+    })
+}
+
+// Only PNG icons are supported, anything else results in an empty bitmap.
+fn load_icon(path: &Path, [width, height]: [u32; 2]) -> Bitmap {
+    let decoded = fs::read(path).ok().and_then(|data| decode_png(&data));
+    let pixels = match decoded {
+        Some((rgba, size)) if width > 0 && height > 0 => resize(&rgba, size, [width, height]),
+        _ => {
+            eprintln!("Could not load icon {}", path.display());
+            return Bitmap {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+                bearing: [0.0, 0.0],
+                colored: true,
+            };
+        }
+    };
+    Bitmap {
+        width,
+        height,
+        pixels,
+        bearing: [0.0, 0.0],
+        colored: true,
+    }
+}
+
+fn decode_png(data: &[u8]) -> Option<(Vec<u8>, [u32; 2])> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().ok()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).ok()?;
+    let rgba = to_rgba(&data[..info.buffer_size()], info.color_type)?;
+    Some((rgba, [info.width, info.height]))
+}
+
+fn to_rgba(data: &[u8], color_type: png::ColorType) -> Option<Vec<u8>> {
+    Some(match color_type {
+        png::ColorType::Rgba => data.to_vec(),
+        png::ColorType::Rgb => data
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => data.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return None,
+    })
+}
+
+// Box filter, averaging every source pixel that falls inside a destination pixel. Colors are
+// weighted by alpha so transparent edges don't darken. Enlarging picks the nearest pixel.
+fn resize(src: &[u8], [sw, sh]: [u32; 2], [dw, dh]: [u32; 2]) -> Vec<u8> {
+    let mut out = Vec::with_capacity((dw * dh) as usize * 4);
+    for y in 0..dh {
+        let (y0, y1) = (y * sh / dh, ((y + 1) * sh / dh).max(y * sh / dh + 1));
+        for x in 0..dw {
+            let (x0, x1) = (x * sw / dw, ((x + 1) * sw / dw).max(x * sw / dw + 1));
+            let mut sum = [0u32; 4];
+            for sy in y0..y1.min(sh) {
+                for sx in x0..x1.min(sw) {
+                    let p = &src[(sy * sw + sx) as usize * 4..][..4];
+                    let a = p[3] as u32;
+                    sum[0] += p[0] as u32 * a;
+                    sum[1] += p[1] as u32 * a;
+                    sum[2] += p[2] as u32 * a;
+                    sum[3] += a;
+                }
+            }
+            let count = ((y1.min(sh) - y0) * (x1.min(sw) - x0)).max(1);
+            let alpha = sum[3].max(1);
+            out.extend_from_slice(&[
+                (sum[0] / alpha) as u8,
+                (sum[1] / alpha) as u8,
+                (sum[2] / alpha) as u8,
+                (sum[3] / count) as u8,
+            ]);
+        }
+    }
+    out
+}
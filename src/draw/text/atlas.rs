@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+// Empty pixels kept around each glyph so linear filtering never samples a neighbour
+const PADDING: u32 = 1;
+const BYTES_PER_PIXEL: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    pub font: usize,
+    pub glyph: u16,
+    /// Pixel size in 1/64ths.
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Glyph(GlyphKey),
+    /// An image file, by its index in the renderer's list of icon paths, scaled to this size.
+    Icon {
+        icon: usize,
+        size: [u32; 2],
+    },
+}
+
+/// Where a glyph lives in the atlas, and how far from the pen position its top-left corner is.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bearing: [f32; 2],
+    pub colored: bool,
+    last_used: u64,
+}
+
+/// RGBA pixels. Outline glyphs are white with coverage in the alpha channel, so they can be
+/// tinted, while `colored` glyphs such as emoji are drawn as they are.
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub bearing: [f32; 2],
+    pub colored: bool,
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    used: u32,
+}
+
+/// An RGBA texture that glyphs are packed into row by row. When it fills up, every glyph
+/// not used during the current frame is evicted and the remaining ones are packed again, and if
+/// that isn't enough it doubles in size up to `max_size`.
+pub struct Atlas {
+    width: u32,
+    height: u32,
+    max_size: u32,
+    pixels: Vec<u8>,
+    shelves: Vec<Shelf>,
+    entries: HashMap<Key, Entry>,
+    frame: u64,
+    dirty: bool,
+}
+
+impl Atlas {
+    pub fn new(width: u32, height: u32, max_size: u32) -> Atlas {
+        Atlas {
+            width,
+            height,
+            max_size,
+            pixels: vec![0; width as usize * height as usize * BYTES_PER_PIXEL],
+            shelves: Vec::new(),
+            entries: HashMap::new(),
+            frame: 0,
+            dirty: true,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Whether the pixels changed since the last call, meaning the texture must be uploaded again.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn get(&mut self, key: &Key) -> Option<Entry> {
+        let frame = self.frame;
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_used = frame;
+            *entry
+        })
+    }
+
+    /// Returns None if the bitmap doesn't fit even after evicting everything unused and growing
+    /// to the largest size.
+    pub fn insert(&mut self, key: Key, bitmap: &Bitmap) -> Option<Entry> {
+        let (x, y) = match self.allocate(bitmap.width, bitmap.height) {
+            Some(pos) => pos,
+            None => {
+                self.repack(self.width, self.height);
+                loop {
+                    if let Some(pos) = self.allocate(bitmap.width, bitmap.height) {
+                        break pos;
+                    }
+                    if self.width >= self.max_size && self.height >= self.max_size {
+                        return None;
+                    }
+                    let max_size = self.max_size;
+                    let grown = |size: u32| (size * 2).min(max_size).max(size);
+                    self.repack(grown(self.width), grown(self.height));
+                }
+            }
+        };
+        let entry = Entry {
+            x,
+            y,
+            width: bitmap.width,
+            height: bitmap.height,
+            bearing: bitmap.bearing,
+            colored: bitmap.colored,
+            last_used: self.frame,
+        };
+        let row_len = bitmap.width as usize * BYTES_PER_PIXEL;
+        for row in 0..bitmap.height {
+            let src = row as usize * row_len;
+            let dst = offset(self.width, x, y + row);
+            self.pixels[dst..dst + row_len].copy_from_slice(&bitmap.pixels[src..src + row_len]);
+        }
+        self.dirty = true;
+        self.entries.insert(key, entry);
+        Some(entry)
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 {
+            return Some((0, 0));
+        }
+        let (width, height) = (width + PADDING, height + PADDING);
+        if width > self.width {
+            return None;
+        }
+
+        let atlas_width = self.width;
+        let best = self
+            .shelves
+            .iter_mut()
+            .filter(|s| s.height >= height && s.height <= height * 2)
+            .filter(|s| s.used + width <= atlas_width)
+            .min_by_key(|s| s.height);
+        if let Some(shelf) = best {
+            let x = shelf.used;
+            shelf.used += width;
+            return Some((x, shelf.y));
+        }
+
+        let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+        if y + height > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            used: width,
+        });
+        Some((0, y))
+    }
+
+    // Resizes the atlas to `width` by `height`, dropping every glyph not used during the current
+    // frame and packing the rest again.
+    fn repack(&mut self, width: u32, height: u32) {
+        let frame = self.frame;
+        let blank = vec![0; width as usize * height as usize * BYTES_PER_PIXEL];
+        let old = std::mem::replace(&mut self.pixels, blank);
+        let old_width = std::mem::replace(&mut self.width, width);
+        self.height = height;
+        let mut kept = self
+            .entries
+            .drain()
+            .filter(|(_, entry)| entry.last_used == frame)
+            .collect::<Vec<_>>();
+        kept.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.height));
+        self.shelves.clear();
+
+        for (key, entry) in kept {
+            let (x, y) = match self.allocate(entry.width, entry.height) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let row_len = entry.width as usize * BYTES_PER_PIXEL;
+            for row in 0..entry.height {
+                let src = offset(old_width, entry.x, entry.y + row);
+                let dst = offset(self.width, x, y + row);
+                self.pixels[dst..dst + row_len].copy_from_slice(&old[src..src + row_len]);
+            }
+            self.entries.insert(key, Entry { x, y, ..entry });
+        }
+        self.dirty = true;
+    }
+}
+
+// Where the pixel at `x`, `y` starts in the pixels of an atlas `width` pixels wide.
+fn offset(width: u32, x: u32, y: u32) -> usize {
+    (y as usize * width as usize + x as usize) * BYTES_PER_PIXEL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(glyph: u16) -> Key {
+        Key::Glyph(GlyphKey {
+            font: 0,
+            glyph,
+            size: 0,
+        })
+    }
+
+    // A square bitmap filled with `value`, taking up 4 by 4 pixels of the atlas with padding.
+    fn bitmap(value: u8) -> Bitmap {
+        Bitmap {
+            width: 3,
+            height: 3,
+            pixels: vec![value; 3 * 3 * BYTES_PER_PIXEL],
+            bearing: [0.0, 0.0],
+            colored: false,
+        }
+    }
+
+    fn pixel(atlas: &mut Atlas, key: Key) -> Option<u8> {
+        let entry = atlas.get(&key)?;
+        Some(atlas.pixels()[offset(atlas.width, entry.x, entry.y)])
+    }
+
+    #[test]
+    fn evicts_unused() {
+        let mut atlas = Atlas::new(8, 8, 16);
+        for i in 0..4 {
+            assert!(atlas.insert(glyph(i), &bitmap(i as u8 + 1)).is_some());
+        }
+        atlas.begin_frame();
+        assert_eq!(pixel(&mut atlas, glyph(3)), Some(4));
+        assert!(atlas.insert(glyph(4), &bitmap(5)).is_some());
+
+        assert_eq!(atlas.size(), [8, 8]);
+        assert_eq!(pixel(&mut atlas, glyph(0)), None);
+        assert_eq!(pixel(&mut atlas, glyph(3)), Some(4));
+        assert_eq!(pixel(&mut atlas, glyph(4)), Some(5));
+    }
+
+    #[test]
+    fn grows_when_everything_is_used() {
+        let mut atlas = Atlas::new(8, 8, 16);
+        for i in 0..5 {
+            assert!(atlas.insert(glyph(i), &bitmap(i as u8 + 1)).is_some());
+        }
+        assert!(atlas.take_dirty());
+
+        assert_eq!(atlas.size(), [16, 16]);
+        assert_eq!(atlas.pixels().len(), 16 * 16 * BYTES_PER_PIXEL);
+        for i in 0..5 {
+            assert_eq!(pixel(&mut atlas, glyph(i)), Some(i as u8 + 1));
+        }
+    }
+
+    #[test]
+    fn full_at_the_largest_size() {
+        let mut atlas = Atlas::new(8, 8, 8);
+        for i in 0..4 {
+            assert!(atlas.insert(glyph(i), &bitmap(1)).is_some());
+        }
+        assert!(atlas.insert(glyph(4), &bitmap(1)).is_none());
+        assert_eq!(atlas.size(), [8, 8]);
+
+        let wide = Bitmap {
+            width: 8,
+            height: 1,
+            pixels: vec![1; 8 * BYTES_PER_PIXEL],
+            bearing: [0.0, 0.0],
+            colored: false,
+        };
+        assert!(Atlas::new(8, 8, 8).insert(glyph(0), &wide).is_none());
+    }
+}
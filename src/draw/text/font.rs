@@ -0,0 +1,226 @@
+use crate::config::FontConfig;
+use ab_glyph::{Font as _, FontRef, GlyphId, PxScale};
+use rustybuzz::Face;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+#[derive(Debug)]
+pub enum FontError {
+    NotFound(String),
+    Io(PathBuf, io::Error),
+    Invalid(PathBuf),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::NotFound(family) => write!(f, "no font found for `{}`", family),
+            FontError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            FontError::Invalid(path) => write!(f, "{} is not a font file", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+pub struct Font {
+    pub path: PathBuf,
+    pub font: FontRef<'static>,
+    /// The same font parsed for shaping, once rather than for every run.
+    pub face: Face<'static>,
+}
+
+impl Font {
+    pub fn open(path: &Path) -> Result<Font, FontError> {
+        let data = fs::read(path).map_err(|e| FontError::Io(path.to_owned(), e))?;
+        // Fonts are used until the launcher exits, so the data is leaked for both parsers to
+        // borrow
+        let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+        let invalid = || FontError::Invalid(path.to_owned());
+        Ok(Font {
+            path: path.to_owned(),
+            font: FontRef::try_from_slice(data).map_err(|_| invalid())?,
+            face: Face::from_slice(data, 0).ok_or_else(invalid)?,
+        })
+    }
+}
+
+/// The configured font followed by its fallbacks, in the order glyphs are looked up in.
+pub struct FontSet {
+    fonts: Vec<Font>,
+}
+
+impl FontSet {
+    pub fn load(config: &FontConfig) -> Result<FontSet, FontError> {
+        let primary = match &config.path {
+            Some(path) => path.clone(),
+            None => {
+                find(&config.family).ok_or_else(|| FontError::NotFound(config.family.clone()))?
+            }
+        };
+        let mut fonts = vec![Font::open(&primary)?];
+
+        for family in &config.fallback {
+            match find(family).ok_or_else(|| FontError::NotFound(family.clone())) {
+                Ok(path) if fonts.iter().any(|f| f.path == path) => {}
+                Ok(path) => match Font::open(&path) {
+                    Ok(font) => fonts.push(font),
+                    Err(e) => eprintln!("Skipping fallback font: {}", e),
+                },
+                Err(e) => eprintln!("Skipping fallback font: {}", e),
+            }
+        }
+
+        Ok(FontSet { fonts })
+    }
+
+    pub fn get(&self, index: usize) -> &FontRef<'static> {
+        &self.fonts[index].font
+    }
+
+    pub fn face(&self, index: usize) -> &Face<'static> {
+        &self.fonts[index].face
+    }
+
+    pub fn primary(&self) -> &FontRef<'static> {
+        self.get(0)
+    }
+
+    /// The scale to draw a font at for an em of `size` pixels, which is what shaping positions
+    /// glyphs for. `PxScale` itself is the height from descent to ascent, which differs per font.
+    pub fn scale(&self, index: usize, size: f32) -> PxScale {
+        let font = self.get(index);
+        let units_per_em = font.units_per_em().unwrap_or(1000.0);
+        PxScale::from(size * font.height_unscaled() / units_per_em)
+    }
+
+    /// The first font that has a glyph for `c`, or the primary font's missing glyph.
+    pub fn lookup(&self, c: char) -> (usize, GlyphId) {
+        self.fonts
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (i, f.font.glyph_id(c)))
+            .find(|(_, id)| id.0 != 0)
+            .unwrap_or((0, GlyphId(0)))
+    }
+
+    /// The first font that can draw every character of a grapheme cluster, so that base
+    /// characters and their combining marks are shaped together.
+    pub fn covering(&self, grapheme: &str) -> usize {
+        let visible = || grapheme.chars().filter(|c| !is_default_ignorable(*c));
+        self.fonts
+            .iter()
+            .position(|f| visible().all(|c| f.font.glyph_id(c).0 != 0))
+            .or_else(|| visible().next().map(|c| self.lookup(c).0))
+            .unwrap_or(0)
+    }
+}
+
+// Joiners and variation selectors are consumed by shaping and need no glyph of their own
+fn is_default_ignorable(c: char) -> bool {
+    matches!(c, '\u{200b}'..='\u{200f}' | '\u{fe00}'..='\u{fe0f}' | '\u{e0100}'..='\u{e01ef}')
+}
+
+/// Resolves a family name such as `monospace` or `DejaVu Sans` to a font file. Defers to
+/// fontconfig when it is installed, otherwise falls back to matching file names in the XDG font
+/// directories.
+pub fn find(family: &str) -> Option<PathBuf> {
+    fc_match(family).or_else(|| scan(family))
+}
+
+fn fc_match(family: &str) -> Option<PathBuf> {
+    let output = Command::new("fc-match")
+        .arg("--format=%{file}")
+        .arg(family)
+        .output()
+        .ok()?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return None;
+    }
+    let path = PathBuf::from(String::from_utf8(output.stdout).ok()?);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn scan(family: &str) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    for dir in font_dirs() {
+        collect(&dir, &mut candidates);
+    }
+    let stem = |path: &PathBuf| Some(normalize(&path.file_stem()?.to_string_lossy()));
+    let candidates = candidates
+        .into_iter()
+        .filter_map(|path| Some((stem(&path)?, path)))
+        .collect::<Vec<_>>();
+
+    let families = match normalize(family).as_str() {
+        "monospace" | "mono" => vec!["dejavusansmono", "liberationmono", "notosansmono"],
+        "sansserif" | "sans" => vec!["dejavusans", "liberationsans", "notosans"],
+        "serif" => vec!["dejavuserif", "liberationserif", "notoserif"],
+        _ => vec![family],
+    };
+    families.into_iter().find_map(|family| {
+        let wanted = normalize(family);
+        candidates
+            .iter()
+            .filter(|(stem, _)| stem.starts_with(&wanted))
+            // Prefer `DejaVuSans-Regular` and `DejaVuSans` over `DejaVuSans-BoldOblique`
+            .min_by_key(|(stem, _)| {
+                let style = &stem[wanted.len()..];
+                (style != "regular" && !style.is_empty(), style.len())
+            })
+            .map(|(_, path)| path.clone())
+    })
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(data) => dirs.push(PathBuf::from(data).join("fonts")),
+        None => dirs.extend(home.iter().map(|h| h.join(".local/share/fonts"))),
+    }
+    dirs.extend(home.iter().map(|h| h.join(".fonts")));
+    let system = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+    dirs.extend(system.split(':').map(|dir| Path::new(dir).join("fonts")));
+    dirs
+}
+
+fn collect(dir: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            collect(&path, out);
+        } else if is_font(&path) {
+            out.push(path);
+        }
+    }
+}
+
+fn is_font(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
@@ -0,0 +1,280 @@
+use super::font::FontSet;
+use ab_glyph::Font as _;
+use rustybuzz::{Direction, UnicodeBuffer};
+use std::ops::Range;
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapedGlyph {
+    pub font: usize,
+    pub glyph: u16,
+    /// Byte offset of the first character this glyph was shaped from.
+    pub cluster: usize,
+    pub advance: f32,
+    pub offset: [f32; 2],
+}
+
+/// Shapes a single line of text with an em of `size` pixels, returning glyphs in the left to right order they
+/// are drawn in. Right to left runs are reordered according to the unicode bidi algorithm.
+pub fn shape_line(fonts: &FontSet, line: &str, size: f32) -> Vec<ShapedGlyph> {
+    let mut glyphs = Vec::new();
+    let bidi = BidiInfo::new(line, None);
+
+    for paragraph in &bidi.paragraphs {
+        let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+        for run in runs {
+            let rtl = levels[run.start].is_rtl();
+            let mut font_runs = split_by_font(fonts, line, run);
+            if rtl {
+                font_runs.reverse();
+            }
+            for (font, range) in font_runs {
+                shape_run(fonts, font, line, range, rtl, size, &mut glyphs);
+            }
+        }
+    }
+
+    glyphs
+}
+
+// Splits a run of equal direction into the longest runs of grapheme clusters that the same font
+// can draw.
+fn split_by_font(fonts: &FontSet, text: &str, run: Range<usize>) -> Vec<(usize, Range<usize>)> {
+    let mut runs: Vec<(usize, Range<usize>)> = Vec::new();
+    for (offset, grapheme) in text[run.clone()].grapheme_indices(true) {
+        let range = run.start + offset..run.start + offset + grapheme.len();
+        let font = fonts.covering(grapheme);
+        match runs.last_mut() {
+            Some((last, last_range)) if *last == font => last_range.end = range.end,
+            _ => runs.push((font, range)),
+        }
+    }
+    runs
+}
+
+fn shape_run(
+    fonts: &FontSet,
+    font: usize,
+    text: &str,
+    range: Range<usize>,
+    rtl: bool,
+    size: f32,
+    out: &mut Vec<ShapedGlyph>,
+) {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(&text[range.clone()]);
+    buffer.set_direction(if rtl {
+        Direction::RightToLeft
+    } else {
+        Direction::LeftToRight
+    });
+    buffer.guess_segment_properties();
+
+    let shaped = rustybuzz::shape(fonts.face(font), &[], buffer);
+    let scale = size / fonts.get(font).units_per_em().unwrap_or(1000.0);
+    for (info, position) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+        out.push(ShapedGlyph {
+            font,
+            glyph: info.glyph_id as u16,
+            cluster: range.start + info.cluster as usize,
+            advance: position.x_advance as f32 * scale,
+            offset: [
+                position.x_offset as f32 * scale,
+                -position.y_offset as f32 * scale,
+            ],
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FontConfig;
+    use ab_glyph::{GlyphId, ScaleFont};
+
+    const SIZE: f32 = 20.0;
+
+    // `families[0]` with the rest as its fallbacks, or `None` when the last of them isn't
+    // installed, which is told by it being the first to have `probe`. Fixtures for scripts that
+    // a system has no font for are skipped.
+    fn load(families: &[&str], probe: char) -> Option<FontSet> {
+        let fonts = FontSet::load(&FontConfig {
+            family: families[0].to_owned(),
+            fallback: families[1..].iter().map(|f| f.to_string()).collect(),
+            ..FontConfig::default()
+        })
+        .ok()?;
+        match fonts.lookup(probe) {
+            (font, id) if font == families.len() - 1 && id.0 != 0 => Some(fonts),
+            _ => {
+                eprintln!(
+                    "Skipping fixture, {} is not installed",
+                    families.last().unwrap()
+                );
+                None
+            }
+        }
+    }
+
+    fn id(fonts: &FontSet, font: usize, c: char) -> u16 {
+        fonts.get(font).glyph_id(c).0
+    }
+
+    // What the rasterizer advances by, which shaping has to agree with.
+    fn advance(fonts: &FontSet, font: usize, glyph: u16) -> f32 {
+        let scaled = fonts.get(font).as_scaled(fonts.scale(font, SIZE));
+        scaled.h_advance(GlyphId(glyph))
+    }
+
+    // Compares the shaped glyphs of `text` to `expected` triples of font, glyph and cluster.
+    // Glyphs in `marks` have no advance, the others advance as much as the rasterizer says.
+    fn check(fonts: &FontSet, text: &str, expected: &[(usize, u16, usize)], marks: &[u16]) {
+        let shaped = shape_line(fonts, text, SIZE);
+        let glyphs = shaped
+            .iter()
+            .map(|g| (g.font, g.glyph, g.cluster))
+            .collect::<Vec<_>>();
+        assert_eq!(glyphs, expected, "glyphs of {:?}", text);
+        for glyph in &shaped {
+            let expected = match marks.contains(&glyph.glyph) {
+                true => 0.0,
+                false => advance(fonts, glyph.font, glyph.glyph),
+            };
+            assert!(
+                (glyph.advance - expected).abs() < 1e-3,
+                "advance of glyph {} in {:?} is {}, expected {}",
+                glyph.glyph,
+                text,
+                glyph.advance,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn latin() {
+        let fonts = match load(&["DejaVu Sans"], 'é') {
+            Some(fonts) => fonts,
+            None => return,
+        };
+        let [h, e, l, o] = ['H', 'e', 'l', 'o'].map(|c| id(&fonts, 0, c));
+        check(
+            &fonts,
+            "Hello",
+            &[(0, h, 0), (0, e, 1), (0, l, 2), (0, l, 3), (0, o, 4)],
+            &[],
+        );
+        // The combining acute accent is composed into the precomposed glyph
+        check(&fonts, "e\u{301}", &[(0, id(&fonts, 0, 'é'), 0)], &[]);
+    }
+
+    #[test]
+    fn arabic() {
+        let fonts = match load(&["DejaVu Sans"], 'س') {
+            Some(fonts) => fonts,
+            None => return,
+        };
+        // Drawn right to left in their joining forms, meem isolated, the lam-alef ligature final
+        // and seen initial, as the presentation forms in the font's character map
+        let meem = id(&fonts, 0, 'م');
+        let lam_alef = id(&fonts, 0, '\u{fefc}');
+        let seen = id(&fonts, 0, '\u{feb3}');
+        check(
+            &fonts,
+            "سلام",
+            &[(0, meem, 6), (0, lam_alef, 2), (0, seen, 0)],
+            &[],
+        );
+
+        let fatha = id(&fonts, 0, '\u{64e}');
+        check(
+            &fonts,
+            "ق\u{64e}",
+            &[(0, fatha, 0), (0, id(&fonts, 0, 'ق'), 0)],
+            &[fatha],
+        );
+
+        // Left to right text around it stays in its own order
+        let shaped = shape_line(&fonts, "ab سلام cd", SIZE);
+        let clusters = shaped.iter().map(|g| g.cluster).collect::<Vec<_>>();
+        assert_eq!(clusters, [0, 1, 2, 9, 5, 3, 11, 12, 13]);
+    }
+
+    #[test]
+    fn devanagari() {
+        let fonts = match load(&["DejaVu Sans", "Noto Sans Devanagari"], 'क') {
+            Some(fonts) => fonts,
+            None => return,
+        };
+        let ka = id(&fonts, 1, 'क');
+        // The vowel sign i is drawn before the consonant it follows, in one cluster with it
+        let shaped = shape_line(&fonts, "कि", SIZE);
+        assert_eq!(shaped.len(), 2);
+        assert_ne!(shaped[0].glyph, ka);
+        assert_eq!(shaped[1].glyph, ka);
+        for glyph in &shaped {
+            assert_eq!((glyph.font, glyph.cluster), (1, 0));
+            assert!((glyph.advance - advance(&fonts, 1, glyph.glyph)).abs() < 1e-3);
+        }
+
+        // Combining marks stay with their base in the Devanagari font
+        let anusvara = id(&fonts, 1, '\u{902}');
+        check(&fonts, "कं", &[(1, ka, 0), (1, anusvara, 0)], &[]);
+        // Followed by latin text from the primary font
+        check(
+            &fonts,
+            "कं a",
+            &[
+                (1, ka, 0),
+                (1, anusvara, 0),
+                (0, id(&fonts, 0, ' '), 6),
+                (0, id(&fonts, 0, 'a'), 7),
+            ],
+            &[],
+        );
+    }
+
+    #[test]
+    fn cjk() {
+        let fonts = match load(&["DejaVu Sans", "Noto Sans CJK JP"], '漢') {
+            Some(fonts) => fonts,
+            None => return,
+        };
+        let [kan, ji, ka] = ['漢', '字', 'か'].map(|c| id(&fonts, 1, c));
+        check(
+            &fonts,
+            "漢字か",
+            &[(1, kan, 0), (1, ji, 3), (1, ka, 6)],
+            &[],
+        );
+        // Full width, taking up the em square
+        for glyph in shape_line(&fonts, "漢字か", SIZE) {
+            assert!((glyph.advance - SIZE).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn emoji() {
+        let fonts = match load(&["DejaVu Sans", "Noto Color Emoji"], '👍') {
+            Some(fonts) => fonts,
+            None => return,
+        };
+        // Modifiers and joined sequences become a single glyph from the emoji font
+        for sequence in &["👍🏽", "👩\u{200d}💻", "🏳\u{fe0f}\u{200d}🌈"] {
+            let shaped = shape_line(&fonts, sequence, SIZE);
+            assert_eq!(shaped.len(), 1, "glyphs of {:?}", sequence);
+            assert_eq!((shaped[0].font, shaped[0].cluster), (1, 0));
+            assert_ne!(shaped[0].glyph, id(&fonts, 1, '👍'));
+            check(&fonts, sequence, &[(1, shaped[0].glyph, 0)], &[]);
+        }
+
+        let thumb = id(&fonts, 1, '👍');
+        check(
+            &fonts,
+            "a👍",
+            &[(0, id(&fonts, 0, 'a'), 0), (1, thumb, 1)],
+            &[],
+        );
+    }
+}
@@ -1,182 +1,501 @@
-use super::window::Window;
-use std::borrow::Borrow;
-use std::marker::PhantomData;
-use std::rc::Rc;
+use super::window::LayerSurface;
+use crate::config::{GpuConfig, GpuSelector};
+use std::cmp::Reverse;
+use std::ffi::c_void;
+use std::fmt;
 use std::sync::Arc;
 use vulkano::command_buffer::DynamicState;
-use vulkano::device::{Device, DeviceExtensions, Queue};
-use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract};
+use vulkano::device::{Device, DeviceCreationError, DeviceExtensions, Queue};
+use vulkano::format::Format;
+use vulkano::framebuffer::{
+    Framebuffer, FramebufferAbstract, FramebufferCreationError, RenderPassAbstract,
+    RenderPassCreationError,
+};
 use vulkano::image;
-use vulkano::instance::{Instance, InstanceCreationError, InstanceExtensions, PhysicalDevice};
+use vulkano::instance::{
+    Instance, InstanceCreationError, InstanceExtensions, PhysicalDevice, PhysicalDeviceType,
+    QueueFamily,
+};
 use vulkano::pipeline::viewport::Viewport;
 use vulkano::swapchain::{
-    ColorSpace, PresentMode, Surface, SurfaceCreationError, SurfaceTransform, Swapchain,
+    CapabilitiesError, ColorSpace, PresentMode, Surface, SurfaceCreationError, SurfaceTransform,
+    Swapchain, SwapchainCreationError,
 };
+use vulkano::VulkanObject;
+use wayland_client::Display;
 
-const WIDTH: u32 = 500;
-const HEIGHT: u32 = 500;
-
+/// The device everything is drawn with. The render pass is made along with the first target,
+/// since it depends on the formats the surfaces support, and is shared by all of them so that
+/// every pipeline works with every target.
 pub struct VkSession {
     pub device: Arc<Device>,
     pub instance: Arc<Instance>,
     pub queue: Arc<Queue>,
-    pub draw_surface: Arc<Surface<Window>>,
-    pub swapchain: Arc<Swapchain<Window>>,
-    pub images: Vec<Arc<image::SwapchainImage<Window>>>,
+    /// Index of the physical device.
+    physical: usize,
+    render_pass: Option<(Format, Arc<dyn RenderPassAbstract + Send + Sync>)>,
+}
+
+/// A surface to draw to, with its swapchain and everything that refers to the swapchain images.
+pub struct Target {
+    pub surface: Arc<Surface<SurfaceHandle>>,
+    pub swapchain: Arc<Swapchain<SurfaceHandle>>,
+    pub images: Vec<Arc<image::SwapchainImage<SurfaceHandle>>>,
     pub dynamic_state: DynamicState,
+    pub render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    pub framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
 }
 
 #[derive(Debug)]
 pub enum VkSessionError {
-    PhysicalDeviceIdNotFound(usize),
-    Todo(Box<dyn std::error::Error>),
+    /// There is no Vulkan loader, or no driver with Wayland support.
+    NoVulkan(InstanceCreationError),
+    /// No GPU matches `gpu.device`.
+    GpuNotFound(GpuSelector),
+    /// The GPU chosen with `gpu.device` can't be drawn with.
+    UnsuitableGpu {
+        name: String,
+        reason: &'static str,
+    },
+    /// None of the GPUs can be drawn with.
+    NoSuitableGpu,
+    Device(DeviceCreationError),
+    /// The chosen GPU, or the queue it was chosen for, went away while it was being opened.
+    GpuUnavailable,
+    /// The GPU can't present to the Wayland surface.
+    SurfaceUnsupported(String),
+    /// A surface can't be drawn in the format the render pass was made for.
+    UnsupportedFormat(Format),
+    Swapchain(SwapchainCreationError),
+    Framebuffer(FramebufferCreationError),
+    RenderPass(RenderPassCreationError),
+    /// The Vulkan surface of a screen stopped working.
+    SurfaceLost,
+    /// Recording, submitting or presenting a frame failed.
+    Draw(String),
 }
+
+impl fmt::Display for VkSessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VkSessionError::NoVulkan(e) => write!(
+                f,
+                "could not load Vulkan ({}), install the Vulkan loader and a driver for your GPU",
+                e
+            ),
+            VkSessionError::GpuNotFound(selector) => write!(
+                f,
+                "no GPU matches `{}`, `infolauncher --list-gpus` shows the ones there are",
+                selector
+            ),
+            VkSessionError::UnsuitableGpu { name, reason } => write!(
+                f,
+                "{} can't be drawn with since {}, pick another GPU with gpu.device",
+                name, reason
+            ),
+            VkSessionError::NoSuitableGpu => write!(
+                f,
+                "none of the GPUs can be drawn with, `infolauncher --list-gpus` shows why"
+            ),
+            VkSessionError::Device(e) => write!(f, "could not open the GPU: {}", e),
+            VkSessionError::GpuUnavailable => write!(f, "the GPU went away while opening it"),
+            VkSessionError::SurfaceUnsupported(reason) => write!(
+                f,
+                "the GPU can't draw to Wayland surfaces ({}), pick another one with gpu.device",
+                reason
+            ),
+            VkSessionError::UnsupportedFormat(format) => write!(
+                f,
+                "a surface doesn't support {:?}, which the others are drawn in",
+                format
+            ),
+            VkSessionError::Swapchain(e) => write!(f, "could not create a swapchain: {}", e),
+            VkSessionError::Framebuffer(e) => write!(f, "could not create a framebuffer: {}", e),
+            VkSessionError::RenderPass(e) => write!(f, "could not create the render pass: {}", e),
+            VkSessionError::SurfaceLost => write!(f, "lost the surface being drawn to"),
+            VkSessionError::Draw(e) => write!(f, "could not draw a frame: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VkSessionError {}
+
 impl From<InstanceCreationError> for VkSessionError {
     fn from(e: InstanceCreationError) -> Self {
-        Self::Todo(Box::new(e))
+        Self::NoVulkan(e)
     }
 }
 
-pub unsafe trait SafeBorrow<T>: Borrow<T> {}
+impl From<SurfaceCreationError> for VkSessionError {
+    fn from(e: SurfaceCreationError) -> Self {
+        Self::SurfaceUnsupported(e.to_string())
+    }
+}
 
-unsafe impl<T> SafeBorrow<T> for T {}
-unsafe impl<'a, T> SafeBorrow<T> for &'a T {}
-unsafe impl<'a, T> SafeBorrow<T> for &'a mut T {}
-unsafe impl<T> SafeBorrow<T> for Rc<T> {}
-unsafe impl<T> SafeBorrow<T> for Arc<T> {}
-unsafe impl<T> SafeBorrow<T> for Box<T> {}
-pub fn create_vk_surface<W>(
-    window: W,
-    instance: Arc<Instance>,
-) -> Result<Arc<Surface<W>>, SurfaceCreationError>
-where
-    W: SafeBorrow<super::window::Window>,
-{
-    unsafe {
-        Surface::from_wayland(
-            instance,
-            window.borrow().display.c_ptr() as *mut _,
-            window.borrow().surface.as_ref().c_ptr() as *mut _,
-            window,
-        )
+impl From<CapabilitiesError> for VkSessionError {
+    fn from(e: CapabilitiesError) -> Self {
+        Self::SurfaceUnsupported(e.to_string())
+    }
+}
+
+impl From<DeviceCreationError> for VkSessionError {
+    fn from(e: DeviceCreationError) -> Self {
+        Self::Device(e)
+    }
+}
+
+impl From<SwapchainCreationError> for VkSessionError {
+    fn from(e: SwapchainCreationError) -> Self {
+        Self::Swapchain(e)
+    }
+}
+
+impl From<FramebufferCreationError> for VkSessionError {
+    fn from(e: FramebufferCreationError) -> Self {
+        Self::Framebuffer(e)
+    }
+}
+
+impl From<RenderPassCreationError> for VkSessionError {
+    fn from(e: RenderPassCreationError) -> Self {
+        Self::RenderPass(e)
     }
 }
 
-impl<'a> VkSession {
-    pub fn initialize(physical_device_id: usize, window: Window) -> Result<Self, VkSessionError> {
-        let extensions = InstanceExtensions {
-            khr_wayland_surface: true,
-            khr_surface: true,
-            ..InstanceExtensions::none()
+/// A physical device, and whether it can draw to Wayland surfaces.
+#[derive(Debug, Clone)]
+pub struct Gpu {
+    pub index: usize,
+    pub name: String,
+    pub kind: PhysicalDeviceType,
+    pub uuid: String,
+    /// Why it can't be drawn with, if it can't.
+    pub unsuitable: Option<&'static str>,
+    /// Id of the queue family to draw and present with.
+    queue_family: Option<u32>,
+}
+
+impl Gpu {
+    fn probe(physical: PhysicalDevice, display: &Display) -> Gpu {
+        let mut gpu = Gpu {
+            index: physical.index(),
+            name: physical.name().to_string(),
+            kind: physical.ty(),
+            uuid: format_uuid(physical.uuid()),
+            unsuitable: None,
+            queue_family: None,
         };
+        let graphics = || physical.queue_families().filter(|q| q.supports_graphics());
+        if !physical.supported_extensions().khr_swapchain {
+            gpu.unsuitable = Some("it has no swapchains");
+        } else if graphics().next().is_none() {
+            gpu.unsuitable = Some("none of its queues can draw");
+        } else {
+            gpu.queue_family = graphics()
+                .find(|&q| presents_to_wayland(q, display))
+                .map(|q| q.id());
+            if gpu.queue_family.is_none() {
+                gpu.unsuitable = Some("none of its queues can present to Wayland surfaces");
+            }
+        }
+        gpu
+    }
 
-        let instance = Instance::new(None, &extensions, None)?;
-        let physical = PhysicalDevice::from_index(&instance, physical_device_id)
-            .ok_or_else(|| VkSessionError::PhysicalDeviceIdNotFound(physical_device_id))?;
-
-        let queue_family = physical
-            .queue_families()
-            .find(|&q| q.supports_graphics())
-            .expect("Could not find a graphical queue family of physical vulkan device");
-
-        let (device, mut queues) = {
-            Device::new(
-                physical,
-                physical.supported_features(),
-                &DeviceExtensions {
-                    khr_swapchain: true,
-                    ..DeviceExtensions::none()
-                },
-                [(queue_family, 0.5)].iter().cloned(),
-            )
-            .expect("Failed to create device")
+    /// How well the GPU suits drawing the launcher, higher being better, or `None` if it can't
+    /// be drawn with. Integrated GPUs come first, since a bar doesn't need more and they use less
+    /// power.
+    pub fn score(&self) -> Option<u32> {
+        if self.unsuitable.is_some() {
+            return None;
+        }
+        Some(match self.kind {
+            PhysicalDeviceType::IntegratedGpu => 4,
+            PhysicalDeviceType::DiscreteGpu => 3,
+            PhysicalDeviceType::VirtualGpu => 2,
+            PhysicalDeviceType::Cpu => 1,
+            PhysicalDeviceType::Other => 0,
+        })
+    }
+
+    // Whether `selector` is the index of the GPU, part of its name in any case, or its UUID with
+    // or without dashes.
+    fn matches(&self, selector: &GpuSelector) -> bool {
+        match selector {
+            GpuSelector::Index(index) => self.index == *index,
+            GpuSelector::Name(name) => {
+                let name = name.to_lowercase();
+                self.name.to_lowercase().contains(&name)
+                    || self.uuid.replace('-', "") == name.replace('-', "")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Gpu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            PhysicalDeviceType::IntegratedGpu => "integrated",
+            PhysicalDeviceType::DiscreteGpu => "discrete",
+            PhysicalDeviceType::VirtualGpu => "virtual",
+            PhysicalDeviceType::Cpu => "cpu",
+            PhysicalDeviceType::Other => "other",
         };
-        let queue = queues.next().unwrap();
+        write!(f, "{}: {} ({}, {})", self.index, self.name, kind, self.uuid)?;
+        match (self.score(), self.unsuitable) {
+            (Some(score), _) => write!(f, ", score {}", score),
+            (None, Some(reason)) => write!(f, ", unsuitable since {}", reason),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+pub fn create_instance() -> Result<Arc<Instance>, InstanceCreationError> {
+    let extensions = InstanceExtensions {
+        khr_wayland_surface: true,
+        khr_surface: true,
+        ..InstanceExtensions::none()
+    };
+    Instance::new(None, &extensions, None)
+}
+
+/// Every physical device, in the order Vulkan lists them.
+pub fn gpus(instance: &Arc<Instance>, display: &Display) -> Vec<Gpu> {
+    PhysicalDevice::enumerate(instance)
+        .map(|physical| Gpu::probe(physical, display))
+        .collect()
+}
 
-        let vksurface = create_vk_surface(window, instance.clone()).unwrap();
+/// The best suited GPU that `selector` matches, or of all of them if there is no selector.
+pub fn choose<'a>(
+    gpus: &'a [Gpu],
+    selector: Option<&GpuSelector>,
+) -> Result<&'a Gpu, VkSessionError> {
+    let selector = match selector {
+        Some(selector) => selector,
+        None => return best(gpus.iter()).ok_or(VkSessionError::NoSuitableGpu),
+    };
+    let matching = || gpus.iter().filter(move |gpu| gpu.matches(selector));
+    if let Some(gpu) = best(matching()) {
+        return Ok(gpu);
+    }
+    // Every match is unsuitable, and the first one tells why
+    match matching().find_map(|gpu| Some((gpu, gpu.unsuitable?))) {
+        Some((gpu, reason)) => Err(VkSessionError::UnsuitableGpu {
+            name: gpu.name.clone(),
+            reason,
+        }),
+        None => Err(VkSessionError::GpuNotFound(selector.clone())),
+    }
+}
+
+// The first of the best suited of `gpus`, so that the order Vulkan lists them in breaks ties.
+fn best<'a>(gpus: impl Iterator<Item = &'a Gpu>) -> Option<&'a Gpu> {
+    gpus.filter(|gpu| gpu.score().is_some())
+        .min_by_key(|gpu| Reverse(gpu.score()))
+}
+
+// Whether `family` can present to surfaces on `display`, which is known before there are any.
+fn presents_to_wayland(family: QueueFamily, display: &Display) -> bool {
+    let physical = family.physical_device();
+    let supported = unsafe {
+        physical
+            .instance()
+            .pointers()
+            .GetPhysicalDeviceWaylandPresentationSupportKHR(
+                physical.internal_object(),
+                family.id(),
+                display.c_ptr() as *mut _,
+            )
+    };
+    supported != 0
+}
+
+// Formatted as usual, such as `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex = uuid
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// The `wl_surface` a Vulkan surface presents to, which is all that vulkano keeps of the window.
+/// The `LayerSurface` it belongs to stays with the thread that made it, and has to outlive the
+/// Vulkan surface.
+pub struct SurfaceHandle(*const c_void);
+
+// The pointer is never dereferenced, only handed to the driver, which may present to the surface
+// from any thread
+unsafe impl Send for SurfaceHandle {}
+unsafe impl Sync for SurfaceHandle {}
+
+pub fn create_vk_surface(
+    display: &Display,
+    surface: &LayerSurface,
+    instance: Arc<Instance>,
+) -> Result<Arc<Surface<SurfaceHandle>>, SurfaceCreationError> {
+    let handle = SurfaceHandle(surface.surface.as_ref().c_ptr() as *const c_void);
+    unsafe { Surface::from_wayland(instance, display.c_ptr() as *mut _, handle.0, handle) }
+}
+
+impl VkSession {
+    /// Opens the GPU chosen in `config`, or the best suited one, to draw to surfaces on
+    /// `display`.
+    pub fn initialize(config: &GpuConfig, display: &Display) -> Result<Self, VkSessionError> {
+        let instance = create_instance()?;
+        let gpus = gpus(&instance, display);
+        let gpu = choose(&gpus, config.device.as_ref())?;
+        let physical = PhysicalDevice::from_index(&instance, gpu.index)
+            .ok_or(VkSessionError::GpuUnavailable)?;
+        let queue_family = gpu
+            .queue_family
+            .and_then(|id| physical.queue_family_by_id(id))
+            .ok_or(VkSessionError::GpuUnavailable)?;
+
+        let (device, mut queues) = Device::new(
+            physical,
+            physical.supported_features(),
+            &DeviceExtensions {
+                khr_swapchain: true,
+                ..DeviceExtensions::none()
+            },
+            [(queue_family, 0.5)].iter().cloned(),
+        )?;
+        let queue = queues.next().ok_or(VkSessionError::GpuUnavailable)?;
+
+        Ok(Self {
+            device,
+            instance,
+            queue,
+            physical: gpu.index,
+            render_pass: None,
+        })
+    }
 
-        let caps = vksurface.capabilities(physical).unwrap();
-        let dimensions = caps.current_extent.unwrap_or([WIDTH, HEIGHT]);
-        let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-        let format = caps.supported_formats[0].0;
+    /// Prepares `surface` for drawing. The target has to be dropped before the surface.
+    pub fn target(
+        &mut self,
+        display: &Display,
+        surface: &LayerSurface,
+    ) -> Result<Target, VkSessionError> {
+        let size = surface.buffer_size();
+        let vksurface = create_vk_surface(display, surface, self.instance.clone())?;
+        let physical = PhysicalDevice::from_index(&self.instance, self.physical)
+            .ok_or(VkSessionError::GpuUnavailable)?;
+
+        if !vksurface.is_supported(self.queue.family())? {
+            let reason = "its graphics queue can't present to them";
+            return Err(VkSessionError::SurfaceUnsupported(reason.to_owned()));
+        }
+        let caps = vksurface.capabilities(physical)?;
+        let dimensions = caps.current_extent.unwrap_or(size);
+        let unsupported = |reason: &str| VkSessionError::SurfaceUnsupported(reason.to_owned());
+        let alpha = caps
+            .supported_composite_alpha
+            .iter()
+            .next()
+            .ok_or_else(|| unsupported("they can't be composited"))?;
+        let format = match (&self.render_pass, caps.supported_formats.first()) {
+            (Some((format, _)), _) => *format,
+            (None, Some((format, _))) => *format,
+            (None, None) => return Err(unsupported("they have no formats")),
+        };
+        if !caps.supported_formats.iter().any(|&(f, _)| f == format) {
+            return Err(VkSessionError::UnsupportedFormat(format));
+        }
         let (swapchain, images) = Swapchain::new(
-            device.clone(),
+            self.device.clone(),
             vksurface.clone(),
             caps.min_image_count,
             format,
             dimensions,
             1,
             caps.supported_usage_flags,
-            &queue,
+            &self.queue,
             SurfaceTransform::Identity,
             alpha,
             PresentMode::Fifo,
             true,
             ColorSpace::SrgbNonLinear,
-        )
-        .unwrap();
-
-        let dynamic_state = DynamicState {
-            line_width: None,
-            viewports: None,
-            scissors: None,
-            ..DynamicState::default()
+        )?;
+
+        let render_pass = match &self.render_pass {
+            Some((_, render_pass)) => render_pass.clone(),
+            None => {
+                let render_pass = Arc::new(vulkano::single_pass_renderpass!(
+                    self.device.clone(),
+                    attachments: {
+                        color: {
+                            load: Clear,
+                            store: Store,
+                            format: format,
+                            samples: 1,
+                        }
+                    },
+                    pass: {
+                        color: [color],
+                        depth_stencil: {}
+                    }
+                )?) as Arc<dyn RenderPassAbstract + Send + Sync>;
+                self.render_pass = Some((format, render_pass.clone()));
+                render_pass
+            }
         };
 
-        Ok(Self {
-            device,
-            instance,
-            queue,
-            draw_surface: vksurface,
+        let mut target = Target {
+            surface: vksurface,
             swapchain,
             images,
-            dynamic_state,
-        })
+            dynamic_state: DynamicState {
+                line_width: None,
+                viewports: None,
+                scissors: None,
+                ..DynamicState::default()
+            },
+            render_pass,
+            framebuffers: Vec::new(),
+        };
+        target.build_framebuffers()?;
+        Ok(target)
+    }
+}
+
+impl Target {
+    /// Replaces the swapchain with one of the given size, along with everything that refers to
+    /// its images. The render pass, and so every pipeline, stays valid.
+    pub fn recreate_swapchain(&mut self, dimensions: [u32; 2]) -> Result<(), VkSessionError> {
+        let (swapchain, images) = self.swapchain.recreate_with_dimensions(dimensions)?;
+        self.swapchain = swapchain;
+        self.images = images;
+        self.build_framebuffers()
     }
 
-    pub fn new_framebuffers(
-        &mut self,
-    ) -> (
-        Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
-        Arc<dyn RenderPassAbstract + Send + Sync>,
-    ) {
+    fn build_framebuffers(&mut self) -> Result<(), VkSessionError> {
+        let [width, height] = self.swapchain.dimensions();
         let viewport = Viewport {
             origin: [0.0, 0.0],
-            dimensions: [500.0, 500.0],
+            dimensions: [width as f32, height as f32],
             depth_range: 0.0..1.0,
         };
         self.dynamic_state.viewports = Some(vec![viewport]);
-        let render_pass = Arc::new(
-            vulkano::single_pass_renderpass!(
-                self.device.clone(),
-                attachments: {
-                    color: {
-                        load: Clear,
-                        store: Store,
-                        format: self.swapchain.format(),
-                        samples: 1,
-                    }
-                },
-                pass: {
-                    color: [color],
-                    depth_stencil: {}
-                }
-            )
-            .unwrap(),
-        );
-        let buffers = self
+        let render_pass = self.render_pass.clone();
+        self.framebuffers = self
             .images
             .iter()
             .map(|image| {
-                Arc::new(
-                    Framebuffer::start(render_pass.clone())
-                        .add(image.clone())
-                        .unwrap()
-                        .build()
-                        .unwrap(),
-                ) as Arc<dyn FramebufferAbstract + Send + Sync>
+                let framebuffer = Framebuffer::start(render_pass.clone())
+                    .add(image.clone())?
+                    .build()?;
+                Ok(Arc::new(framebuffer) as Arc<dyn FramebufferAbstract + Send + Sync>)
             })
-            .collect::<Vec<_>>();
-        (buffers, render_pass)
+            .collect::<Result<Vec<_>, FramebufferCreationError>>()?;
+        Ok(())
     }
 }
@@ -1,8 +1,21 @@
-use std::cell::RefCell;
+use crate::config::{self, BarConfig, Edge, Margin, WindowConfig};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
 use wayland_client::protocol::wl_display::WlDisplay;
+use wayland_client::protocol::wl_output::{self, WlOutput};
+use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::protocol::{wl_compositor, wl_surface};
 use wayland_client::Main;
-use wayland_client::{Attached, Display, EventQueue, GlobalManager};
+use wayland_client::{
+    Attached, ConnectError, Display, EventQueue, GlobalError, GlobalEvent, GlobalManager,
+};
+use wayland_protocols::unstable::xdg_output::v1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1;
+use wayland_protocols::unstable::xdg_output::v1::client::zxdg_output_v1;
+use wayland_protocols::viewporter::client::wp_viewport::WpViewport;
+use wayland_protocols::viewporter::client::wp_viewporter::WpViewporter;
 use wayland_protocols::wlr::unstable::layer_shell::v1::client::zwlr_layer_shell_v1::{
     Layer, ZwlrLayerShellV1,
 };
@@ -10,82 +23,551 @@ use wayland_protocols::wlr::unstable::layer_shell::v1::client::zwlr_layer_surfac
     Anchor, Event, ZwlrLayerSurfaceV1,
 };
 
+mod fractional_scale;
+mod xdg_activation;
+
+use fractional_scale::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
+use fractional_scale::wp_fractional_scale_v1::{self, WpFractionalScaleV1};
+use xdg_activation::xdg_activation_token_v1;
+use xdg_activation::xdg_activation_v1::XdgActivationV1;
+
+// Fractional scales are sent as this many 120ths
+const FRACTIONAL_SCALE_DENOMINATOR: f64 = 120.0;
+
+/// The connection to the compositor, and the globals that surfaces are made with.
 pub struct Window {
     pub display: Display,
     pub events: RefCell<EventQueue>,
     pub attached_display: Attached<WlDisplay>,
     pub globals: GlobalManager,
-    pub surface: Main<wl_surface::WlSurface>,
+    pub compositor: Main<wl_compositor::WlCompositor>,
     pub layer: Main<ZwlrLayerShellV1>,
+    /// Both are needed for fractional scales, without them surfaces are scaled by whole numbers.
+    viewporter: Option<Main<WpViewporter>>,
+    fractional_scale: Option<Main<WpFractionalScaleManagerV1>>,
+    /// Hands out the tokens that let launched applications take the focus.
+    activation: Option<Main<XdgActivationV1>>,
+    /// The connected outputs in the order the compositor announced them, kept up to date as they
+    /// are plugged in and out.
+    pub outputs: Rc<RefCell<Vec<Output>>>,
+}
+
+/// A monitor. Its name and description come from xdg-output, so they stay empty on compositors
+/// without it.
+pub struct Output {
+    /// Name of the output's global, which identifies it until it is unplugged.
+    pub id: u32,
+    pub output: WlOutput,
+    pub info: Rc<RefCell<OutputInfo>>,
+}
+
+#[derive(Debug)]
+pub struct OutputInfo {
+    pub name: String,
+    pub description: String,
+    /// Set once the name and description are known.
+    pub done: bool,
+    /// How many pixels the output has per surface coordinate, 1 if it doesn't say.
+    pub scale: i32,
+}
+
+impl Default for OutputInfo {
+    fn default() -> OutputInfo {
+        OutputInfo {
+            name: String::new(),
+            description: String::new(),
+            done: false,
+            scale: 1,
+        }
+    }
+}
+
+impl Output {
+    /// Whether `selector` is the name of the output, such as `DP-1`, or part of its description,
+    /// such as the model of the monitor.
+    pub fn matches(&self, selector: &str) -> bool {
+        let info = self.info.borrow();
+        info.name == selector || (!selector.is_empty() && info.description.contains(selector))
+    }
+}
+
+/// Where a surface goes on its output, and how it behaves there.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// Zero stretches the surface between the anchored edges on that side.
+    pub size: [u32; 2],
+    pub anchor: Vec<Edge>,
+    pub margin: Margin,
+    pub layer: config::Layer,
+    /// Space along the anchored edge that other windows are kept out of.
+    pub exclusive_zone: i32,
+    pub keyboard: bool,
+}
+
+impl Placement {
+    /// The launcher, where the configured window is.
+    pub fn window(config: &WindowConfig) -> Placement {
+        Placement {
+            size: [config.width, config.height],
+            anchor: config.anchor.clone(),
+            margin: config.margin,
+            layer: config.layer,
+            exclusive_zone: 0,
+            keyboard: true,
+        }
+    }
+
+    /// A bar across the whole edge, `height` high or filling the output if that is zero. When
+    /// the bar is exclusive, windows are kept out of its configured height.
+    pub fn bar(config: &BarConfig, window: &WindowConfig, height: u32) -> Placement {
+        let mut anchor = vec![config.edge, Edge::Left, Edge::Right];
+        if height == 0 {
+            anchor = vec![Edge::Top, Edge::Bottom, Edge::Left, Edge::Right];
+        }
+        Placement {
+            size: [0, height],
+            anchor,
+            margin: Margin::default(),
+            layer: window.layer,
+            exclusive_zone: if config.exclusive {
+                config.height as i32
+            } else {
+                0
+            },
+            keyboard: false,
+        }
+    }
+}
+
+/// A layer shell surface, on one output.
+pub struct LayerSurface {
+    pub surface: Main<wl_surface::WlSurface>,
     pub layer_surface: Main<ZwlrLayerSurfaceV1>,
+    /// The size asked for, which the compositor fills in where it is zero.
+    requested: Rc<Cell<[u32; 2]>>,
+    /// The size from the most recent configure event.
+    pub size: Rc<Cell<[u32; 2]>>,
+    /// Set while a frame callback is outstanding, meaning the compositor isn't ready for a new
+    /// frame yet.
+    pub frame_pending: Rc<Cell<bool>>,
+    /// Set when the compositor takes the surface away, such as when its output is unplugged.
+    pub closed: Rc<Cell<bool>>,
+    /// The outputs the surface is shown on, whose scale it takes when there is no fractional
+    /// scale.
+    entered: Rc<RefCell<Vec<WlOutput>>>,
+    outputs: Rc<RefCell<Vec<Output>>>,
+    /// The scale the compositor prefers, if it supports fractional scales.
+    preferred_scale: Rc<Cell<Option<f64>>>,
+    viewport: Option<Main<WpViewport>>,
+    fractional_scale: Option<Main<WpFractionalScaleV1>>,
 }
-unsafe impl Send for Window {}
-unsafe impl Sync for Window {}
 
 #[derive(Debug)]
 pub enum WindowError {
-    Todo(Box<dyn std::error::Error>),
+    NoWaylandDisplay(ConnectError),
+    /// The compositor has no wlr-layer-shell, which every surface is made with.
+    LayerShellUnsupported,
+    /// The compositor lacks some other global, or has too old a version of it.
+    MissingGlobal(&'static str, GlobalError),
+    Connection(io::Error),
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowError::NoWaylandDisplay(e) => write!(
+                f,
+                "could not connect to a Wayland compositor ({}), \
+                 check that one is running and that WAYLAND_DISPLAY is set",
+                e
+            ),
+            WindowError::LayerShellUnsupported => write!(
+                f,
+                "the compositor doesn't support the wlr-layer-shell protocol, \
+                 infolauncher needs a compositor that does, such as sway or Hyprland"
+            ),
+            WindowError::MissingGlobal(interface, GlobalError::Missing) => {
+                write!(f, "the compositor doesn't support {}", interface)
+            }
+            WindowError::MissingGlobal(interface, GlobalError::VersionTooLow(version)) => write!(
+                f,
+                "the compositor only supports version {} of {}, which is too old",
+                version, interface
+            ),
+            WindowError::Connection(e) => write!(f, "lost the connection to the compositor: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WindowError {}
+
+impl From<ConnectError> for WindowError {
+    fn from(e: ConnectError) -> Self {
+        Self::NoWaylandDisplay(e)
+    }
 }
 
-impl From<wayland_client::ConnectError> for WindowError {
-    fn from(e: wayland_client::ConnectError) -> Self {
-        Self::Todo(Box::new(e))
+impl From<io::Error> for WindowError {
+    fn from(e: io::Error) -> Self {
+        Self::Connection(e)
     }
 }
 
 impl Window {
-    pub fn spawn(
-        width: u32,
-        height: u32,
-        offset_top: i32,
-        offset_left: i32,
-    ) -> Result<Window, WindowError> {
+    pub fn connect() -> Result<Window, WindowError> {
         let display = Display::connect_to_env()?;
 
         let mut event_queue = display.create_event_queue();
         let attached_display = (*display).clone().attach(event_queue.get_token());
 
-        let globals = GlobalManager::new(&attached_display);
-        event_queue.sync_roundtrip(|_, _| unreachable!()).unwrap();
-
-        let compositor = globals
-            .instantiate_exact::<wl_compositor::WlCompositor>(1)
-            .unwrap();
-        let surface = compositor.create_surface();
-
-        let layer = globals.instantiate_exact::<ZwlrLayerShellV1>(1).unwrap();
-        let layer_surface =
-            layer.get_layer_surface(&surface, None, Layer::Top, String::from("infolauncher"));
-        layer_surface.set_size(width, height);
-        layer_surface.set_anchor(Anchor::Top);
-        layer_surface.set_margin(offset_top, 0, 0, offset_left);
-        layer_surface.set_keyboard_interactivity(1);
-        layer_surface.assign_mono(move |layer_surface, event| match event {
-            Event::Configure {
-                width: given_width, height: given_height, serial
-            } => {
-                println!("Configuring layer surface with {}:{} -> {}", width, height, serial);
-                if (given_width, given_height) != (width, height) {
-                    panic!("I was prevented by the compositor from using the appropriate window width and height");
+        let outputs: Rc<RefCell<Vec<Output>>> = Rc::default();
+        let xdg_output: Rc<RefCell<Option<Main<ZxdgOutputManagerV1>>>> = Rc::default();
+        // Set once it is known that the compositor has no xdg-output manager
+        let nameless: Rc<Cell<bool>> = Rc::default();
+        let (known, manager, unnamed) = (outputs.clone(), xdg_output.clone(), nameless.clone());
+        let globals = GlobalManager::new_with_cb(&attached_display, move |event, registry, _| {
+            match event {
+                GlobalEvent::New {
+                    id,
+                    interface,
+                    version,
+                } if interface == "wl_output" => {
+                    // Scales were added in version 2
+                    let wl_output = registry.bind::<WlOutput>(version.min(2), id);
+                    let info: Rc<RefCell<OutputInfo>> = Rc::default();
+                    let scaled = info.clone();
+                    wl_output.assign_mono(move |_, event| {
+                        if let wl_output::Event::Scale { factor } = event {
+                            scaled.borrow_mut().scale = factor;
+                        }
+                    });
+                    let output = Output {
+                        id,
+                        output: wl_output.detach(),
+                        info,
+                    };
+                    // The outputs announced along with the manager are described once it is bound
+                    match &*manager.borrow() {
+                        Some(manager) => describe(manager, &output),
+                        None if unnamed.get() => output.info.borrow_mut().done = true,
+                        None => {}
+                    }
+                    known.borrow_mut().push(output);
                 }
-                layer_surface.ack_configure(serial);
+                GlobalEvent::Removed { id, interface } if interface == "wl_output" => {
+                    known.borrow_mut().retain(|output| output.id != id);
+                }
+                _ => {}
             }
-            Event::Closed => {eprintln!("Got close event!"); *super::STATUS.lock().unwrap() = super::Status::Closing},
-            _ => println!("Unhandled event"),
         });
-        let _eventsn = event_queue.sync_roundtrip(|_, _| {}).unwrap();
-        eprintln!("Syncing after Configure event");
-        surface.commit();
-        let _eventsn = event_queue.sync_roundtrip(|_, _| {}).unwrap();
+        event_queue.sync_roundtrip(|_, _| unreachable!())?;
+
+        // Names and descriptions were added in version 2
+        match globals.instantiate_exact::<ZxdgOutputManagerV1>(2) {
+            Ok(manager) => {
+                for output in outputs.borrow().iter() {
+                    describe(&manager, output);
+                }
+                *xdg_output.borrow_mut() = Some(manager);
+            }
+            Err(e) => {
+                eprintln!("Outputs can't be told apart by name: {:?}", e);
+                nameless.set(true);
+                for output in outputs.borrow().iter() {
+                    output.info.borrow_mut().done = true;
+                }
+            }
+        }
+        event_queue.sync_roundtrip(|_, _| {})?;
+
+        // Buffer scales were added in version 3
+        let compositor = globals
+            .instantiate_range::<wl_compositor::WlCompositor>(1, 3)
+            .map_err(|e| WindowError::MissingGlobal("wl_compositor", e))?;
+        let layer = globals
+            .instantiate_exact::<ZwlrLayerShellV1>(1)
+            .map_err(|_| WindowError::LayerShellUnsupported)?;
+        let (viewporter, fractional_scale) = match (
+            globals.instantiate_exact::<WpViewporter>(1),
+            globals.instantiate_exact::<WpFractionalScaleManagerV1>(1),
+        ) {
+            (Ok(viewporter), Ok(fractional_scale)) => (Some(viewporter), Some(fractional_scale)),
+            _ => (None, None),
+        };
+        let activation = globals.instantiate_exact::<XdgActivationV1>(1).ok();
 
         Ok(Self {
             display,
             events: RefCell::new(event_queue),
             attached_display,
             globals,
-            surface,
+            compositor,
             layer,
-            layer_surface,
+            viewporter,
+            fractional_scale,
+            activation,
+            outputs,
         })
     }
+
+    /// Makes a surface on `output`, or on the one the compositor picks if there is none, and
+    /// waits for it to be configured.
+    pub fn create_surface(
+        &self,
+        placement: &Placement,
+        output: Option<&WlOutput>,
+    ) -> Result<LayerSurface, WindowError> {
+        let surface = self.compositor.create_surface();
+        let layer_surface = self.layer.get_layer_surface(
+            &surface,
+            output,
+            layer_of(placement.layer),
+            String::from("infolauncher"),
+        );
+        let requested = Rc::new(Cell::new(placement.size));
+        let size = Rc::new(Cell::new(placement.size));
+        let (wanted, configured_size) = (requested.clone(), size.clone());
+        let closed = Rc::new(Cell::new(false));
+        let closed_by_compositor = closed.clone();
+        layer_surface.assign_mono(move |layer_surface, event| match event {
+            Event::Configure {
+                width,
+                height,
+                serial,
+            } => {
+                // Zero leaves that side to the client, and stretched sides are always given
+                let [wanted_width, wanted_height] = wanted.get();
+                let pick = |given, ours| if given == 0 { ours } else { given };
+                configured_size.set([pick(width, wanted_width), pick(height, wanted_height)]);
+                layer_surface.ack_configure(serial);
+            }
+            Event::Closed => closed_by_compositor.set(true),
+            _ => {}
+        });
+
+        let entered: Rc<RefCell<Vec<WlOutput>>> = Rc::default();
+        let on = entered.clone();
+        surface.assign_mono(move |_, event| match event {
+            wl_surface::Event::Enter { output } => on.borrow_mut().push(output),
+            wl_surface::Event::Leave { output } => on
+                .borrow_mut()
+                .retain(|entered| !entered.as_ref().equals(output.as_ref())),
+            _ => {}
+        });
+        let preferred_scale = Rc::new(Cell::new(None));
+        let viewport = self
+            .viewporter
+            .as_ref()
+            .map(|viewporter| viewporter.get_viewport(&surface));
+        let fractional_scale = self.fractional_scale.as_ref().map(|manager| {
+            let fractional_scale = manager.get_fractional_scale(&surface);
+            let preferred = preferred_scale.clone();
+            fractional_scale.assign_mono(move |_, event| {
+                if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+                    preferred.set(Some(scale as f64 / FRACTIONAL_SCALE_DENOMINATOR));
+                }
+            });
+            fractional_scale
+        });
+
+        let surface = LayerSurface {
+            surface,
+            layer_surface,
+            requested,
+            size,
+            frame_pending: Rc::new(Cell::new(false)),
+            closed,
+            entered,
+            outputs: self.outputs.clone(),
+            preferred_scale,
+            viewport,
+            fractional_scale,
+        };
+        surface.place(placement);
+        self.events.borrow_mut().sync_roundtrip(|_, _| {})?;
+        Ok(surface)
+    }
+
+    /// Asks for a token that lets an application launched because of the input with `serial` on
+    /// `surface` take the focus. None when the compositor doesn't hand out tokens.
+    pub fn activation_token(
+        &self,
+        seat: &WlSeat,
+        serial: u32,
+        surface: &wl_surface::WlSurface,
+    ) -> io::Result<Option<String>> {
+        let activation = match &self.activation {
+            Some(activation) => activation,
+            None => return Ok(None),
+        };
+        let token = Rc::new(RefCell::new(None));
+        let done = token.clone();
+        let request = activation.get_activation_token();
+        request.assign_mono(move |_, event| {
+            if let xdg_activation_token_v1::Event::Done { token } = event {
+                *done.borrow_mut() = Some(token);
+            }
+        });
+        request.set_serial(serial, seat);
+        request.set_surface(surface);
+        request.commit();
+        // The token is sent in answer to the commit
+        self.events.borrow_mut().sync_roundtrip(|_, _| {})?;
+        request.destroy();
+        let token = token.borrow_mut().take();
+        Ok(token)
+    }
+
+    /// Dispatches incoming events. Once everything is sent, `wait` is given the connection's fd
+    /// to wait on, and returns whether it became readable.
+    pub fn dispatch<F>(&self, wait: F) -> io::Result<()>
+    where
+        F: FnOnce(RawFd) -> io::Result<bool>,
+    {
+        let mut events = self.events.borrow_mut();
+        if events.dispatch_pending(|_, _| {})? > 0 {
+            return Ok(());
+        }
+        self.display.flush()?;
+        let guard = match events.prepare_read() {
+            Some(guard) => guard,
+            // Another read queued events between the two calls
+            None => return events.dispatch_pending(|_, _| {}).map(drop),
+        };
+        if wait(self.display.get_connection_fd())? {
+            guard.read_events()?;
+        } else {
+            // Cancels the read
+            drop(guard);
+        }
+        events.dispatch_pending(|_, _| {}).map(drop)
+    }
+}
+
+impl LayerSurface {
+    /// Moves or resizes the surface. The compositor answers with a configure event, and the new
+    /// size shows in `size` once that is dispatched.
+    pub fn place(&self, placement: &Placement) {
+        let [width, height] = placement.size;
+        self.requested.set(placement.size);
+        self.layer_surface.set_size(width, height);
+        self.layer_surface.set_anchor(anchor_of(&placement.anchor));
+        let margin = placement.margin;
+        self.layer_surface
+            .set_margin(margin.top, margin.right, margin.bottom, margin.left);
+        self.layer_surface
+            .set_exclusive_zone(placement.exclusive_zone);
+        self.layer_surface
+            .set_keyboard_interactivity(placement.keyboard as u32);
+        self.surface.commit();
+    }
+
+    /// How many pixels are drawn per surface coordinate. This is the fractional scale when the
+    /// compositor has one, and otherwise the largest scale of the outputs the surface is on.
+    pub fn scale(&self) -> f64 {
+        if let Some(scale) = self.preferred_scale.get() {
+            return scale;
+        }
+        let outputs = self.outputs.borrow();
+        let scale = self
+            .entered
+            .borrow()
+            .iter()
+            .filter_map(|entered| {
+                outputs
+                    .iter()
+                    .find(|output| output.output.as_ref().equals(entered.as_ref()))
+            })
+            .map(|output| output.info.borrow().scale)
+            .max()
+            .unwrap_or(1);
+        scale as f64
+    }
+
+    /// The size of the buffers the surface is drawn in, in pixels.
+    pub fn buffer_size(&self) -> [u32; 2] {
+        let scale = self.scale();
+        let [width, height] = self.size.get();
+        [
+            (width as f64 * scale).round() as u32,
+            (height as f64 * scale).round() as u32,
+        ]
+    }
+
+    /// Tells the compositor how the next buffer, of `buffer_size`, maps onto the surface. Takes
+    /// effect with the commit that presents it.
+    pub fn apply_scale(&self) {
+        match &self.viewport {
+            Some(viewport) => {
+                let [width, height] = self.size.get();
+                viewport.set_destination(width as i32, height as i32);
+            }
+            None if self.surface.as_ref().version() >= 3 => {
+                self.surface.set_buffer_scale(self.scale() as i32)
+            }
+            None => {}
+        }
+    }
+
+    /// Asks to be told when the compositor wants the next frame. Has to be called before the
+    /// frame that it applies to is presented, since presenting commits the surface.
+    pub fn request_frame(&self) {
+        self.frame_pending.set(true);
+        let pending = self.frame_pending.clone();
+        self.surface
+            .frame()
+            .assign_mono(move |_, _| pending.set(false));
+    }
+}
+
+impl Drop for LayerSurface {
+    fn drop(&mut self) {
+        if let Some(fractional_scale) = &self.fractional_scale {
+            fractional_scale.destroy();
+        }
+        if let Some(viewport) = &self.viewport {
+            viewport.destroy();
+        }
+        self.layer_surface.destroy();
+        self.surface.destroy();
+    }
+}
+
+// Asks for the name and description of `output`.
+fn describe(manager: &ZxdgOutputManagerV1, output: &Output) {
+    let info = output.info.clone();
+    manager
+        .get_xdg_output(&output.output)
+        .assign_mono(move |_, event| {
+            let mut info = info.borrow_mut();
+            match event {
+                zxdg_output_v1::Event::Name { name } => info.name = name,
+                zxdg_output_v1::Event::Description { description } => {
+                    info.description = description
+                }
+                zxdg_output_v1::Event::Done => info.done = true,
+                _ => {}
+            }
+        });
+}
+
+fn layer_of(layer: config::Layer) -> Layer {
+    match layer {
+        config::Layer::Background => Layer::Background,
+        config::Layer::Bottom => Layer::Bottom,
+        config::Layer::Top => Layer::Top,
+        config::Layer::Overlay => Layer::Overlay,
+    }
+}
+
+fn anchor_of(edges: &[Edge]) -> Anchor {
+    edges.iter().fold(Anchor::empty(), |anchor, edge| {
+        anchor
+            | match edge {
+                Edge::Top => Anchor::Top,
+                Edge::Bottom => Anchor::Bottom,
+                Edge::Left => Anchor::Left,
+                Edge::Right => Anchor::Right,
+            }
+    })
 }
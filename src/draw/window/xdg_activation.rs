@@ -0,0 +1,14 @@
+//! Client side of the xdg-activation-v1 protocol, which the wayland-protocols version in use
+//! predates. Generated from `protocols/xdg-activation-v1.xml` by the build script.
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports, clippy::all)]
+
+pub(crate) use wayland_client::protocol::{wl_seat, wl_surface};
+pub(crate) use wayland_client::sys;
+pub(crate) use wayland_client::{AnonymousObject, Attached, Main, Proxy, ProxyMap};
+pub(crate) use wayland_commons::map::{Object, ObjectMetadata};
+pub(crate) use wayland_commons::smallvec;
+pub(crate) use wayland_commons::wire::{Argument, ArgumentType, Message, MessageDesc};
+pub(crate) use wayland_commons::{Interface, MessageGroup};
+
+include!(concat!(env!("OUT_DIR"), "/xdg_activation_v1.rs"));
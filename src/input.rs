@@ -0,0 +1,5 @@
+pub mod bindings;
+pub mod clipboard;
+pub mod editor;
+pub mod keyboard;
+pub mod pointer;
@@ -0,0 +1,611 @@
+use super::keyboard::{KeyEvent, Modifiers};
+use crate::config::BindingsConfig;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use xkbcommon::xkb;
+
+// Readline keys, shared by every mode that has a line to edit.
+const DEFAULT_EDITING: [(&str, &str); 32] = [
+    ("left", "cursor-left"),
+    ("ctrl+b", "cursor-left"),
+    ("right", "cursor-right"),
+    ("ctrl+f", "cursor-right"),
+    ("ctrl+left", "word-left"),
+    ("alt+b", "word-left"),
+    ("ctrl+right", "word-right"),
+    ("alt+f", "word-right"),
+    ("home", "line-start"),
+    ("ctrl+a", "line-start"),
+    ("end", "line-end"),
+    ("ctrl+e", "line-end"),
+    ("shift+left", "select-left"),
+    ("shift+right", "select-right"),
+    ("ctrl+shift+left", "select-word-left"),
+    ("ctrl+shift+right", "select-word-right"),
+    ("shift+home", "select-to-start"),
+    ("shift+end", "select-to-end"),
+    ("backspace", "delete-backward"),
+    ("ctrl+h", "delete-backward"),
+    ("delete", "delete-forward"),
+    ("ctrl+d", "delete-forward"),
+    ("ctrl+w", "delete-word"),
+    ("alt+backspace", "delete-word"),
+    ("ctrl+u", "kill-to-start"),
+    ("ctrl+k", "kill-to-end"),
+    ("ctrl+y", "yank"),
+    ("ctrl+z", "undo"),
+    ("ctrl+underscore", "undo"),
+    ("ctrl+shift+z", "redo"),
+    ("ctrl+v", "paste"),
+    ("shift+insert", "paste"),
+];
+const DEFAULT_LAUNCHER: [(&str, &str); 10] = [
+    ("escape", "close"),
+    ("return", "launch"),
+    ("kp_enter", "launch"),
+    ("down", "select-next"),
+    ("up", "select-previous"),
+    ("tab", "select-next"),
+    ("shift+tab", "select-previous"),
+    ("ctrl+n", "select-next"),
+    ("ctrl+p", "select-previous"),
+    ("ctrl+x", "command-mode"),
+];
+const DEFAULT_COMMAND: [(&str, &str); 3] = [
+    ("escape", "launcher-mode"),
+    ("return", "launch"),
+    ("kp_enter", "launch"),
+];
+const DEFAULT_BAR: [(&str, &str); 1] = [("escape", "close")];
+
+// Binding an action to this removes the default binding of the same keys.
+const UNBOUND: &str = "none";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Close,
+    /// Starts the selected result, or runs the command typed in command mode.
+    Launch,
+    SelectNext,
+    SelectPrevious,
+    CursorLeft,
+    CursorRight,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectToStart,
+    SelectToEnd,
+    SelectAll,
+    DeleteBackward,
+    DeleteForward,
+    DeleteWord,
+    KillToStart,
+    KillToEnd,
+    ClearLine,
+    Yank,
+    Undo,
+    Redo,
+    Paste,
+    /// Switches to typing a shell command instead of searching.
+    CommandMode,
+    LauncherMode,
+}
+
+const ACTIONS: [(&str, Action); 29] = [
+    ("close", Action::Close),
+    ("launch", Action::Launch),
+    ("select-next", Action::SelectNext),
+    ("select-previous", Action::SelectPrevious),
+    ("cursor-left", Action::CursorLeft),
+    ("cursor-right", Action::CursorRight),
+    ("word-left", Action::WordLeft),
+    ("word-right", Action::WordRight),
+    ("line-start", Action::LineStart),
+    ("line-end", Action::LineEnd),
+    ("select-left", Action::SelectLeft),
+    ("select-right", Action::SelectRight),
+    ("select-word-left", Action::SelectWordLeft),
+    ("select-word-right", Action::SelectWordRight),
+    ("select-to-start", Action::SelectToStart),
+    ("select-to-end", Action::SelectToEnd),
+    ("select-all", Action::SelectAll),
+    ("delete-backward", Action::DeleteBackward),
+    ("delete-forward", Action::DeleteForward),
+    ("delete-word", Action::DeleteWord),
+    ("kill-to-start", Action::KillToStart),
+    ("kill-to-end", Action::KillToEnd),
+    ("clear-line", Action::ClearLine),
+    ("yank", Action::Yank),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("paste", Action::Paste),
+    ("command-mode", Action::CommandMode),
+    ("launcher-mode", Action::LauncherMode),
+];
+
+impl Action {
+    /// Whether holding the keys down should do it again, which would be surprising for actions
+    /// like launching.
+    pub fn repeats(self) -> bool {
+        !matches!(
+            self,
+            Action::Close | Action::Launch | Action::CommandMode | Action::LauncherMode
+        )
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ACTIONS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| format!("unknown action `{}`", s))
+    }
+}
+
+/// Which table of bindings applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingMode {
+    Launcher,
+    Command,
+    Bar,
+}
+
+impl BindingMode {
+    fn name(self) -> &'static str {
+        match self {
+            BindingMode::Launcher => "launcher",
+            BindingMode::Command => "command",
+            BindingMode::Bar => "bar",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BindingError {
+    pub mode: &'static str,
+    pub keys: String,
+    pub message: String,
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid binding `{}` in [bindings.{}]: {}",
+            self.keys, self.mode, self.message
+        )
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+/// A key together with the modifiers held down with it, such as `ctrl+n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Combo {
+    keysym: xkb::Keysym,
+    modifiers: Modifiers,
+}
+
+impl Combo {
+    fn parse(spec: &str) -> Result<Combo, String> {
+        // `ctrl++` binds the plus key
+        let (modifiers, key) = match spec.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "plus"),
+            None => match spec.rfind('+') {
+                Some(plus) => (&spec[..plus], &spec[plus + 1..]),
+                None => ("", spec),
+            },
+        };
+        let mut combo = Combo {
+            keysym: keysym(key).ok_or_else(|| format!("unknown key `{}`", key))?,
+            modifiers: Modifiers::default(),
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            let held = match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => &mut combo.modifiers.ctrl,
+                "alt" => &mut combo.modifiers.alt,
+                "shift" => &mut combo.modifiers.shift,
+                "logo" | "super" => &mut combo.modifiers.logo,
+                _ => return Err(format!("unknown modifier `{}`", modifier)),
+            };
+            *held = true;
+        }
+        Ok(combo)
+    }
+
+    // Shift is part of typing characters like `?`, so it only counts for keys that don't type
+    // anything, unless the binding asks for it.
+    fn matches(&self, event: &KeyEvent) -> bool {
+        let (want, got) = (self.modifiers, event.modifiers);
+        let shift = got.shift == want.shift || (event.text.is_some() && !want.shift);
+        normalize(event.keysym) == self.keysym
+            && got.ctrl == want.ctrl
+            && got.alt == want.alt
+            && got.logo == want.logo
+            && shift
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    /// The text it was written as, for error messages.
+    spec: String,
+    keys: Vec<Combo>,
+    action: Action,
+}
+
+/// What a key press amounted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feed {
+    Action(Action),
+    /// The key started or continued a sequence that isn't complete yet.
+    Pending,
+    /// No binding uses the key, so it is typed.
+    Unbound,
+}
+
+/// Maps key sequences to actions, with a table for each mode. A sequence such as
+/// `ctrl+x ctrl+c` is a list of combos separated by spaces.
+pub struct Bindings {
+    launcher: Vec<Binding>,
+    command: Vec<Binding>,
+    bar: Vec<Binding>,
+    /// Keys of a sequence typed so far.
+    pending: Vec<KeyEvent>,
+}
+
+impl Bindings {
+    /// Builds the tables from the defaults and `config` on top of them, rejecting unknown keys
+    /// and actions, and bindings that can never be reached because another one shadows them.
+    pub fn new(config: &BindingsConfig) -> Result<Bindings, BindingError> {
+        Ok(Bindings {
+            launcher: table(
+                BindingMode::Launcher,
+                &[&DEFAULT_EDITING[..], &DEFAULT_LAUNCHER].concat(),
+                &config.launcher,
+            )?,
+            command: table(
+                BindingMode::Command,
+                &[&DEFAULT_EDITING[..], &DEFAULT_COMMAND].concat(),
+                &config.command,
+            )?,
+            bar: table(BindingMode::Bar, &DEFAULT_BAR, &config.bar)?,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Looks the key up in the table of `mode`, taking the keys pressed before it into account
+    /// when they started a sequence.
+    pub fn feed(&mut self, mode: BindingMode, event: &KeyEvent) -> Feed {
+        let table = match mode {
+            BindingMode::Launcher => &self.launcher,
+            BindingMode::Command => &self.command,
+            BindingMode::Bar => &self.bar,
+        };
+        self.pending.push(event.clone());
+        loop {
+            let pending = &self.pending;
+            let started = |binding: &&Binding| {
+                binding.keys.len() >= pending.len()
+                    && binding.keys.iter().zip(pending).all(|(c, e)| c.matches(e))
+            };
+            // No binding is the start of another, so a complete one is the only candidate
+            match table.iter().find(started) {
+                Some(binding) if binding.keys.len() == pending.len() => {
+                    self.pending.clear();
+                    return Feed::Action(binding.action);
+                }
+                Some(_) => return Feed::Pending,
+                // A key that breaks off a sequence may still be bound on its own
+                None if pending.len() > 1 => {
+                    self.pending.drain(..pending.len() - 1);
+                }
+                None => {
+                    self.pending.clear();
+                    return Feed::Unbound;
+                }
+            }
+        }
+    }
+
+    /// Forgets a half typed sequence, for when the mode changes.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
+
+fn table(
+    mode: BindingMode,
+    defaults: &[(&str, &str)],
+    config: &BTreeMap<String, String>,
+) -> Result<Vec<Binding>, BindingError> {
+    let error = |keys: &str, message: String| BindingError {
+        mode: mode.name(),
+        keys: keys.to_owned(),
+        message,
+    };
+    let parse = |spec: &str| -> Result<Vec<Combo>, BindingError> {
+        let keys = spec
+            .split_whitespace()
+            .map(Combo::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|message| error(spec, message))?;
+        if keys.is_empty() {
+            return Err(error(spec, String::from("no keys given")));
+        }
+        Ok(keys)
+    };
+
+    let mut bindings = Vec::new();
+    for (spec, action) in defaults {
+        bindings.push(Binding {
+            spec: spec.to_string(),
+            keys: parse(spec)?,
+            action: action.parse().unwrap(),
+        });
+    }
+    // Spelling the same keys differently, as in `Ctrl+N` and `ctrl+n`, is a conflict too
+    let mut configured: Vec<(&str, Vec<Combo>)> = Vec::new();
+    for (spec, action) in config {
+        let keys = parse(spec)?;
+        if let Some((other, _)) = configured.iter().find(|(_, other)| *other == keys) {
+            return Err(error(spec, format!("`{}` is the same keys", other)));
+        }
+        configured.push((spec, keys.clone()));
+        bindings.retain(|binding| binding.keys != keys);
+        if action == UNBOUND {
+            continue;
+        }
+        let action = action.parse().map_err(|message| error(spec, message))?;
+        bindings.push(Binding {
+            spec: spec.clone(),
+            keys,
+            action,
+        });
+    }
+
+    for (i, first) in bindings.iter().enumerate() {
+        for second in &bindings[i + 1..] {
+            let (short, long) = if first.keys.len() <= second.keys.len() {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            if long.keys.starts_with(&short.keys) {
+                let message = if short.keys.len() == long.keys.len() {
+                    format!("`{}` is the same keys", long.spec)
+                } else {
+                    format!(
+                        "it is the start of `{}`, so that can never be typed",
+                        long.spec
+                    )
+                };
+                return Err(error(&short.spec, message));
+            }
+        }
+    }
+    Ok(bindings)
+}
+
+fn keysym(name: &str) -> Option<xkb::Keysym> {
+    let name = match name.to_lowercase().as_str() {
+        "enter" => "Return",
+        "esc" => "Escape",
+        "del" => "Delete",
+        _ => name,
+    };
+    match xkb::keysym_from_name(name, xkb::KEYSYM_CASE_INSENSITIVE) {
+        xkb::keysyms::KEY_NoSymbol => None,
+        keysym => Some(normalize(keysym)),
+    }
+}
+
+// Letters typed with shift held come as upper case keysyms, but are bound as lower case.
+fn normalize(keysym: xkb::Keysym) -> xkb::Keysym {
+    match keysym {
+        xkb::keysyms::KEY_A..=xkb::keysyms::KEY_Z => {
+            keysym - xkb::keysyms::KEY_A + xkb::keysyms::KEY_a
+        }
+        xkb::keysyms::KEY_ISO_Left_Tab => xkb::keysyms::KEY_Tab,
+        _ => keysym,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xkb::keysyms::{
+        KEY_ISO_Left_Tab, KEY_Return, KEY_Tab, KEY_a, KEY_c, KEY_n, KEY_plus, KEY_question, KEY_x,
+    };
+
+    const CTRL: Modifiers = Modifiers {
+        ctrl: true,
+        alt: false,
+        shift: false,
+        logo: false,
+    };
+    const SHIFT: Modifiers = Modifiers {
+        ctrl: false,
+        alt: false,
+        shift: true,
+        logo: false,
+    };
+    const NONE: Modifiers = Modifiers {
+        ctrl: false,
+        alt: false,
+        shift: false,
+        logo: false,
+    };
+
+    fn launcher(config: &[(&str, &str)]) -> Result<Vec<Binding>, BindingError> {
+        let config = config
+            .iter()
+            .map(|(keys, action)| (keys.to_string(), action.to_string()))
+            .collect();
+        table(BindingMode::Launcher, &DEFAULT_LAUNCHER, &config)
+    }
+
+    fn action(bindings: &[Binding], keys: &str) -> Option<Action> {
+        let keys = keys
+            .split_whitespace()
+            .map(|combo| Combo::parse(combo).unwrap())
+            .collect::<Vec<_>>();
+        bindings
+            .iter()
+            .find(|binding| binding.keys == keys)
+            .map(|binding| binding.action)
+    }
+
+    fn key(keysym: xkb::Keysym, modifiers: Modifiers, text: Option<&str>) -> KeyEvent {
+        KeyEvent {
+            keysym,
+            modifiers,
+            text: text.map(str::to_owned),
+            repeated: false,
+        }
+    }
+
+    #[test]
+    fn combos() {
+        let combos = [
+            ("n", Ok((KEY_n, NONE))),
+            ("ctrl+n", Ok((KEY_n, CTRL))),
+            ("Ctrl+N", Ok((KEY_n, CTRL))),
+            ("control+n", Ok((KEY_n, CTRL))),
+            ("ctrl++", Ok((KEY_plus, CTRL))),
+            ("shift+Return", Ok((KEY_Return, SHIFT))),
+            ("enter", Ok((KEY_Return, NONE))),
+            ("ctrl+nope", Err("unknown key `nope`")),
+            ("hyper+n", Err("unknown modifier `hyper`")),
+        ];
+        for (spec, expected) in combos.iter() {
+            let combo = Combo::parse(spec).map(|combo| (combo.keysym, combo.modifiers));
+            assert_eq!(combo, expected.map_err(str::to_owned), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn tables() {
+        let errors = [
+            (
+                &[("ctrl+x ctrl+c", "close")][..],
+                "ctrl+x",
+                "it is the start of `ctrl+x ctrl+c`, so that can never be typed",
+            ),
+            (
+                &[("Ctrl+N", "close"), ("ctrl+n", "launch")],
+                "ctrl+n",
+                "`Ctrl+N` is the same keys",
+            ),
+            (
+                &[
+                    ("ctrl+x", "none"),
+                    ("ctrl+x ctrl+c", "close"),
+                    ("ctrl+x ctrl+c a", "close"),
+                ],
+                "ctrl+x ctrl+c",
+                "it is the start of `ctrl+x ctrl+c a`, so that can never be typed",
+            ),
+            (&[("ctrl+q", "quit")], "ctrl+q", "unknown action `quit`"),
+            (&[("ctrl+q", "")], "ctrl+q", "unknown action ``"),
+            (&[(" ", "close")], " ", "no keys given"),
+        ];
+        for (config, keys, message) in errors.iter() {
+            let error = launcher(config).err();
+            let error = error
+                .as_ref()
+                .map(|e| (e.mode, e.keys.as_str(), e.message.as_str()));
+            assert_eq!(error, Some(("launcher", *keys, *message)), "{:?}", config);
+        }
+
+        let bindings = launcher(&[]).unwrap();
+        assert_eq!(bindings.len(), DEFAULT_LAUNCHER.len());
+        assert_eq!(action(&bindings, "ctrl+x"), Some(Action::CommandMode));
+
+        let bindings = launcher(&[("ctrl+x", "none"), ("ctrl+x ctrl+c", "close")]).unwrap();
+        assert_eq!(action(&bindings, "ctrl+x"), None);
+        assert_eq!(action(&bindings, "ctrl+x ctrl+c"), Some(Action::Close));
+
+        let bindings = launcher(&[("Ctrl+N", "select-all"), ("ctrl++", "launch")]).unwrap();
+        assert_eq!(action(&bindings, "ctrl+n"), Some(Action::SelectAll));
+        assert_eq!(action(&bindings, "ctrl+plus"), Some(Action::Launch));
+        assert_eq!(bindings.len(), DEFAULT_LAUNCHER.len() + 1);
+    }
+
+    #[test]
+    fn feed() {
+        let mut config = BindingsConfig::default();
+        for (keys, action) in &[
+            ("ctrl+x", "none"),
+            ("ctrl+x ctrl+c", "close"),
+            ("question", "select-all"),
+        ] {
+            config.launcher.insert(keys.to_string(), action.to_string());
+        }
+        let mut bindings = Bindings::new(&config).unwrap();
+        let sequences = [
+            (
+                vec![key(KEY_n, CTRL, None)],
+                Feed::Action(Action::SelectNext),
+            ),
+            (vec![key(KEY_n, NONE, Some("n"))], Feed::Unbound),
+            // Shift only counts when the key doesn't type anything
+            (
+                vec![key(KEY_question, SHIFT, Some("?"))],
+                Feed::Action(Action::SelectAll),
+            ),
+            (
+                vec![key(KEY_ISO_Left_Tab, SHIFT, None)],
+                Feed::Action(Action::SelectPrevious),
+            ),
+            (
+                vec![key(KEY_Tab, NONE, None)],
+                Feed::Action(Action::SelectNext),
+            ),
+            (
+                vec![key(
+                    KEY_n,
+                    Modifiers {
+                        shift: true,
+                        ..CTRL
+                    },
+                    None,
+                )],
+                Feed::Unbound,
+            ),
+            (vec![key(KEY_x, CTRL, None)], Feed::Pending),
+            (vec![key(KEY_c, CTRL, None)], Feed::Action(Action::Close)),
+            // A key that breaks off the sequence counts on its own
+            (
+                vec![key(KEY_x, CTRL, None), key(KEY_n, CTRL, None)],
+                Feed::Action(Action::SelectNext),
+            ),
+            (
+                vec![key(KEY_x, CTRL, None), key(KEY_a, NONE, Some("a"))],
+                Feed::Unbound,
+            ),
+            (
+                vec![key(KEY_x, CTRL, None), key(KEY_x, CTRL, None)],
+                Feed::Pending,
+            ),
+            (vec![key(KEY_c, CTRL, None)], Feed::Action(Action::Close)),
+        ];
+        for (keys, expected) in sequences.iter() {
+            let fed = keys
+                .iter()
+                .map(|key| bindings.feed(BindingMode::Launcher, key))
+                .last();
+            assert_eq!(fed, Some(*expected), "{:?}", keys);
+        }
+    }
+}
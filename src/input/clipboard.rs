@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+use std::rc::Rc;
+use wayland_client::protocol::wl_data_device::{self, WlDataDevice};
+use wayland_client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use wayland_client::protocol::wl_data_offer::{self, WlDataOffer};
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::{Display, Main};
+
+// Text types in order of preference, the last ones being what X11 clients offer through Xwayland.
+const TEXT_MIME_TYPES: [&str; 4] = [
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "STRING",
+];
+// How long to wait for the copying client to send more, so that a hung one can't hang the
+// launcher with it.
+const READ_TIMEOUT_MS: i32 = 1000;
+
+struct Offer {
+    offer: WlDataOffer,
+    mime_types: Rc<RefCell<Vec<String>>>,
+}
+
+/// Reads what is on the clipboard, which stays with the client that copied it until someone
+/// asks for it.
+pub struct Clipboard {
+    _device: Main<WlDataDevice>,
+    selection: Rc<RefCell<Option<Offer>>>,
+}
+
+impl Clipboard {
+    pub fn new(manager: &WlDataDeviceManager, seat: &WlSeat) -> Clipboard {
+        let selection: Rc<RefCell<Option<Offer>>> = Rc::new(RefCell::new(None));
+        let current = selection.clone();
+        // Offers are announced along with their types right before they become the selection
+        let mut announced: Option<Offer> = None;
+
+        let device = manager.get_data_device(seat);
+        device.assign_mono(move |_, event| match event {
+            wl_data_device::Event::DataOffer { id } => {
+                let mime_types = Rc::new(RefCell::new(Vec::new()));
+                let types = mime_types.clone();
+                id.assign_mono(move |_, event| {
+                    if let wl_data_offer::Event::Offer { mime_type } = event {
+                        types.borrow_mut().push(mime_type);
+                    }
+                });
+                if let Some(unused) = announced.take() {
+                    unused.offer.destroy();
+                }
+                announced = Some(Offer {
+                    offer: id.detach(),
+                    mime_types,
+                });
+            }
+            wl_data_device::Event::Selection { id } => {
+                let offer = match id {
+                    Some(_) => announced.take(),
+                    None => None,
+                };
+                if let Some(previous) = current.replace(offer) {
+                    previous.offer.destroy();
+                }
+            }
+            _ => {}
+        });
+        Clipboard {
+            _device: device,
+            selection,
+        }
+    }
+
+    /// The text on the clipboard, or None if it is empty or holds something else.
+    pub fn read(&self, display: &Display) -> io::Result<Option<String>> {
+        let selection = self.selection.borrow();
+        let offer = match &*selection {
+            Some(offer) => offer,
+            None => return Ok(None),
+        };
+        let mime_type = {
+            let offered = offer.mime_types.borrow();
+            match TEXT_MIME_TYPES
+                .iter()
+                .find(|mime_type| offered.iter().any(|o| o == *mime_type))
+            {
+                Some(mime_type) => mime_type.to_string(),
+                None => return Ok(None),
+            }
+        };
+
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let (mut reader, writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        offer.offer.receive(mime_type, fds[1]);
+        display.flush()?;
+        // The compositor has its own copy of the write end now, and the end of the data only
+        // shows once every copy is closed
+        drop(writer);
+
+        let mut data = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let mut fd = libc::pollfd {
+                fd: fds[0],
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut fd, 1, READ_TIMEOUT_MS) } {
+                -1 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(e);
+                }
+                0 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "clipboard owner stopped sending",
+                    ))
+                }
+                _ => {}
+            }
+            match reader.read(&mut buffer)? {
+                0 => break,
+                n => data.extend_from_slice(&buffer[..n]),
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+}
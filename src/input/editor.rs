@@ -0,0 +1,430 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+const MAX_UNDO: usize = 100;
+
+// What the last change was, so that a run of typed characters or deletions is undone at once
+// and consecutive kills are yanked back together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Insert,
+    Delete,
+    Kill,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    text: String,
+    cursor: usize,
+    anchor: Option<usize>,
+}
+
+/// A single line of editable text with a cursor, a selection, a kill buffer and undo history,
+/// editable the way readline does it. Positions are byte offsets that always fall on grapheme
+/// cluster boundaries, so that the cursor never ends up inside a character made of several code
+/// points.
+#[derive(Debug, Default)]
+pub struct Editor {
+    text: String,
+    cursor: usize,
+    /// The other end of the selection, if there is one.
+    anchor: Option<usize>,
+    killed: String,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    last: Option<Edit>,
+}
+
+impl Editor {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The selected range, unless it is empty.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor.filter(|&anchor| anchor != self.cursor)?;
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    /// Types `text` at the cursor, replacing the selection.
+    pub fn insert(&mut self, text: &str) {
+        let range = self.selection().unwrap_or(self.cursor..self.cursor);
+        self.replace(range, text, Edit::Insert);
+    }
+
+    /// Like `insert`, but undone on its own, and with line breaks turned into spaces.
+    pub fn paste(&mut self, text: &str) {
+        let text = text
+            .trim_end_matches(&['\r', '\n'][..])
+            .replace(&['\r', '\n'][..], " ");
+        let range = self.selection().unwrap_or(self.cursor..self.cursor);
+        self.replace(range, &text, Edit::Other);
+    }
+
+    pub fn clear(&mut self) {
+        if !self.text.is_empty() {
+            self.replace(0..self.text.len(), "", Edit::Other);
+        }
+    }
+
+    pub fn move_left(&mut self, select: bool) {
+        match self.selection() {
+            Some(selection) if !select => self.move_to(selection.start, false),
+            _ => self.move_to(self.previous_boundary(self.cursor), select),
+        }
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        match self.selection() {
+            Some(selection) if !select => self.move_to(selection.end, false),
+            _ => self.move_to(self.next_boundary(self.cursor), select),
+        }
+    }
+
+    pub fn word_left(&mut self, select: bool) {
+        self.move_to(self.word_start(self.cursor), select);
+    }
+
+    pub fn word_right(&mut self, select: bool) {
+        self.move_to(self.word_end(self.cursor), select);
+    }
+
+    pub fn line_start(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+
+    pub fn line_end(&mut self, select: bool) {
+        self.move_to(self.text.len(), select);
+    }
+
+    pub fn select_all(&mut self) {
+        self.move_to(0, false);
+        self.move_to(self.text.len(), true);
+    }
+
+    pub fn delete_backward(&mut self) {
+        let range = self
+            .selection()
+            .unwrap_or(self.previous_boundary(self.cursor)..self.cursor);
+        self.replace(range, "", Edit::Delete);
+    }
+
+    pub fn delete_forward(&mut self) {
+        let range = self
+            .selection()
+            .unwrap_or(self.cursor..self.next_boundary(self.cursor));
+        self.replace(range, "", Edit::Delete);
+    }
+
+    /// Kills from the start of the word before the cursor, or deletes the selection.
+    pub fn delete_word_backward(&mut self) {
+        match self.selection() {
+            Some(selection) => self.replace(selection, "", Edit::Delete),
+            None => self.kill(self.word_start(self.cursor)..self.cursor, true),
+        }
+    }
+
+    pub fn kill_to_start(&mut self) {
+        self.kill(0..self.cursor, true);
+    }
+
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.cursor..self.text.len(), false);
+    }
+
+    /// Inserts the text killed last, replacing the selection.
+    pub fn yank(&mut self) {
+        let killed = self.killed.clone();
+        let range = self.selection().unwrap_or(self.cursor..self.cursor);
+        self.replace(range, &killed, Edit::Other);
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(snapshot) = self.undo.pop() {
+            self.redo.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(snapshot) = self.redo.pop() {
+            self.undo.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    fn move_to(&mut self, position: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = position;
+        self.last = None;
+    }
+
+    // Removes `range` into the kill buffer. Kills right after each other add up, so that
+    // killing several words and yanking them back gives all of them.
+    fn kill(&mut self, range: Range<usize>, backward: bool) {
+        if range.is_empty() {
+            return;
+        }
+        let killed = &self.text[range.clone()];
+        match self.last {
+            Some(Edit::Kill) if backward => self.killed.insert_str(0, killed),
+            Some(Edit::Kill) => self.killed.push_str(killed),
+            _ => self.killed = killed.to_owned(),
+        }
+        self.replace(range, "", Edit::Kill);
+    }
+
+    fn replace(&mut self, range: Range<usize>, with: &str, edit: Edit) {
+        if range.is_empty() && with.is_empty() {
+            return;
+        }
+        let continues = self.last == Some(edit) && edit != Edit::Other;
+        if !continues {
+            if self.undo.len() == MAX_UNDO {
+                self.undo.remove(0);
+            }
+            self.undo.push(self.snapshot());
+        }
+        self.redo.clear();
+
+        self.text.replace_range(range.clone(), with);
+        self.cursor = range.start + with.len();
+        self.anchor = None;
+        self.last = Some(edit);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            text: self.text.clone(),
+            cursor: self.cursor,
+            anchor: self.anchor,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.text = snapshot.text;
+        self.cursor = snapshot.cursor;
+        self.anchor = snapshot.anchor;
+        self.last = None;
+    }
+
+    fn previous_boundary(&self, from: usize) -> usize {
+        self.text[..from]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self, from: usize) -> usize {
+        self.text[from..]
+            .graphemes(true)
+            .next()
+            .map_or(from, |g| from + g.len())
+    }
+
+    // Skips whatever separates words, then the word itself.
+    fn word_start(&self, from: usize) -> usize {
+        let mut start = from;
+        let mut in_word = false;
+        for (i, grapheme) in self.text[..from].grapheme_indices(true).rev() {
+            let word = is_word(grapheme);
+            if in_word && !word {
+                break;
+            }
+            in_word |= word;
+            start = i;
+        }
+        start
+    }
+
+    fn word_end(&self, from: usize) -> usize {
+        let mut end = from;
+        let mut in_word = false;
+        for (i, grapheme) in self.text[from..].grapheme_indices(true) {
+            let word = is_word(grapheme);
+            if in_word && !word {
+                break;
+            }
+            in_word |= word;
+            end = from + i + grapheme.len();
+        }
+        end
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    matches!(grapheme.chars().next(), Some(c) if c.is_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> Editor {
+        let mut editor = Editor::default();
+        editor.insert(text);
+        editor
+    }
+
+    #[test]
+    fn moves_and_deletes_whole_graphemes() {
+        // An e with a combining accent, and a family made of three emoji joined by ZWJs
+        let accent = "e\u{301}";
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        let mut editor = typed(&format!("a{}{}b", accent, family));
+        let family_at = 1 + accent.len();
+
+        editor.move_left(false);
+        assert_eq!(editor.cursor(), family_at + family.len());
+        editor.move_left(false);
+        assert_eq!(editor.cursor(), family_at);
+        editor.move_left(false);
+        assert_eq!(editor.cursor(), 1);
+        editor.move_right(false);
+        assert_eq!(editor.cursor(), family_at);
+
+        editor.delete_backward();
+        assert_eq!(editor.text(), format!("a{}b", family));
+        assert_eq!(editor.cursor(), 1);
+        editor.move_right(false);
+        assert_eq!(editor.cursor(), 1 + family.len());
+        editor.delete_backward();
+        assert_eq!(editor.text(), "ab");
+        editor.delete_forward();
+        assert_eq!(editor.text(), "a");
+    }
+
+    #[test]
+    fn words() {
+        let mut editor = typed("foo  bar-baz");
+        let mut left = Vec::new();
+        for _ in 0..4 {
+            editor.word_left(false);
+            left.push(editor.cursor());
+        }
+        assert_eq!(left, [9, 5, 0, 0]);
+        let mut right = Vec::new();
+        for _ in 0..4 {
+            editor.word_right(false);
+            right.push(editor.cursor());
+        }
+        assert_eq!(right, [3, 8, 12, 12]);
+
+        // From inside a word, only the rest of it is skipped
+        editor.line_start(false);
+        editor.move_right(false);
+        editor.word_right(false);
+        assert_eq!(editor.cursor(), 3);
+        editor.move_left(false);
+        editor.word_left(false);
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn consecutive_kills_are_yanked_together() {
+        let mut editor = typed("one two three");
+        editor.delete_word_backward();
+        assert_eq!(editor.text(), "one two ");
+        editor.delete_word_backward();
+        assert_eq!(editor.text(), "one ");
+        editor.yank();
+        assert_eq!(editor.text(), "one two three");
+        assert_eq!(editor.cursor(), editor.text().len());
+
+        editor.line_start(false);
+        editor.word_right(false);
+        editor.kill_to_end();
+        assert_eq!(editor.text(), "one");
+        editor.kill_to_start();
+        assert_eq!(editor.text(), "");
+        editor.yank();
+        assert_eq!(editor.text(), "one two three");
+
+        // Anything in between starts a new kill
+        editor.delete_word_backward();
+        editor.move_left(false);
+        editor.delete_word_backward();
+        editor.line_end(false);
+        editor.yank();
+        assert_eq!(editor.text(), "one  two");
+    }
+
+    #[test]
+    fn typing_is_undone_at_once() {
+        let mut editor = Editor::default();
+        for c in ["a", "b", "c"].iter() {
+            editor.insert(c);
+        }
+        editor.move_left(false);
+        editor.insert("d");
+        assert_eq!(editor.text(), "abdc");
+        editor.delete_backward();
+        editor.delete_backward();
+        assert_eq!(editor.text(), "ac");
+
+        editor.undo();
+        assert_eq!(editor.text(), "abdc");
+        editor.undo();
+        assert_eq!(editor.text(), "abc");
+        assert_eq!(editor.cursor(), 2);
+        editor.undo();
+        assert_eq!(editor.text(), "");
+        editor.undo();
+        assert_eq!(editor.text(), "");
+
+        editor.redo();
+        assert_eq!(editor.text(), "abc");
+        editor.redo();
+        assert_eq!(editor.text(), "abdc");
+        editor.undo();
+        editor.insert("x");
+        assert_eq!(editor.text(), "abxc");
+        editor.redo();
+        assert_eq!(editor.text(), "abxc");
+    }
+
+    #[test]
+    fn paste_folds_lines() {
+        let mut editor = typed("echo ");
+        editor.paste("one\ntwo\r\nthree\r\n\n");
+        assert_eq!(editor.text(), "echo one two  three");
+        editor.undo();
+        assert_eq!(editor.text(), "echo ");
+    }
+
+    #[test]
+    fn selection_is_replaced() {
+        let mut editor = typed("hello world");
+        editor.word_left(true);
+        assert_eq!(editor.selection(), Some(6..11));
+        editor.insert("there");
+        assert_eq!(editor.text(), "hello there");
+        assert_eq!(editor.selection(), None);
+
+        editor.line_start(false);
+        editor.word_right(true);
+        editor.paste("bye");
+        assert_eq!(editor.text(), "bye there");
+
+        editor.select_all();
+        assert_eq!(editor.selection(), Some(0..9));
+        editor.delete_backward();
+        assert_eq!(editor.text(), "");
+
+        // Moving without selecting collapses the selection to the side moved to
+        editor.insert("abc");
+        editor.move_left(true);
+        editor.move_left(true);
+        editor.move_right(false);
+        assert_eq!((editor.cursor(), editor.selection()), (3, None));
+    }
+}
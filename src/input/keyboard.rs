@@ -0,0 +1,353 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::time::{Duration, Instant};
+use xkbcommon::xkb;
+
+// Wayland key codes are evdev ones, which xkb offsets by 8 for historical X11 reasons.
+const EVDEV_OFFSET: u32 = 8;
+
+#[derive(Debug)]
+pub enum KeyboardError {
+    UnsupportedFormat,
+    Map(io::Error),
+    /// xkbcommon couldn't compile the keymap sent by the compositor.
+    InvalidKeymap,
+}
+
+impl fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyboardError::UnsupportedFormat => write!(f, "keymap is not in the xkb v1 format"),
+            KeyboardError::Map(e) => write!(f, "could not map keymap: {}", e),
+            KeyboardError::InvalidKeymap => write!(f, "could not compile keymap"),
+        }
+    }
+}
+
+impl std::error::Error for KeyboardError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+}
+
+/// A key press translated with the current keymap and modifiers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub keysym: xkb::Keysym,
+    pub modifiers: Modifiers,
+    /// What typing the key produces, if anything. Dead keys and unfinished compose sequences
+    /// produce nothing until the character they make up is complete.
+    pub text: Option<String>,
+    /// Sent again because the key is being held down.
+    pub repeated: bool,
+}
+
+/// Keeps the keymap and modifier state the compositor sends, and turns key codes into keysyms
+/// and text with them.
+pub struct Keyboard {
+    context: xkb::Context,
+    state: Option<xkb::State>,
+    compose: Option<xkb::compose::State>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        // Without a compose table dead keys and the compose key do nothing, which is no reason
+        // to give up on the keyboard
+        let compose = xkb::compose::Table::new_from_locale(
+            &context,
+            &compose_locale(),
+            xkb::compose::COMPILE_NO_FLAGS,
+        )
+        .ok()
+        .map(|table| xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS));
+        Keyboard {
+            context,
+            state: None,
+            compose,
+        }
+    }
+}
+
+impl Keyboard {
+    /// Compiles the keymap from a `wl_keyboard` keymap event, taking ownership of `fd`.
+    pub fn set_keymap(&mut self, xkb_v1: bool, fd: RawFd, size: u32) -> Result<(), KeyboardError> {
+        let file = unsafe { File::from_raw_fd(fd) };
+        if !xkb_v1 {
+            return Err(KeyboardError::UnsupportedFormat);
+        }
+        let map = unsafe {
+            memmap2::MmapOptions::new()
+                .len(size as usize)
+                .map_copy_read_only(&file)
+        }
+        .map_err(KeyboardError::Map)?;
+        // The keymap is a NUL terminated string
+        let end = map.iter().position(|&b| b == 0).unwrap_or(map.len());
+        let source = String::from_utf8_lossy(&map[..end]).into_owned();
+
+        let keymap = xkb::Keymap::new_from_string(
+            &self.context,
+            source,
+            xkb::KEYMAP_FORMAT_TEXT_V1,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .ok_or(KeyboardError::InvalidKeymap)?;
+        self.state = Some(xkb::State::new(&keymap));
+        Ok(())
+    }
+
+    /// Whether holding `key` down should repeat it, which modifiers for example don't.
+    pub fn repeats(&self, key: u32) -> bool {
+        match &self.state {
+            Some(state) => state.get_keymap().key_repeats(key + EVDEV_OFFSET),
+            None => false,
+        }
+    }
+
+    pub fn set_modifiers(&mut self, depressed: u32, latched: u32, locked: u32, group: u32) {
+        if let Some(state) = &mut self.state {
+            state.update_mask(depressed, latched, locked, 0, 0, group);
+        }
+    }
+
+    /// Translates a pressed evdev key code. Nothing comes out until a keymap has been set.
+    pub fn press(&mut self, key: u32) -> Option<KeyEvent> {
+        let state = self.state.as_ref()?;
+        let code = key + EVDEV_OFFSET;
+        let keysym = state.key_get_one_sym(code);
+        let active = |name| state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE);
+        let modifiers = Modifiers {
+            ctrl: active(xkb::MOD_NAME_CTRL),
+            alt: active(xkb::MOD_NAME_ALT),
+            shift: active(xkb::MOD_NAME_SHIFT),
+            logo: active(xkb::MOD_NAME_LOGO),
+        };
+
+        let text = match &mut self.compose {
+            Some(compose) if compose.feed(keysym) == xkb::compose::FeedResult::Accepted => {
+                match compose.status() {
+                    xkb::compose::Status::Composing => None,
+                    xkb::compose::Status::Composed => {
+                        let text = compose.utf8();
+                        compose.reset();
+                        text
+                    }
+                    xkb::compose::Status::Cancelled => {
+                        compose.reset();
+                        None
+                    }
+                    xkb::compose::Status::Nothing => Some(state.key_get_utf8(code)),
+                }
+            }
+            _ => Some(state.key_get_utf8(code)),
+        };
+        // Keys such as Return and Backspace come out as control characters, which are never
+        // meant to be inserted
+        let text = text.filter(|text| !text.is_empty() && !text.chars().any(char::is_control));
+
+        Some(KeyEvent {
+            keysym,
+            modifiers,
+            text,
+            repeated: false,
+        })
+    }
+}
+
+/// Repeats the key being held down. Compositors leave repeating to clients, and only say how
+/// fast it should happen.
+#[derive(Debug)]
+pub struct Repeat {
+    delay: Duration,
+    /// Time between repeats, or None if repeating is turned off.
+    interval: Option<Duration>,
+    held: Option<Held>,
+}
+
+#[derive(Debug)]
+struct Held {
+    key: u32,
+    event: KeyEvent,
+    next: Instant,
+}
+
+impl Default for Repeat {
+    // What most compositors send, until they have said otherwise
+    fn default() -> Repeat {
+        Repeat {
+            delay: Duration::from_millis(600),
+            interval: Some(Duration::from_millis(40)),
+            held: None,
+        }
+    }
+}
+
+impl Repeat {
+    /// Sets the rate and delay from a `wl_keyboard` repeat info event. A rate of 0 turns repeating
+    /// off.
+    pub fn set_info(&mut self, rate: i32, delay: i32) {
+        self.delay = Duration::from_millis(delay.max(0) as u64);
+        self.interval = match rate {
+            rate if rate > 0 => Some(Duration::from_micros(1_000_000 / rate as u64)),
+            _ => None,
+        };
+        self.held = None;
+    }
+
+    /// Starts repeating `event` after the delay, replacing whatever key was repeating before.
+    pub fn press(&mut self, key: u32, event: KeyEvent) {
+        self.held = match self.interval {
+            Some(_) => Some(Held {
+                key,
+                event,
+                next: Instant::now() + self.delay,
+            }),
+            None => None,
+        };
+    }
+
+    pub fn release(&mut self, key: u32) {
+        if self.held.as_ref().map(|held| held.key) == Some(key) {
+            self.held = None;
+        }
+    }
+
+    /// Stops repeating, for when the keyboard focus is lost and releases won't be seen.
+    pub fn cancel(&mut self) {
+        self.held = None;
+    }
+
+    /// When the next repeat is due, if a key is held.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.held.as_ref().map(|held| held.next)
+    }
+
+    /// The repeated key if it is due by `now`. Repeats missed while the event loop was busy are
+    /// dropped rather than all delivered at once.
+    pub fn due(&mut self, now: Instant) -> Option<KeyEvent> {
+        let interval = self.interval?;
+        let held = self.held.as_mut().filter(|held| held.next <= now)?;
+        held.next += interval;
+        if held.next <= now {
+            held.next = now + interval;
+        }
+        Some(KeyEvent {
+            repeated: true,
+            ..held.event.clone()
+        })
+    }
+}
+
+// The compose table depends on the locale, and is looked up the way libX11 does.
+fn compose_locale() -> OsString {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| OsString::from("C"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn key(text: &str) -> KeyEvent {
+        KeyEvent {
+            keysym: xkb::keysyms::KEY_a,
+            modifiers: Modifiers::default(),
+            text: Some(text.to_owned()),
+            repeated: false,
+        }
+    }
+
+    fn repeated(text: &str) -> Option<KeyEvent> {
+        Some(KeyEvent {
+            repeated: true,
+            ..key(text)
+        })
+    }
+
+    // Presses `code` and returns when its first repeat is due, which is the delay from now.
+    fn press(repeat: &mut Repeat, code: u32, event: KeyEvent, delay: Duration) -> Instant {
+        let before = Instant::now();
+        repeat.press(code, event);
+        let after = Instant::now();
+        let first = repeat.deadline().unwrap();
+        assert!(before + delay <= first && first <= after + delay);
+        first
+    }
+
+    #[test]
+    fn delay_and_rate() {
+        let mut repeat = Repeat::default();
+        let first = press(&mut repeat, 30, key("a"), 600 * MS);
+        assert_eq!(repeat.due(first), repeated("a"));
+        assert_eq!(repeat.deadline(), Some(first + 40 * MS));
+
+        // New repeat info stops the key being held
+        repeat.set_info(25, 300);
+        assert_eq!(repeat.deadline(), None);
+        let first = press(&mut repeat, 30, key("a"), 300 * MS);
+        assert_eq!(repeat.due(first - MS), None);
+        assert_eq!(repeat.due(first), repeated("a"));
+        assert_eq!(repeat.due(first + 39 * MS), None);
+        assert_eq!(repeat.due(first + 40 * MS), repeated("a"));
+        assert_eq!(repeat.deadline(), Some(first + 80 * MS));
+
+        repeat.set_info(0, 300);
+        repeat.press(30, key("a"));
+        assert_eq!(repeat.deadline(), None);
+        assert_eq!(repeat.due(Instant::now() + 1000 * MS), None);
+
+        repeat.set_info(10, -5);
+        press(&mut repeat, 30, key("a"), Duration::from_secs(0));
+        assert_eq!(repeat.interval, Some(100 * MS));
+    }
+
+    #[test]
+    fn press_and_release() {
+        let mut repeat = Repeat::default();
+        press(&mut repeat, 30, key("a"), 600 * MS);
+        let first = press(&mut repeat, 48, key("b"), 600 * MS);
+
+        // Only the last key pressed repeats, and releasing another one changes nothing
+        repeat.release(30);
+        assert_eq!(repeat.due(first), repeated("b"));
+        repeat.release(48);
+        assert_eq!(repeat.deadline(), None);
+        assert_eq!(repeat.due(first + 1000 * MS), None);
+
+        let first = press(&mut repeat, 30, key("a"), 600 * MS);
+        repeat.cancel();
+        assert_eq!(repeat.deadline(), None);
+        assert_eq!(repeat.due(first), None);
+    }
+
+    #[test]
+    fn catches_up() {
+        let mut repeat = Repeat::default();
+        let first = press(&mut repeat, 30, key("a"), 600 * MS);
+
+        // Running a little late keeps the pace
+        assert_eq!(repeat.due(first + 15 * MS), repeated("a"));
+        assert_eq!(repeat.deadline(), Some(first + 40 * MS));
+
+        // Missed repeats are dropped, and the next one is an interval from now
+        let late = first + 1000 * MS;
+        assert_eq!(repeat.due(late), repeated("a"));
+        assert_eq!(repeat.due(late), None);
+        assert_eq!(repeat.deadline(), Some(late + 40 * MS));
+        assert_eq!(repeat.due(late + 40 * MS), repeated("a"));
+    }
+}
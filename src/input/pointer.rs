@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use wayland_client::protocol::wl_compositor::WlCompositor;
+use wayland_client::protocol::wl_pointer::{self, WlPointer};
+use wayland_client::protocol::wl_shm::WlShm;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::protocol::wl_touch;
+use wayland_client::{Attached, Main};
+use wayland_cursor::CursorTheme;
+
+// From linux/input-event-codes.h
+const BTN_LEFT: u32 = 0x110;
+// How far the wheel scrolls in one notch, in the surface coordinates that axis events use.
+const SCROLL_STEP: f64 = 10.0;
+const DEFAULT_CURSOR_SIZE: u32 = 24;
+
+/// Pointer and touch input, in surface coordinates. Touch is reduced to what a pointer does, a
+/// finger hovering where it is and clicking where it is lifted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerEvent {
+    Motion([f32; 2]),
+    Leave,
+    Click([f32; 2]),
+    /// Whole notches of the wheel, positive when scrolling down.
+    Scroll {
+        at: [f32; 2],
+        steps: i32,
+    },
+}
+
+/// Turns pointer and touch events into `PointerEvent`s.
+#[derive(Debug, Default)]
+pub struct Pointer {
+    position: [f32; 2],
+    /// Scrolling that didn't add up to a whole step yet.
+    scrolled: f64,
+    touches: HashMap<i32, [f32; 2]>,
+}
+
+impl Pointer {
+    pub fn pointer(&mut self, event: wl_pointer::Event) -> Option<PointerEvent> {
+        match event {
+            wl_pointer::Event::Enter {
+                surface_x,
+                surface_y,
+                ..
+            }
+            | wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                self.position = [surface_x as f32, surface_y as f32];
+                Some(PointerEvent::Motion(self.position))
+            }
+            wl_pointer::Event::Leave { .. } => {
+                self.scrolled = 0.0;
+                Some(PointerEvent::Leave)
+            }
+            wl_pointer::Event::Button { button, state, .. }
+                if button == BTN_LEFT && state == wl_pointer::ButtonState::Pressed =>
+            {
+                Some(PointerEvent::Click(self.position))
+            }
+            wl_pointer::Event::Axis { axis, value, .. }
+                if axis == wl_pointer::Axis::VerticalScroll =>
+            {
+                self.scrolled += value;
+                let steps = (self.scrolled / SCROLL_STEP).trunc();
+                self.scrolled -= steps * SCROLL_STEP;
+                if steps == 0.0 {
+                    return None;
+                }
+                Some(PointerEvent::Scroll {
+                    at: self.position,
+                    steps: steps as i32,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn touch(&mut self, event: wl_touch::Event) -> Option<PointerEvent> {
+        match event {
+            wl_touch::Event::Down { id, x, y, .. } | wl_touch::Event::Motion { id, x, y, .. } => {
+                let position = [x as f32, y as f32];
+                self.touches.insert(id, position);
+                Some(PointerEvent::Motion(position))
+            }
+            wl_touch::Event::Up { id, .. } => self.touches.remove(&id).map(PointerEvent::Click),
+            wl_touch::Event::Cancel => {
+                self.touches.clear();
+                Some(PointerEvent::Leave)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The cursor image shown over the window, loaded from the cursor theme named by
+/// `XCURSOR_THEME` at the size in `XCURSOR_SIZE` times the scale of the screen it is over.
+pub struct Cursor {
+    shm: Attached<WlShm>,
+    /// Size in surface coordinates.
+    size: u32,
+    scale: u32,
+    theme: CursorTheme,
+    surface: Main<WlSurface>,
+    /// The pointer over the window and the serial of its enter event, which setting the image
+    /// needs.
+    entered: Option<(WlPointer, u32)>,
+    shape: &'static str,
+}
+
+impl Cursor {
+    pub fn new(compositor: &WlCompositor, shm: &Attached<WlShm>) -> Cursor {
+        let size = std::env::var("XCURSOR_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_CURSOR_SIZE);
+        Cursor {
+            shm: shm.clone(),
+            size,
+            scale: 1,
+            theme: CursorTheme::load(size, shm),
+            surface: compositor.create_surface(),
+            entered: None,
+            shape: "left_ptr",
+        }
+    }
+
+    pub fn enter(&mut self, pointer: &WlPointer, serial: u32) {
+        self.entered = Some((pointer.clone(), serial));
+        self.show();
+    }
+
+    pub fn leave(&mut self) {
+        self.entered = None;
+    }
+
+    /// Switches to the theme's cursor called `shape`, such as `left_ptr` or `hand2`.
+    pub fn set_shape(&mut self, shape: &'static str) {
+        if shape != self.shape {
+            self.shape = shape;
+            self.show();
+        }
+    }
+
+    /// Loads the theme again at `scale` pixels per surface coordinate, if it isn't already.
+    pub fn set_scale(&mut self, scale: u32) {
+        let scale = scale.max(1);
+        if scale != self.scale {
+            self.scale = scale;
+            self.theme = CursorTheme::load(self.size * scale, &self.shm);
+            self.show();
+        }
+    }
+
+    fn show(&mut self) {
+        let (pointer, serial) = match &self.entered {
+            Some(entered) => entered,
+            None => return,
+        };
+        let image = match self.theme.get_cursor(self.shape) {
+            Some(cursor) => &cursor[0],
+            None => {
+                eprintln!("Cursor theme has no {} cursor", self.shape);
+                return;
+            }
+        };
+        let (width, height) = image.dimensions();
+        let (x, y) = image.hotspot();
+        // Buffer scales were added in version 3, and the compositor only takes images whose size
+        // divides by the scale. Themes without an image that large fall back to a smaller one,
+        // which is shown unscaled.
+        let scalable = self.surface.as_ref().version() >= 3;
+        let scale = if scalable && width % self.scale == 0 && height % self.scale == 0 {
+            self.scale
+        } else {
+            1
+        };
+        if scalable {
+            self.surface.set_buffer_scale(scale as i32);
+        }
+        self.surface.attach(Some(&**image), 0, 0);
+        self.surface
+            .damage(0, 0, (width / scale) as i32, (height / scale) as i32);
+        self.surface.commit();
+        let hotspot = ((x / scale) as i32, (y / scale) as i32);
+        pointer.set_cursor(*serial, Some(&self.surface), hotspot.0, hotspot.1);
+    }
+}
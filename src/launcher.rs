@@ -0,0 +1,289 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub mod desktop;
+pub mod exec;
+pub mod fuzzy;
+pub mod history;
+use desktop::{Action, DesktopEntry, Locale};
+use fuzzy::Matcher;
+use history::History;
+
+const ICON_SIZES: [&str; 6] = ["48x48", "64x64", "32x32", "128x128", "256x256", "24x24"];
+
+/// Something that can be launched: an application, or one of its actions such as "New Window".
+#[derive(Debug, Clone, Copy)]
+pub struct Item<'a> {
+    pub entry: &'a DesktopEntry,
+    pub action: Option<&'a Action>,
+    name: &'a str,
+}
+
+impl<'a> Item<'a> {
+    /// Identifies the item across runs: the desktop file ID, followed by `:` and the action ID
+    /// for actions.
+    pub fn id(&self) -> String {
+        match self.action {
+            Some(action) => format!("{}:{}", self.entry.id, action.id),
+            None => self.entry.id.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn icon(&self) -> Option<&'a str> {
+        self.action
+            .and_then(|action| action.icon.as_deref())
+            .or(self.entry.icon.as_deref())
+    }
+}
+
+/// Every application that should be offered, read from the desktop files in the XDG data
+/// directories.
+pub struct Index {
+    entries: Vec<DesktopEntry>,
+    /// Every entry followed by its actions, as indices into `entries` and their `actions`.
+    items: Vec<(usize, Option<usize>)>,
+    /// The name of every item, which is matched against on every key press.
+    names: Vec<String>,
+    data_dirs: Vec<PathBuf>,
+    icons: RefCell<HashMap<String, Option<PathBuf>>>,
+}
+
+impl Index {
+    pub fn load() -> Index {
+        let data_dirs = data_dirs();
+        let locale = Locale::from_env();
+        let current_desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        let current_desktops = current_desktop.split(':').collect::<Vec<_>>();
+
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        for dir in &data_dirs {
+            let applications = dir.join("applications");
+            let mut files = Vec::new();
+            collect(&applications, &mut files);
+            files.sort();
+
+            for path in files {
+                // Desktop file IDs are relative paths with `/` replaced by `-`, and the first
+                // directory in which an ID is found hides it in every later one
+                let id = match path.strip_prefix(&applications) {
+                    Ok(relative) => relative.to_string_lossy().replace('/', "-"),
+                    Err(_) => continue,
+                };
+                if !seen.insert(id.clone()) {
+                    continue;
+                }
+                let contents = match fs::read_to_string(&path) {
+                    Ok(contents) => contents,
+                    Err(e) => {
+                        eprintln!("Skipping {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                match desktop::parse(&id, &path, &contents, &locale) {
+                    Ok(entry) if entry.hidden || entry.no_display => {}
+                    Ok(entry) if !entry.shown_in(&current_desktops) => {}
+                    Ok(entry) if !try_exec(&entry) => {}
+                    Ok(entry) => entries.push(entry),
+                    Err(desktop::DesktopError::NotAnApplication) => {}
+                    Err(e) => eprintln!("Skipping {}: {}", path.display(), e),
+                }
+            }
+        }
+        entries.sort_by_cached_key(|entry| entry.name.to_lowercase());
+        let items = entries
+            .iter()
+            .enumerate()
+            .flat_map(|(i, entry)| {
+                std::iter::once((i, None))
+                    .chain((0..entry.actions.len()).map(move |a| (i, Some(a))))
+            })
+            .collect::<Vec<_>>();
+        let names = items
+            .iter()
+            .map(|&(entry, action): &(usize, Option<usize>)| {
+                let entry = &entries[entry];
+                match action {
+                    Some(action) => format!("{}: {}", entry.name, entry.actions[action].name),
+                    None => entry.name.clone(),
+                }
+            })
+            .collect();
+
+        Index {
+            entries,
+            items,
+            names,
+            data_dirs,
+            icons: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn item(&self, index: usize) -> Item<'_> {
+        let (entry, action) = self.items[index];
+        let entry = &self.entries[entry];
+        Item {
+            entry,
+            action: action.map(|action| &entry.actions[action]),
+            name: &self.names[index],
+        }
+    }
+
+    /// Resolves an `Icon` value to a PNG file. Absolute paths are used as they are, while names
+    /// are looked up in the hicolor theme and then in `pixmaps`.
+    pub fn icon_path(&self, icon: &str) -> Option<PathBuf> {
+        self.icons
+            .borrow_mut()
+            .entry(icon.to_owned())
+            .or_insert_with(|| self.find_icon(icon))
+            .clone()
+    }
+
+    fn find_icon(&self, icon: &str) -> Option<PathBuf> {
+        let path = Path::new(icon);
+        if path.is_absolute() {
+            return Some(path.to_owned()).filter(|p| p.is_file());
+        }
+        let file = format!("{}.png", icon);
+        let themed = self.data_dirs.iter().flat_map(|dir| {
+            ICON_SIZES
+                .iter()
+                .map(move |size| dir.join("icons/hicolor").join(size).join("apps"))
+        });
+        let pixmaps = self.data_dirs.iter().map(|dir| dir.join("pixmaps"));
+        themed
+            .chain(pixmaps)
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ranked {
+    /// Index of the item in the `Index`.
+    pub item: usize,
+    pub score: i32,
+    /// Byte offsets of the characters in the item's name that matched the query.
+    pub positions: Vec<usize>,
+}
+
+/// The results for the query being typed. Typing more characters can only narrow the results
+/// down, so then only the previous results are scored again instead of the whole index.
+#[derive(Default)]
+pub struct Search {
+    query: String,
+    results: Vec<Ranked>,
+}
+
+impl Search {
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn results(&self) -> &[Ranked] {
+        &self.results
+    }
+
+    /// Ranks the items of `index` against `query`, best first, raising the ones often and
+    /// recently launched. An empty query lists every application, leaving out their actions.
+    pub fn update(&mut self, index: &Index, history: &History, query: &str) {
+        let narrowing = !self.query.trim().is_empty() && query.starts_with(self.query.as_str());
+        let candidates = if narrowing {
+            self.results.iter().map(|ranked| ranked.item).collect()
+        } else {
+            (0..index.items.len()).collect::<Vec<_>>()
+        };
+
+        let mut matcher = Matcher::new(query);
+        let now = history::unix_now();
+        let mut results = candidates
+            .into_iter()
+            .filter_map(|i| {
+                let item = index.item(i);
+                if matcher.is_empty() && item.action.is_some() {
+                    return None;
+                }
+                let (score, positions) = rank(&mut matcher, &item)?;
+                Some(Ranked {
+                    item: i,
+                    score: score + history.boost(&item.id(), query, now),
+                    positions,
+                })
+            })
+            .collect::<Vec<_>>();
+        // Items are in alphabetical order, so that breaks ties and orders the ones never launched
+        // when nothing has been typed
+        results.sort_by_key(|ranked| (std::cmp::Reverse(ranked.score), ranked.item));
+
+        self.query = query.to_owned();
+        self.results = results;
+    }
+}
+
+// Matches in the generic name, keywords or comment count for less than ones in the name, and
+// have nothing to highlight.
+fn rank(matcher: &mut Matcher, item: &Item) -> Option<(i32, Vec<usize>)> {
+    if let Some(m) = matcher.score(item.name()) {
+        return Some((m.score, m.positions));
+    }
+    let entry = item.entry;
+    let other = entry
+        .generic_name
+        .iter()
+        .chain(entry.keywords.iter())
+        .chain(entry.comment.iter())
+        .filter_map(|text| matcher.score(text))
+        .map(|m| m.score)
+        .max()?;
+    Some((other / 2, Vec::new()))
+}
+
+// Entries with `TryExec` are only shown if that program is installed.
+fn try_exec(entry: &DesktopEntry) -> bool {
+    let program = match &entry.try_exec {
+        Some(program) => Path::new(program),
+        None => return true,
+    };
+    if program.is_absolute() {
+        return program.is_file();
+    }
+    match std::env::var_os("PATH") {
+        Some(path) => std::env::split_paths(&path).any(|dir| dir.join(program).is_file()),
+        None => false,
+    }
+}
+
+fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    match std::env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(data) => dirs.push(PathBuf::from(data)),
+        None => dirs
+            .extend(std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))),
+    }
+    let system = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+    dirs.extend(system.split(':').map(PathBuf::from));
+    dirs
+}
+
+fn collect(dir: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            collect(&path, out);
+        } else if path.extension().and_then(|ext| ext.to_str()) == Some("desktop") {
+            out.push(path);
+        }
+    }
+}
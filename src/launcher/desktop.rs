@@ -0,0 +1,613 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const FIELD_CODES: [char; 8] = ['f', 'F', 'u', 'U', 'i', 'c', 'k', '%'];
+const DEPRECATED_FIELD_CODES: [char; 6] = ['d', 'D', 'n', 'N', 'v', 'm'];
+
+#[derive(Debug)]
+pub enum DesktopError {
+    Syntax {
+        line: usize,
+        message: &'static str,
+    },
+    MissingKey(&'static str),
+    InvalidExec(String),
+    /// Anything but `Type=Application`, such as links and directories.
+    NotAnApplication,
+}
+
+impl fmt::Display for DesktopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DesktopError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            DesktopError::MissingKey(key) => write!(f, "missing required key `{}`", key),
+            DesktopError::InvalidExec(e) => write!(f, "invalid Exec: {}", e),
+            DesktopError::NotAnApplication => write!(f, "not an application"),
+        }
+    }
+}
+
+impl std::error::Error for DesktopError {}
+
+/// The user's language, used to pick translated values such as `Name[sv]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Locale {
+    lang: String,
+    country: Option<String>,
+    modifier: Option<String>,
+}
+
+impl Locale {
+    /// Reads the locale the way gettext does, from `LC_ALL`, `LC_MESSAGES` and then `LANG`.
+    pub fn from_env() -> Locale {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map(|value| Locale::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// Parses `lang_COUNTRY.ENCODING@MODIFIER`, where everything but `lang` is optional.
+    pub fn parse(locale: &str) -> Locale {
+        let (rest, modifier) = match locale.find('@') {
+            Some(at) => (&locale[..at], Some(locale[at + 1..].to_owned())),
+            None => (locale, None),
+        };
+        let rest = rest.split('.').next().unwrap_or("");
+        let (lang, country) = match rest.find('_') {
+            Some(underscore) => (&rest[..underscore], Some(rest[underscore + 1..].to_owned())),
+            None => (rest, None),
+        };
+        match lang {
+            "C" | "POSIX" => Locale::default(),
+            _ => Locale {
+                lang: lang.to_owned(),
+                country,
+                modifier,
+            },
+        }
+    }
+
+    // The suffixes to look for, from most to least specific, as laid out by the desktop entry
+    // specification.
+    fn variants(&self) -> Vec<String> {
+        if self.lang.is_empty() {
+            return Vec::new();
+        }
+        let mut variants = Vec::new();
+        if let (Some(country), Some(modifier)) = (&self.country, &self.modifier) {
+            variants.push(format!("{}_{}@{}", self.lang, country, modifier));
+        }
+        if let Some(country) = &self.country {
+            variants.push(format!("{}_{}", self.lang, country));
+        }
+        if let Some(modifier) = &self.modifier {
+            variants.push(format!("{}@{}", self.lang, modifier));
+        }
+        variants.push(self.lang.clone());
+        variants
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Action {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: Vec<String>,
+}
+
+/// An application from a `.desktop` file. Field codes such as `%f` are left in `exec` to be
+/// expanded when launching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// The desktop file ID, e.g. `org.gnome.Nautilus.desktop`.
+    pub id: String,
+    pub path: PathBuf,
+    pub name: String,
+    pub generic_name: Option<String>,
+    pub comment: Option<String>,
+    pub keywords: Vec<String>,
+    pub icon: Option<String>,
+    pub exec: Vec<String>,
+    pub try_exec: Option<String>,
+    pub path_dir: Option<PathBuf>,
+    pub terminal: bool,
+    pub no_display: bool,
+    pub hidden: bool,
+    pub only_show_in: Vec<String>,
+    pub not_show_in: Vec<String>,
+    pub actions: Vec<Action>,
+}
+
+impl DesktopEntry {
+    /// Whether the entry is meant to be shown in a desktop named by `XDG_CURRENT_DESKTOP`, which
+    /// may list several names separated by `:`.
+    pub fn shown_in(&self, current_desktops: &[&str]) -> bool {
+        let current = |list: &[String]| list.iter().any(|d| current_desktops.contains(&d.as_str()));
+        if !self.only_show_in.is_empty() && !current(&self.only_show_in) {
+            return false;
+        }
+        !current(&self.not_show_in)
+    }
+}
+
+type Group = HashMap<String, String>;
+
+/// Parses the contents of a desktop file. `id` is the desktop file ID it was found under.
+pub fn parse(
+    id: &str,
+    path: &Path,
+    contents: &str,
+    locale: &Locale,
+) -> Result<DesktopEntry, DesktopError> {
+    let groups = parse_groups(contents)?;
+    let main = groups
+        .iter()
+        .find(|(name, _)| name == "Desktop Entry")
+        .map(|(_, group)| group)
+        .ok_or(DesktopError::Syntax {
+            line: 1,
+            message: "missing [Desktop Entry] group",
+        })?;
+
+    match main.get("Type").map(String::as_str) {
+        Some("Application") => {}
+        Some(_) => return Err(DesktopError::NotAnApplication),
+        None => return Err(DesktopError::MissingKey("Type")),
+    }
+    let string = |key| main.get(key).map(|v| unescape(v));
+    let translated = |key| localized(main, key, locale).map(|v| unescape(v));
+    let list = |key| main.get(key).map(|v| split_list(v)).unwrap_or_default();
+    let boolean = |key| main.get(key).map(String::as_str) == Some("true");
+
+    let hidden = boolean("Hidden");
+    let exec = match string("Exec") {
+        Some(exec) => split_exec(&exec)?,
+        None => Vec::new(),
+    };
+    // Entries without Exec are either D-Bus activated or only there to hide another entry
+    if exec.is_empty() && !hidden && !boolean("DBusActivatable") {
+        return Err(DesktopError::MissingKey("Exec"));
+    }
+
+    let mut actions = Vec::new();
+    for action in list("Actions") {
+        let group = match groups
+            .iter()
+            .find(|(name, _)| *name == format!("Desktop Action {}", action))
+        {
+            Some((_, group)) => group,
+            None => continue,
+        };
+        let exec = match group.get("Exec") {
+            Some(exec) => split_exec(&unescape(exec))?,
+            None => continue,
+        };
+        actions.push(Action {
+            name: localized(group, "Name", locale)
+                .map(|v| unescape(v))
+                .unwrap_or_else(|| action.clone()),
+            icon: group.get("Icon").map(|v| unescape(v)),
+            id: action,
+            exec,
+        });
+    }
+
+    Ok(DesktopEntry {
+        id: id.to_owned(),
+        path: path.to_owned(),
+        name: translated("Name").ok_or(DesktopError::MissingKey("Name"))?,
+        generic_name: translated("GenericName"),
+        comment: translated("Comment"),
+        keywords: localized(main, "Keywords", locale)
+            .map(|v| split_list(v))
+            .unwrap_or_default(),
+        icon: string("Icon"),
+        exec,
+        try_exec: string("TryExec"),
+        path_dir: string("Path").filter(|p| !p.is_empty()).map(PathBuf::from),
+        terminal: boolean("Terminal"),
+        no_display: boolean("NoDisplay"),
+        hidden,
+        only_show_in: list("OnlyShowIn"),
+        not_show_in: list("NotShowIn"),
+        actions,
+    })
+}
+
+// Groups in file order, with the raw, still escaped, value of every key. Comments and blank
+// lines are skipped.
+fn parse_groups(contents: &str) -> Result<Vec<(String, Group)>, DesktopError> {
+    let mut groups: Vec<(String, Group)> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let syntax = |message| DesktopError::Syntax {
+            line: i + 1,
+            message,
+        };
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(syntax("unterminated group header"));
+            }
+            let name = &line[1..line.len() - 1];
+            if groups.iter().any(|(existing, _)| existing == name) {
+                return Err(syntax("duplicate group"));
+            }
+            groups.push((name.to_owned(), Group::new()));
+            continue;
+        }
+
+        let eq = line
+            .find('=')
+            .ok_or_else(|| syntax("expected `key=value`"))?;
+        let (key, value) = (line[..eq].trim(), line[eq + 1..].trim());
+        match groups.last_mut() {
+            Some((_, group)) => {
+                group
+                    .entry(key.to_owned())
+                    .or_insert_with(|| value.to_owned());
+            }
+            None => return Err(syntax("key outside of a group")),
+        }
+    }
+    Ok(groups)
+}
+
+fn localized<'a>(group: &'a Group, key: &str, locale: &Locale) -> Option<&'a String> {
+    locale
+        .variants()
+        .iter()
+        .find_map(|variant| group.get(&format!("{}[{}]", key, variant)))
+        .or_else(|| group.get(key))
+}
+
+// Resolves the escapes allowed in string values.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// Splits a `;` separated list, where `\;` is a literal semicolon.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(other) => {
+                    current.push('\\');
+                    current.push(other);
+                }
+                None => current.push('\\'),
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut current))),
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        items.push(unescape(&current));
+    }
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Splits an already unescaped `Exec` value into arguments. Quoted arguments may contain spaces
+/// and escape `"`, `` ` ``, `$` and `\` with a backslash. Field codes are validated but kept, and
+/// deprecated ones are dropped. Literal percent signs are written as `%%` in the result.
+pub fn split_exec(exec: &str) -> Result<Vec<String>, DesktopError> {
+    let invalid = |message: &str| DesktopError::InvalidExec(message.to_owned());
+    let mut args = Vec::new();
+    let mut chars = exec.chars().peekable();
+
+    loop {
+        while chars.peek() == Some(&' ') {
+            chars.next();
+        }
+        let mut arg = String::new();
+        match chars.peek() {
+            None => break,
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ '"') | Some(c @ '`') | Some(c @ '$') | Some(c @ '\\') => {
+                                arg.push(c)
+                            }
+                            _ => return Err(invalid("invalid escape inside quotes")),
+                        },
+                        // Field codes aren't allowed inside quotes, so keep percent signs literal
+                        Some('%') => arg.push_str("%%"),
+                        Some(c) => arg.push(c),
+                        None => return Err(invalid("unterminated quote")),
+                    }
+                }
+                if matches!(chars.peek(), Some(c) if *c != ' ') {
+                    return Err(invalid("quote must end the argument"));
+                }
+            }
+            Some(_) => {
+                while let Some(&c) = chars.peek() {
+                    if c == ' ' {
+                        break;
+                    }
+                    chars.next();
+                    if c != '%' {
+                        arg.push(c);
+                        continue;
+                    }
+                    match chars.next() {
+                        Some(code) if FIELD_CODES.contains(&code) => {
+                            arg.push('%');
+                            arg.push(code);
+                        }
+                        Some(code) if DEPRECATED_FIELD_CODES.contains(&code) => {}
+                        Some(code) => {
+                            return Err(DesktopError::InvalidExec(format!(
+                                "unknown field code `%{}`",
+                                code
+                            )))
+                        }
+                        None => return Err(invalid("`%` at the end")),
+                    }
+                }
+                // An argument that only held deprecated field codes is dropped entirely
+                if arg.is_empty() {
+                    continue;
+                }
+            }
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIREFOX: &str = r#"
+# A comment before the first group
+[Desktop Entry]
+Type=Application
+Name=Firefox
+Name[de]=Feuerfuchs
+Name[de_DE]=Feuerfuchs Deutschland
+Name[de_DE@euro]=Feuerfuchs Euro
+Name[sv]=Eldräv
+GenericName=Web Browser
+GenericName[de]=Webbrowser
+Comment=Browse the\sWorld Wide Web
+Keywords=Internet;WWW;Browser\;Web;
+Keywords[de]=Internet;Netz;
+Icon=firefox
+Exec=firefox %u
+Terminal=false
+Actions=new-window;private;missing;
+
+[Desktop Action new-window]
+Name=New Window
+Name[de]=Neues Fenster
+Exec=firefox --new-window %u
+
+[Desktop Action private]
+Name=New Private Window
+Icon=firefox-private
+Exec=firefox --private-window %u
+"#;
+
+    fn parse_entry(contents: &str, locale: &str) -> Result<DesktopEntry, DesktopError> {
+        parse(
+            "test.desktop",
+            Path::new("/usr/share/applications/test.desktop"),
+            contents,
+            &Locale::parse(locale),
+        )
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(
+            Locale::parse("de_DE.UTF-8@euro"),
+            Locale {
+                lang: String::from("de"),
+                country: Some(String::from("DE")),
+                modifier: Some(String::from("euro")),
+            }
+        );
+        assert_eq!(Locale::parse("C.UTF-8"), Locale::default());
+        assert_eq!(
+            Locale::parse("de_DE@euro").variants(),
+            ["de_DE@euro", "de_DE", "de@euro", "de"]
+        );
+        assert_eq!(Locale::parse("sv.UTF-8").variants(), ["sv"]);
+    }
+
+    #[test]
+    fn localized_keys() {
+        let name = |locale| parse_entry(FIREFOX, locale).unwrap().name;
+        assert_eq!(name("de_DE.UTF-8@euro"), "Feuerfuchs Euro");
+        assert_eq!(name("de_DE.UTF-8"), "Feuerfuchs Deutschland");
+        assert_eq!(name("de_AT.UTF-8"), "Feuerfuchs");
+        assert_eq!(name("de_AT@euro"), "Feuerfuchs");
+        assert_eq!(name("sv_SE"), "Eldräv");
+        assert_eq!(name("fr_FR.UTF-8"), "Firefox");
+        assert_eq!(name("C"), "Firefox");
+
+        let entry = parse_entry(FIREFOX, "de_CH").unwrap();
+        assert_eq!(entry.generic_name.as_deref(), Some("Webbrowser"));
+        assert_eq!(entry.comment.as_deref(), Some("Browse the World Wide Web"));
+        assert_eq!(entry.keywords, strings(&["Internet", "Netz"]));
+    }
+
+    #[test]
+    fn lists_with_escaped_semicolons() {
+        let entry = parse_entry(FIREFOX, "C").unwrap();
+        assert_eq!(entry.keywords, strings(&["Internet", "WWW", "Browser;Web"]));
+        assert_eq!(
+            split_list(r"a\;b;;c\sd;\;e"),
+            strings(&["a;b", "c d", ";e"])
+        );
+    }
+
+    #[test]
+    fn actions() {
+        let entry = parse_entry(FIREFOX, "de_DE").unwrap();
+        assert_eq!(entry.exec, strings(&["firefox", "%u"]));
+        assert_eq!(
+            entry.actions,
+            [
+                Action {
+                    id: String::from("new-window"),
+                    name: String::from("Neues Fenster"),
+                    icon: None,
+                    exec: strings(&["firefox", "--new-window", "%u"]),
+                },
+                Action {
+                    id: String::from("private"),
+                    name: String::from("New Private Window"),
+                    icon: Some(String::from("firefox-private")),
+                    exec: strings(&["firefox", "--private-window", "%u"]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn exec_quoting() {
+        let exec = |line: &str| {
+            let contents = format!("[Desktop Entry]\nType=Application\nName=A\nExec={}\n", line);
+            parse_entry(&contents, "C").map(|entry| entry.exec)
+        };
+        assert_eq!(
+            exec(r#"sh -c "echo \\"a  b\\" \\$HOME \\`x\\` \\\\" %f"#).unwrap(),
+            strings(&["sh", "-c", r#"echo "a  b" $HOME `x` \"#, "%f"])
+        );
+        assert_eq!(
+            exec(r#"printf "100%" 50%% %d %U"#).unwrap(),
+            strings(&["printf", "100%%", "50%%", "%U"])
+        );
+        assert_eq!(
+            exec(r"app\sname --flag").unwrap(),
+            strings(&["app", "name", "--flag"])
+        );
+        assert!(matches!(
+            exec(r#"app "unterminated"#),
+            Err(DesktopError::InvalidExec(_))
+        ));
+        assert!(matches!(
+            exec(r#"app "a"b"#),
+            Err(DesktopError::InvalidExec(_))
+        ));
+        assert!(matches!(
+            exec(r#"app "\\n""#),
+            Err(DesktopError::InvalidExec(_))
+        ));
+        assert!(matches!(exec("app %z"), Err(DesktopError::InvalidExec(_))));
+        assert!(matches!(exec("app %"), Err(DesktopError::InvalidExec(_))));
+    }
+
+    #[test]
+    fn visibility() {
+        let entry = parse_entry(
+            "[Desktop Entry]\nType=Application\nName=Settings\nExec=settings\nNoDisplay=true\n\
+             OnlyShowIn=GNOME;Unity;\n",
+            "C",
+        )
+        .unwrap();
+        assert!(entry.no_display);
+        assert!(!entry.hidden);
+        assert!(entry.shown_in(&["ubuntu", "GNOME"]));
+        assert!(!entry.shown_in(&["KDE"]));
+        assert!(!entry.shown_in(&[]));
+
+        let entry = parse_entry(
+            "[Desktop Entry]\nType=Application\nName=Tool\nExec=tool\nNotShowIn=KDE;\n",
+            "C",
+        )
+        .unwrap();
+        assert!(!entry.no_display);
+        assert!(entry.shown_in(&["GNOME"]));
+        assert!(entry.shown_in(&[]));
+        assert!(!entry.shown_in(&["sway", "KDE"]));
+
+        // Hiding an entry of the same ID needs no Exec
+        let entry = parse_entry(
+            "[Desktop Entry]\nType=Application\nName=Gone\nHidden=true\n",
+            "C",
+        )
+        .unwrap();
+        assert!(entry.hidden);
+        assert!(entry.exec.is_empty());
+    }
+
+    #[test]
+    fn invalid_entries() {
+        let error = |contents| parse_entry(contents, "C").unwrap_err();
+        assert!(matches!(
+            error("[Desktop Entry]\nType=Link\nName=Site\nURL=https://example.com\n"),
+            DesktopError::NotAnApplication
+        ));
+        assert!(matches!(
+            error("[Desktop Entry]\nType=Application\nExec=app\n"),
+            DesktopError::MissingKey("Name")
+        ));
+        assert!(matches!(
+            error("[Desktop Entry]\nType=Application\nName=App\n"),
+            DesktopError::MissingKey("Exec")
+        ));
+        assert!(matches!(
+            error("[Desktop Entry]\nName=App\n"),
+            DesktopError::MissingKey("Type")
+        ));
+        assert!(matches!(
+            error("Name=App\n"),
+            DesktopError::Syntax { line: 1, .. }
+        ));
+        assert!(matches!(
+            error("[Desktop Entry]\nType=Application\n[Desktop Entry\n"),
+            DesktopError::Syntax { line: 3, .. }
+        ));
+        assert!(matches!(
+            error("[Desktop Entry]\n\nName App\n"),
+            DesktopError::Syntax { line: 3, .. }
+        ));
+        assert!(matches!(
+            error("[Other]\nName=App\n"),
+            DesktopError::Syntax { line: 1, .. }
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+use super::Item;
+use std::fmt;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug)]
+pub enum ExecError {
+    /// Nothing to run, such as an `Exec` of nothing but field codes.
+    EmptyCommand,
+    Spawn {
+        program: String,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::EmptyCommand => write!(f, "there is no command to run"),
+            ExecError::Spawn { program, error } => {
+                write!(f, "could not start `{}`: {}", program, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+/// Starts desktop entries the way the desktop entry specification describes, detached from the
+/// launcher so that they keep running after it exits.
+pub struct Executor {
+    /// Command that runs the arguments following it in a terminal, for `Terminal=true` entries.
+    terminal: Vec<String>,
+}
+
+impl Executor {
+    /// `terminal` is used as given when not empty, otherwise `$TERMINAL -e` or `xterm -e`.
+    pub fn new(terminal: &[String]) -> Executor {
+        let terminal = if terminal.is_empty() {
+            let program = std::env::var("TERMINAL")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| String::from("xterm"));
+            vec![program, String::from("-e")]
+        } else {
+            terminal.to_vec()
+        };
+        Executor { terminal }
+    }
+
+    /// Starts `item`, handing it `activation_token` so that the compositor lets it take the
+    /// focus.
+    pub fn launch(&self, item: &Item, activation_token: Option<&str>) -> Result<(), ExecError> {
+        let mut args = expand(item);
+        if item.entry.terminal {
+            args.splice(0..0, self.terminal.iter().cloned());
+        }
+        self.spawn(&args, item.entry.path_dir.as_deref(), activation_token)
+    }
+
+    /// Runs a command line typed by the user with `sh`.
+    pub fn run(&self, command_line: &str, activation_token: Option<&str>) -> Result<(), ExecError> {
+        if command_line.trim().is_empty() {
+            return Err(ExecError::EmptyCommand);
+        }
+        let args = [
+            String::from("sh"),
+            String::from("-c"),
+            command_line.to_owned(),
+        ];
+        self.spawn(&args, None, activation_token)
+    }
+
+    fn spawn(
+        &self,
+        args: &[String],
+        dir: Option<&Path>,
+        activation_token: Option<&str>,
+    ) -> Result<(), ExecError> {
+        if args.is_empty() {
+            return Err(ExecError::EmptyCommand);
+        }
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]).stdin(Stdio::null());
+        if let Some(dir) = dir {
+            command.current_dir(dir);
+        }
+        // A token the launcher was started with has been used up, so children only ever get a
+        // fresh one
+        for var in ["XDG_ACTIVATION_TOKEN", "DESKTOP_STARTUP_ID"].iter() {
+            match activation_token {
+                Some(token) => command.env(var, token),
+                None => command.env_remove(var),
+            };
+        }
+        // A new session leaves the child out of the launcher's process group, so it isn't sent
+        // the signals meant for the launcher, such as the terminal's SIGHUP. The signals that the
+        // launcher blocks to read them itself are unblocked again.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                let mut mask = std::mem::zeroed();
+                libc::sigemptyset(&mut mask);
+                match libc::pthread_sigmask(libc::SIG_SETMASK, &mask, std::ptr::null_mut()) {
+                    0 => Ok(()),
+                    e => Err(io::Error::from_raw_os_error(e)),
+                }
+            });
+        }
+
+        let mut child = command.spawn().map_err(|error| ExecError::Spawn {
+            program: args[0].clone(),
+            error,
+        })?;
+        // Waiting on the child keeps it from becoming a zombie once it exits while the launcher
+        // is still running
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// The arguments of `Exec` with their field codes expanded. Nothing is ever opened with the
+/// application, so the file and URL codes are removed.
+fn expand(item: &Item) -> Vec<String> {
+    let exec = match item.action {
+        Some(action) => &action.exec,
+        None => &item.entry.exec,
+    };
+    let mut args = Vec::with_capacity(exec.len());
+    for arg in exec {
+        match arg.as_str() {
+            "%f" | "%F" | "%u" | "%U" => {}
+            // The only code that expands to two arguments, and only when it stands alone
+            "%i" => {
+                if let Some(icon) = item.icon() {
+                    args.push(String::from("--icon"));
+                    args.push(icon.to_owned());
+                }
+            }
+            _ => args.push(expand_arg(arg, item)),
+        }
+    }
+    args
+}
+
+fn expand_arg(arg: &str, item: &Item) -> String {
+    let mut expanded = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('c') => expanded.push_str(&item.entry.name),
+            Some('k') => expanded.push_str(&item.entry.path.to_string_lossy()),
+            // `split_exec` only keeps valid codes, and the rest expand to nothing here
+            _ => {}
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launcher::desktop::{self, DesktopEntry, Locale};
+
+    const EDITOR: &str = r#"
+[Desktop Entry]
+Type=Application
+Name=Editor
+Icon=editor
+Exec=editor --name %c --desktop-file %k %f %F %u %U --icon-of=%i 100%% "100% sure" "two words" "%u" %i
+Actions=plain;
+
+[Desktop Action plain]
+Name=Plain Window
+Icon=editor-plain
+Exec=editor --plain %U %i
+"#;
+
+    fn parse_entry(contents: &str) -> DesktopEntry {
+        desktop::parse(
+            "editor.desktop",
+            Path::new("/usr/share/applications/editor.desktop"),
+            contents,
+            &Locale::default(),
+        )
+        .unwrap()
+    }
+
+    fn expand_item(entry: &DesktopEntry, action: Option<&str>) -> Vec<String> {
+        let action = action.map(|id| entry.actions.iter().find(|a| a.id == id).unwrap());
+        expand(&Item {
+            entry,
+            action,
+            name: &entry.name,
+        })
+    }
+
+    #[test]
+    fn field_codes() {
+        let entry = parse_entry(EDITOR);
+        assert_eq!(
+            expand_item(&entry, None),
+            [
+                "editor",
+                "--name",
+                "Editor",
+                "--desktop-file",
+                "/usr/share/applications/editor.desktop",
+                "--icon-of=",
+                "100%",
+                "100% sure",
+                "two words",
+                "%u",
+                "--icon",
+                "editor",
+            ]
+        );
+        assert_eq!(
+            expand_item(&entry, Some("plain")),
+            ["editor", "--plain", "--icon", "editor-plain"]
+        );
+    }
+
+    #[test]
+    fn missing_icon() {
+        let entry = parse_entry(&EDITOR.replace("Icon=editor\n", ""));
+        assert_eq!(
+            expand_item(&entry, None)[5..],
+            ["--icon-of=", "100%", "100% sure", "two words", "%u"]
+        );
+        assert_eq!(
+            expand_item(&entry, Some("plain")),
+            ["editor", "--plain", "--icon", "editor-plain"]
+        );
+
+        // Actions without an icon of their own have the application's
+        let entry = parse_entry(&EDITOR.replace("Icon=editor-plain\n", ""));
+        assert_eq!(
+            expand_item(&entry, Some("plain")),
+            ["editor", "--plain", "--icon", "editor"]
+        );
+    }
+}
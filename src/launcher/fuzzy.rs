@@ -0,0 +1,361 @@
+const SCORE_MATCH: i32 = 16;
+const GAP_START: i32 = -3;
+const GAP_EXTENSION: i32 = -1;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CAMEL: i32 = 7;
+const BONUS_CONSECUTIVE: i32 = 4;
+// The first character of the pattern decides where the match starts, so its bonus counts more
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+// No valid alignment ends here.
+const NONE: i32 = i32::MIN / 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub score: i32,
+    /// Byte offsets of the matched characters in the candidate, in increasing order.
+    pub positions: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Lower,
+    Upper,
+    Digit,
+    Other,
+}
+
+fn class(c: char) -> Class {
+    if c.is_lowercase() {
+        Class::Lower
+    } else if c.is_uppercase() {
+        Class::Upper
+    } else if c.is_numeric() {
+        Class::Digit
+    } else if c.is_alphabetic() {
+        // Scripts without case
+        Class::Lower
+    } else {
+        Class::Other
+    }
+}
+
+// Matches right after a separator, at a lower to upper case change or where digits start are
+// more likely to be what was meant than ones in the middle of a word.
+fn bonus(previous: Class, current: Class) -> i32 {
+    match (previous, current) {
+        (Class::Other, Class::Other) => 0,
+        (Class::Other, _) => BONUS_BOUNDARY,
+        (Class::Lower, Class::Upper) => BONUS_CAMEL,
+        (Class::Lower, Class::Digit) | (Class::Upper, Class::Digit) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+fn fold(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else if c.is_ascii() {
+        c.to_ascii_lowercase()
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+/// Scores candidates against a pattern whose characters must all appear in order, though not
+/// necessarily next to each other. The pattern is case sensitive only if it has upper case
+/// characters in it. Buffers are kept between candidates so that scoring doesn't allocate.
+pub struct Matcher {
+    pattern: Vec<char>,
+    case_sensitive: bool,
+    chars: Vec<(usize, char)>,
+    bonuses: Vec<i32>,
+    bounds: Vec<(usize, usize)>,
+    scores: Vec<i32>,
+    consecutive: Vec<bool>,
+}
+
+impl Matcher {
+    pub fn new(pattern: &str) -> Matcher {
+        let case_sensitive = pattern.chars().any(char::is_uppercase);
+        Matcher {
+            pattern: pattern
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| fold(c, case_sensitive))
+                .collect(),
+            case_sensitive,
+            chars: Vec::new(),
+            bonuses: Vec::new(),
+            bounds: Vec::new(),
+            scores: Vec::new(),
+            consecutive: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+
+    /// The best scoring way to match the pattern in `candidate`, or None if it doesn't contain
+    /// the pattern at all. An empty pattern matches everything with a score of 0.
+    pub fn score(&mut self, candidate: &str) -> Option<Match> {
+        if self.pattern.is_empty() {
+            return Some(Match {
+                score: 0,
+                positions: Vec::new(),
+            });
+        }
+
+        // Most candidates don't match at all, so rule those out before doing any real work
+        let case_sensitive = self.case_sensitive;
+        let mut pattern = self.pattern.iter().peekable();
+        for c in candidate.chars() {
+            match pattern.peek() {
+                Some(&&p) if p == fold(c, case_sensitive) => {
+                    pattern.next();
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        if pattern.peek().is_some() {
+            return None;
+        }
+
+        self.chars.clear();
+        self.bonuses.clear();
+        let mut previous = Class::Other;
+        for (offset, c) in candidate.char_indices() {
+            let current = class(c);
+            self.bonuses.push(bonus(previous, current));
+            self.chars.push((offset, fold(c, case_sensitive)));
+            previous = current;
+        }
+
+        // Pattern character i can only be matched between where it is first found after the
+        // earlier characters, and where it is last found before the later ones.
+        self.bounds.clear();
+        let mut j = 0;
+        for &p in &self.pattern {
+            j += self.chars[j..].iter().position(|&(_, c)| c == p)?;
+            self.bounds.push((j, 0));
+            j += 1;
+        }
+        let mut end = self.chars.len();
+        for (i, &p) in self.pattern.iter().enumerate().rev() {
+            end = self.chars[..end].iter().rposition(|&(_, c)| c == p)?;
+            self.bounds[i].1 = end;
+        }
+
+        let (rows, columns) = (self.pattern.len(), self.chars.len());
+        self.scores.clear();
+        self.scores.resize(rows * columns, NONE);
+        self.consecutive.clear();
+        self.consecutive.resize(rows * columns, false);
+
+        // scores[i * columns + j] is the best score of matching the first i + 1 pattern
+        // characters with the last one at candidate character j
+        for (i, &p) in self.pattern.iter().enumerate() {
+            let row = i * columns;
+            let (first, last) = self.bounds[i];
+            // Cells of the previous row are only valid from its first bound on
+            let start = if i > 0 {
+                self.bounds[i - 1].0 + 1
+            } else {
+                first
+            };
+            let mut gapped = NONE;
+            for j in start..=last {
+                let mut from_gap = NONE;
+                let mut from_previous = NONE;
+                if i > 0 && j > 0 {
+                    // Best way to have matched the previous pattern character two or more
+                    // characters back, paying for the characters skipped in between
+                    if j > 1 {
+                        gapped = (gapped + GAP_EXTENSION)
+                            .max(self.scores[row - columns + j - 2] + GAP_START);
+                    }
+                    from_gap = gapped;
+                    from_previous = self.scores[row - columns + j - 1];
+                }
+                if self.chars[j].1 != p {
+                    continue;
+                }
+
+                let bonus = self.bonuses[j];
+                let score = if i == 0 {
+                    bonus * BONUS_FIRST_CHAR_MULTIPLIER
+                } else if from_previous > NONE
+                    && from_previous + bonus.max(BONUS_CONSECUTIVE) >= from_gap + bonus
+                {
+                    self.consecutive[row + j] = true;
+                    from_previous + bonus.max(BONUS_CONSECUTIVE)
+                } else if from_gap > NONE {
+                    from_gap + bonus
+                } else {
+                    continue;
+                };
+                self.scores[row + j] = score + SCORE_MATCH;
+            }
+        }
+
+        let last = (rows - 1) * columns;
+        let (first, end) = self.bounds[rows - 1];
+        let (mut j, score) = (first..=end)
+            .map(|j| (j, self.scores[last + j]))
+            .filter(|(_, score)| *score > NONE)
+            .max_by_key(|(j, score)| (*score, std::cmp::Reverse(*j)))?;
+
+        // Walk back through the table to find which characters made up the best alignment
+        let mut positions = vec![0; rows];
+        for i in (0..rows).rev() {
+            positions[i] = self.chars[j].0;
+            if i == 0 {
+                break;
+            }
+            let row = i * columns;
+            if self.consecutive[row + j] {
+                j -= 1;
+                continue;
+            }
+            let target = self.scores[row + j] - SCORE_MATCH - self.bonuses[j];
+            j = (0..j - 1)
+                .rev()
+                .find(|&k| {
+                    let gap = (j - k - 1) as i32;
+                    self.scores[row - columns + k] + GAP_START + GAP_EXTENSION * (gap - 1) == target
+                })
+                .unwrap_or(j - 1);
+        }
+        Some(Match { score, positions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Xorshift, so that the generated cases are the same on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+
+        // Strings over a small alphabet, so that patterns often match, with upper case, digits,
+        // separators and multi-byte characters in it.
+        fn string(&mut self, max: usize) -> String {
+            const ALPHABET: [char; 14] = [
+                'a', 'b', 'c', 'A', 'B', '1', ' ', '-', '_', 'é', 'É', 'ß', '字', '😀',
+            ];
+            (0..self.next(max + 1))
+                .map(|_| ALPHABET[self.next(ALPHABET.len())])
+                .collect()
+        }
+
+        fn word(&mut self, max: usize) -> String {
+            (0..1 + self.next(max))
+                .map(|_| (b'a' + self.next(3) as u8) as char)
+                .collect()
+        }
+    }
+
+    // Whether the pattern's characters appear in order in `candidate`, folded as the matcher
+    // folds them.
+    fn is_subsequence(pattern: &str, candidate: &str) -> bool {
+        let case_sensitive = pattern.chars().any(char::is_uppercase);
+        let mut candidate = candidate.chars().map(|c| fold(c, case_sensitive));
+        pattern
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .all(|p| candidate.any(|c| c == fold(p, case_sensitive)))
+    }
+
+    #[test]
+    fn matches_exactly_the_subsequences() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20_000 {
+            let (pattern, candidate) = (rng.string(4), rng.string(16));
+            let m = Matcher::new(&pattern).score(&candidate);
+            assert_eq!(
+                m.is_some(),
+                is_subsequence(&pattern, &candidate),
+                "{:?} in {:?}",
+                pattern,
+                candidate
+            );
+        }
+    }
+
+    #[test]
+    fn positions_are_the_matched_characters_in_order() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20_000 {
+            let (pattern, candidate) = (rng.string(4), rng.string(16));
+            let mut matcher = Matcher::new(&pattern);
+            let m = match matcher.score(&candidate) {
+                Some(m) => m,
+                None => continue,
+            };
+            let context = format!("{:?} in {:?}: {:?}", pattern, candidate, m);
+            assert_eq!(m.positions.len(), matcher.pattern.len(), "{}", context);
+            assert!(m.positions.windows(2).all(|w| w[0] < w[1]), "{}", context);
+            for (&position, &p) in m.positions.iter().zip(&matcher.pattern) {
+                assert!(candidate.is_char_boundary(position), "{}", context);
+                let c = candidate[position..].chars().next().unwrap();
+                assert_eq!(fold(c, matcher.case_sensitive), p, "{}", context);
+            }
+        }
+    }
+
+    #[test]
+    fn prefix_scores_at_least_as_well() {
+        let mut rng = Rng(0x1234_5678_9abc_def1);
+        for _ in 0..20_000 {
+            let pattern = rng.word(4);
+            let rest = rng.string(8);
+            let prefixed = format!("{}{}", pattern, rest);
+            let inner = format!("{}{}{}", rng.word(4), pattern, rest);
+            let mut matcher = Matcher::new(&pattern);
+            let prefix = matcher.score(&prefixed).unwrap().score;
+            let other = matcher.score(&inner).unwrap().score;
+            assert!(
+                prefix >= other,
+                "{:?} scores {} in {:?} but {} in {:?}",
+                pattern,
+                prefix,
+                prefixed,
+                other,
+                inner
+            );
+        }
+    }
+
+    #[test]
+    fn ranking() {
+        let score = |pattern, candidate| Matcher::new(pattern).score(candidate).unwrap().score;
+        // Word starts and camel case humps over the middle of words
+        assert!(score("fb", "Foo Bar") > score("fb", "fabric"));
+        assert!(score("fb", "FooBar") > score("fb", "fabric"));
+        // Consecutive characters over scattered ones
+        assert!(score("term", "Terminal") > score("term", "Tele Remote"));
+        assert_eq!(
+            Matcher::new("fb").score("Foo Bar").unwrap().positions,
+            [0, 4]
+        );
+        // Upper case in the pattern makes it case sensitive
+        assert!(Matcher::new("Fb").score("foo bar").is_none());
+        assert!(Matcher::new("fb").score("FOO BAR").is_some());
+        // Whitespace in the pattern is ignored, and an empty pattern matches anything
+        assert_eq!(
+            Matcher::new("f b").score("Foo Bar").unwrap().positions,
+            [0, 4]
+        );
+        assert_eq!(Matcher::new(" ").score("x").unwrap().score, 0);
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const APP_DIR: &str = "infolauncher";
+const FILE_NAME: &str = "history.tsv";
+const HEADER: &str = "# infolauncher history v1";
+
+// Once the ranks add up to more than this, all of them are scaled down by `DECAY` and the ones
+// that drop below 1 are forgotten, which keeps the file small and lets old habits fade.
+const MAX_TOTAL_RANK: f64 = 1000.0;
+const DECAY: f64 = 0.9;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+// How much frecency counts for next to the fuzzy match score, on a logarithmic scale so that a
+// favourite can't bury a much better match.
+const FRECENCY_WEIGHT: f64 = 12.0;
+// Added when the query is the start of the one last used to launch the item.
+const QUERY_BONUS: i32 = 24;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Launch count, scaled down every time the history outgrows its cap.
+    pub rank: f64,
+    /// Seconds since the unix epoch.
+    pub last_launch: u64,
+    /// What was typed when the item was last launched.
+    pub last_query: String,
+}
+
+impl Record {
+    /// Frequency weighted by recency, so that something launched a lot last month is passed by
+    /// something launched a few times today.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_launch);
+        let recency = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+        self.rank * recency
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    /// Neither `XDG_STATE_HOME` nor `HOME` is set.
+    NoStateDir,
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryError::NoStateDir => {
+                write!(f, "no place to keep history, set XDG_STATE_HOME or HOME")
+            }
+            HistoryError::Io(path, e) => write!(f, "could not write {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+/// What has been launched before, kept in `$XDG_STATE_HOME/infolauncher/history.tsv` with one
+/// tab separated line of ID, rank, last launch and last query per item.
+#[derive(Debug, Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    records: HashMap<String, Record>,
+}
+
+impl History {
+    /// Reads the history file. A missing file is an empty history, and one that can't be made
+    /// sense of is moved aside to `history.tsv.corrupt` so that launching still works.
+    pub fn load() -> History {
+        let path = state_dir().map(|dir| dir.join(APP_DIR).join(FILE_NAME));
+        let records = match &path {
+            Some(path) => read(path),
+            None => HashMap::new(),
+        };
+        History { path, records }
+    }
+
+    /// Every record, most frecent first.
+    pub fn records(&self) -> Vec<(&str, &Record)> {
+        let now = unix_now();
+        let mut records = self
+            .records
+            .iter()
+            .map(|(id, record)| (id.as_str(), record))
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| {
+            b.1.frecency(now)
+                .partial_cmp(&a.1.frecency(now))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(b.0))
+        });
+        records
+    }
+
+    /// How much to raise the score of the item `id` when searching for `query`.
+    pub fn boost(&self, id: &str, query: &str, now: u64) -> i32 {
+        let record = match self.records.get(id) {
+            Some(record) => record,
+            None => return 0,
+        };
+        let mut boost = (FRECENCY_WEIGHT * record.frecency(now).ln_1p()) as i32;
+        let query = query.trim().to_lowercase();
+        if !query.is_empty() && record.last_query.to_lowercase().starts_with(&query) {
+            boost += QUERY_BONUS;
+        }
+        boost
+    }
+
+    /// Remembers that `id` was launched after typing `query`, and saves the history.
+    pub fn record(&mut self, id: &str, query: &str) -> Result<(), HistoryError> {
+        let now = unix_now();
+        let record = self.records.entry(id.to_owned()).or_insert(Record {
+            rank: 0.0,
+            last_launch: now,
+            last_query: String::new(),
+        });
+        record.rank += 1.0;
+        record.last_launch = now;
+        record.last_query = query.trim().to_owned();
+
+        if self.records.values().map(|r| r.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            for record in self.records.values_mut() {
+                record.rank *= DECAY;
+            }
+            // The item just launched stays even if this was its first launch
+            self.records
+                .retain(|key, record| key == id || record.rank >= 1.0);
+        }
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> Result<(), HistoryError> {
+        self.records.clear();
+        let path = self.path.as_ref().ok_or(HistoryError::NoStateDir)?;
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(HistoryError::Io(path.clone(), e)),
+            _ => Ok(()),
+        }
+    }
+
+    // Writes to a temporary file next to the real one and renames it over it, so that a crash
+    // or a second instance saving at the same time never leaves a half written file behind.
+    fn save(&self) -> Result<(), HistoryError> {
+        let path = self.path.as_ref().ok_or(HistoryError::NoStateDir)?;
+        let tmp = path.with_file_name(format!("{}.{}.tmp", FILE_NAME, std::process::id()));
+        let write = || -> io::Result<()> {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+            writeln!(file, "{}", HEADER)?;
+            for (id, record) in self.records() {
+                writeln!(
+                    file,
+                    "{}\t{}\t{}\t{}",
+                    sanitize(id),
+                    record.rank,
+                    record.last_launch,
+                    sanitize(&record.last_query)
+                )?;
+            }
+            file.into_inner()?.sync_all()?;
+            fs::rename(&tmp, path)
+        };
+        write().map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            HistoryError::Io(path.clone(), e)
+        })
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn read(path: &Path) -> HashMap<String, Record> {
+    let mut records = HashMap::new();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return records,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            set_aside(path, "it is not valid UTF-8");
+            return records;
+        }
+        Err(e) => {
+            eprintln!("Could not read history {}: {}", path.display(), e);
+            return records;
+        }
+    };
+    let mut lines = contents.lines().enumerate();
+    if lines.next().map(|(_, header)| header) != Some(HEADER) && !contents.is_empty() {
+        set_aside(path, "its header is missing");
+        return records;
+    }
+
+    // A single bad line is most likely from a partial edit by hand, so only that line is lost
+    for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+        match parse_line(line) {
+            Some((id, record)) => {
+                records.insert(id.to_owned(), record);
+            }
+            None => eprintln!(
+                "Ignoring malformed line {} of {}",
+                number + 1,
+                path.display()
+            ),
+        }
+    }
+    records
+}
+
+fn parse_line(line: &str) -> Option<(&str, Record)> {
+    let mut fields = line.splitn(4, '\t');
+    let id = fields.next().filter(|id| !id.is_empty())?;
+    let rank = fields
+        .next()?
+        .parse::<f64>()
+        .ok()
+        .filter(|rank| rank.is_finite() && *rank > 0.0)?;
+    let last_launch = fields.next()?.parse().ok()?;
+    let last_query = fields.next().unwrap_or("").to_owned();
+    Some((
+        id,
+        Record {
+            rank,
+            last_launch,
+            last_query,
+        },
+    ))
+}
+
+fn set_aside(path: &Path, reason: &str) {
+    let corrupt = path.with_file_name(format!("{}.corrupt", FILE_NAME));
+    match fs::rename(path, &corrupt) {
+        Ok(()) => eprintln!(
+            "History {} was moved to {} because {}, starting a new one",
+            path.display(),
+            corrupt.display(),
+            reason
+        ),
+        Err(e) => eprintln!("Ignoring history {}: {}", path.display(), e),
+    }
+}
+
+// Tabs and newlines would split the field, and are never typed into the launcher anyway.
+fn sanitize(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+fn state_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_STATE_HOME").filter(|v| !v.is_empty()) {
+        Some(state) => Some(PathBuf::from(state)),
+        None => std::env::var_os("HOME")
+            .filter(|v| !v.is_empty())
+            .map(|home| PathBuf::from(home).join(".local/state")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A history file in a directory of the temporary directory unique to the test, so that
+    // setting it aside doesn't clash with another test.
+    fn history_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("infolauncher-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(FILE_NAME)
+    }
+
+    fn record(rank: f64, last_launch: u64, last_query: &str) -> Record {
+        Record {
+            rank,
+            last_launch,
+            last_query: last_query.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_lines() {
+        let cases = [
+            (
+                "firefox.desktop\t3\t100\tfire",
+                Some(("firefox.desktop", record(3.0, 100, "fire"))),
+            ),
+            (
+                "firefox.desktop\t0.5\t100",
+                Some(("firefox.desktop", record(0.5, 100, ""))),
+            ),
+            (
+                "firefox.desktop\t1\t100\ta\tb",
+                Some(("firefox.desktop", record(1.0, 100, "a\tb"))),
+            ),
+            ("\t1\t100\tfire", None),
+            ("firefox.desktop\t0\t100\tfire", None),
+            ("firefox.desktop\t-1\t100\tfire", None),
+            ("firefox.desktop\tNaN\t100\tfire", None),
+            ("firefox.desktop\tinf\t100\tfire", None),
+            ("firefox.desktop\t1\tyesterday\tfire", None),
+            ("firefox.desktop\t1", None),
+            ("firefox.desktop", None),
+        ];
+        for (line, expected) in cases.iter() {
+            assert_eq!(&parse_line(line), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn read_skips_bad_lines() {
+        let path = history_path("read");
+        let contents = format!(
+            "{}\na.desktop\t2\t100\tq\nbroken\n\nb.desktop\t1\t200\n",
+            HEADER
+        );
+        fs::write(&path, contents).unwrap();
+        let records = read(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records["a.desktop"], record(2.0, 100, "q"));
+        assert_eq!(records["b.desktop"], record(1.0, 200, ""));
+    }
+
+    #[test]
+    fn bad_header_is_set_aside() {
+        let path = history_path("header");
+        fs::write(&path, "a.desktop\t2\t100\tq\n").unwrap();
+        let records = read(&path);
+        let moved = !path.exists();
+        let corrupt = fs::read_to_string(path.with_file_name("history.tsv.corrupt"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert!(records.is_empty());
+        assert!(moved);
+        assert_eq!(corrupt.unwrap(), "a.desktop\t2\t100\tq\n");
+    }
+
+    #[test]
+    fn cap_decays_and_forgets() {
+        let path = history_path("cap");
+        let mut history = History {
+            path: Some(path.clone()),
+            records: HashMap::new(),
+        };
+        history
+            .records
+            .insert("big.desktop".to_owned(), record(998.5, 100, ""));
+        history
+            .records
+            .insert("old.desktop".to_owned(), record(1.05, 100, ""));
+        history.record("new.desktop", " ne ").unwrap();
+        let saved = read(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(history.records.len(), 2);
+        assert!((history.records["big.desktop"].rank - 998.5 * DECAY).abs() < 1e-9);
+        assert_eq!(history.records["new.desktop"].rank, DECAY);
+        assert_eq!(history.records["new.desktop"].last_query, "ne");
+        assert!(!history.records.contains_key("old.desktop"));
+        assert_eq!(saved, history.records);
+    }
+
+    #[test]
+    fn boost_prefers_the_last_query() {
+        let now = 10 * WEEK;
+        let mut history = History::default();
+        history
+            .records
+            .insert("firefox.desktop".to_owned(), record(3.0, now, "Fire"));
+        let base = history.boost("firefox.desktop", "", now);
+
+        assert!(base > 0);
+        assert_eq!(
+            history.boost("firefox.desktop", "fi", now),
+            base + QUERY_BONUS
+        );
+        assert_eq!(
+            history.boost("firefox.desktop", " FIRE ", now),
+            base + QUERY_BONUS
+        );
+        assert_eq!(history.boost("firefox.desktop", "fox", now), base);
+        assert_eq!(history.boost("firefox.desktop", "firefox", now), base);
+        assert_eq!(history.boost("other.desktop", "fi", now), 0);
+        assert!(history.boost("firefox.desktop", "", now + 2 * WEEK) < base);
+    }
+
+    #[test]
+    fn records_by_frecency() {
+        let now = unix_now();
+        let mut history = History::default();
+        for (id, rank, age) in [
+            ("often.desktop", 10.0, 2 * DAY),
+            ("today.desktop", 2.0, 0),
+            ("stale.desktop", 5.0, 2 * WEEK),
+            ("also-stale.desktop", 5.0, 3 * WEEK),
+        ]
+        .iter()
+        {
+            history
+                .records
+                .insert(id.to_string(), record(*rank, now - age, ""));
+        }
+        let ids = history
+            .records()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            ids,
+            [
+                "today.desktop",
+                "often.desktop",
+                "also-stale.desktop",
+                "stale.desktop"
+            ]
+        );
+    }
+}
@@ -1,21 +1,125 @@
-#[macro_use]
-extern crate lazy_static;
 #[macro_use(event_enum)]
 extern crate wayland_client;
-use std::path::Path;
+use std::process::exit;
 
+mod cli;
+mod config;
 mod draw;
+mod input;
+mod launcher;
+mod ui;
 
 fn main() {
-    assert!(Path::new("src/draw/shader/test_vert.spv").exists());
-    assert!(Path::new("src/draw/shader/test_frag.spv").exists());
+    // Parse flags
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            exit(2);
+        }
+    };
+    let mode = match args.command {
+        cli::Command::Run(mode) => mode,
+        cli::Command::Help => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        cli::Command::Version => {
+            println!("{}", cli::version());
+            return;
+        }
+        cli::Command::CheckConfig => {
+            match config::Config::load(args.config.as_deref(), &args.overrides) {
+                Ok(config) => match input::bindings::Bindings::new(&config.bindings) {
+                    Ok(_) => println!("config ok"),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        exit(1);
+                    }
+                },
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
+            return;
+        }
+        cli::Command::ListGpus => {
+            let config = match config::Config::load(args.config.as_deref(), &args.overrides) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            };
+            if let Err(e) = draw::list_gpus(&config.gpu) {
+                eprintln!("{}", e);
+                exit(e.exit_code());
+            }
+            return;
+        }
+        cli::Command::History(command) => {
+            let mut history = launcher::history::History::load();
+            match command {
+                cli::HistoryCommand::List => print_history(&history),
+                cli::HistoryCommand::Clear => {
+                    if let Err(e) = history.clear() {
+                        eprintln!("{}", e);
+                        exit(1);
+                    }
+                }
+            }
+            return;
+        }
+    };
+
     // Load config file
+    let config = match config::Config::load(args.config.as_deref(), &args.overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
 
-    // Parse flags
+    let bindings = match input::bindings::Bindings::new(&config.bindings) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
 
     // Initialize drawing
-    let drawer = draw::Drawer::initialize();
-    // drawer.open_window();
+    let result =
+        draw::Drawer::initialize(&config, mode, bindings).and_then(|drawer| drawer.listen_events());
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(e.exit_code());
+    }
+}
+
+fn print_history(history: &launcher::history::History) {
+    let now = launcher::history::unix_now();
+    for (id, record) in history.records() {
+        println!(
+            "{:>8.1}  {:>6.1}  {:>8}  {}  {:?}",
+            record.frecency(now),
+            record.rank,
+            age(now.saturating_sub(record.last_launch)),
+            id,
+            record.last_query
+        );
+    }
+}
 
-    drawer.listen_events();
+// A short rough duration such as `3h ago`.
+fn age(seconds: u64) -> String {
+    let (value, unit) = match seconds {
+        s if s < 60 => (s, "s"),
+        s if s < 60 * 60 => (s / 60, "m"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "h"),
+        s => (s / (24 * 60 * 60), "d"),
+    };
+    format!("{}{} ago", value, unit)
 }
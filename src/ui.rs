@@ -0,0 +1,599 @@
+use crate::draw::text::TextStyle;
+use std::path::PathBuf;
+
+/// Measures text without drawing it, so that layout can be computed without a GPU.
+pub trait Measure {
+    fn measure(&self, text: &str, style: &TextStyle) -> [f32; 2];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        let [x, y] = point;
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The rectangle with every coordinate multiplied by `factor`.
+    pub fn scaled(&self, factor: f32) -> Rect {
+        Rect::new(
+            self.x * factor,
+            self.y * factor,
+            self.width * factor,
+            self.height * factor,
+        )
+    }
+
+    fn shrink(&self, padding: &Edges) -> Rect {
+        Rect {
+            x: self.x + padding.left,
+            y: self.y + padding.top,
+            width: (self.width - padding.left - padding.right).max(0.0),
+            height: (self.height - padding.top - padding.bottom).max(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Edges {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Edges {
+    pub fn all(size: f32) -> Edges {
+        Edges {
+            top: size,
+            right: size,
+            bottom: size,
+            left: size,
+        }
+    }
+
+    pub fn symmetric(vertical: f32, horizontal: f32) -> Edges {
+        Edges {
+            top: vertical,
+            right: horizontal,
+            bottom: vertical,
+            left: horizontal,
+        }
+    }
+
+    fn horizontal(&self) -> f32 {
+        self.left + self.right
+    }
+
+    fn vertical(&self) -> f32 {
+        self.top + self.bottom
+    }
+}
+
+/// How much space a widget takes along one axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    /// Just enough for the content.
+    Shrink,
+    /// Exactly this many pixels.
+    Fixed(f32),
+    /// A share of the space left over by the siblings, proportional to the weight. Outside of a
+    /// row or column along its axis this fills the whole parent.
+    Fill(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+impl Align {
+    fn offset(self, free: f32) -> f32 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => (free / 2.0).max(0.0),
+            Align::End => free.max(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Kind {
+    Row {
+        children: Vec<Widget>,
+        spacing: f32,
+    },
+    Column {
+        children: Vec<Widget>,
+        spacing: f32,
+    },
+    /// Children drawn on top of each other, the first one at the bottom.
+    Stack(Vec<Widget>),
+    Text {
+        text: String,
+        style: TextStyle,
+    },
+    /// An image file drawn at its natural size of `size` pixels square.
+    Icon {
+        path: PathBuf,
+        size: f32,
+    },
+    Rect {
+        color: [f32; 4],
+        radius: f32,
+    },
+}
+
+/// A node in the widget tree. Every widget is placed within the slot its parent gives it, sized
+/// according to `width` and `height` and aligned within the slot when it ends up smaller.
+#[derive(Debug, Clone)]
+pub struct Widget {
+    pub kind: Kind,
+    pub width: Length,
+    pub height: Length,
+    pub padding: Edges,
+    pub align: (Align, Align),
+    /// Identifies the widget to `hit`, for widgets that react to the pointer.
+    pub tag: Option<usize>,
+}
+
+impl Widget {
+    fn new(kind: Kind) -> Widget {
+        Widget {
+            kind,
+            width: Length::Shrink,
+            height: Length::Shrink,
+            padding: Edges::default(),
+            align: (Align::Start, Align::Start),
+            tag: None,
+        }
+    }
+
+    pub fn row(children: Vec<Widget>) -> Widget {
+        Widget::new(Kind::Row {
+            children,
+            spacing: 0.0,
+        })
+    }
+
+    pub fn column(children: Vec<Widget>) -> Widget {
+        Widget::new(Kind::Column {
+            children,
+            spacing: 0.0,
+        })
+    }
+
+    pub fn stack(children: Vec<Widget>) -> Widget {
+        Widget::new(Kind::Stack(children))
+    }
+
+    pub fn text<S: Into<String>>(text: S, style: TextStyle) -> Widget {
+        Widget::new(Kind::Text {
+            text: text.into(),
+            style,
+        })
+    }
+
+    pub fn icon<P: Into<PathBuf>>(path: P, size: f32) -> Widget {
+        Widget::new(Kind::Icon {
+            path: path.into(),
+            size,
+        })
+    }
+
+    /// A rectangle filling its slot, with corners rounded by `radius` pixels.
+    pub fn rect(color: [f32; 4], radius: f32) -> Widget {
+        Widget {
+            width: Length::Fill(1),
+            height: Length::Fill(1),
+            ..Widget::new(Kind::Rect { color, radius })
+        }
+    }
+
+    pub fn width(self, width: Length) -> Widget {
+        Widget { width, ..self }
+    }
+
+    pub fn height(self, height: Length) -> Widget {
+        Widget { height, ..self }
+    }
+
+    pub fn padding(self, padding: Edges) -> Widget {
+        Widget { padding, ..self }
+    }
+
+    pub fn align(self, horizontal: Align, vertical: Align) -> Widget {
+        Widget {
+            align: (horizontal, vertical),
+            ..self
+        }
+    }
+
+    pub fn tag(self, tag: usize) -> Widget {
+        Widget {
+            tag: Some(tag),
+            ..self
+        }
+    }
+
+    /// Space between the children of a row or column.
+    pub fn spacing(mut self, size: f32) -> Widget {
+        match &mut self.kind {
+            Kind::Row { spacing, .. } | Kind::Column { spacing, .. } => *spacing = size,
+            _ => {}
+        }
+        self
+    }
+
+    /// The size this widget wants, including its padding.
+    pub fn measure<M: Measure>(&self, m: &M) -> [f32; 2] {
+        self.measured(m).size
+    }
+
+    // Measures the whole tree bottom up, so that layout measures every widget once.
+    fn measured<M: Measure>(&self, m: &M) -> Measured {
+        let children = self
+            .children()
+            .iter()
+            .map(|c| c.measured(m))
+            .collect::<Vec<_>>();
+        let sizes = || children.iter().map(|c| c.size);
+        let content = match &self.kind {
+            Kind::Row { spacing, .. } => [
+                sizes().map(|s| s[0]).sum::<f32>() + gaps(children.len(), *spacing),
+                sizes().map(|s| s[1]).fold(0.0, f32::max),
+            ],
+            Kind::Column { spacing, .. } => [
+                sizes().map(|s| s[0]).fold(0.0, f32::max),
+                sizes().map(|s| s[1]).sum::<f32>() + gaps(children.len(), *spacing),
+            ],
+            Kind::Stack(_) => sizes().fold([0.0f32, 0.0], |a, s| [a[0].max(s[0]), a[1].max(s[1])]),
+            Kind::Text { text, style } => m.measure(text, style),
+            Kind::Icon { size, .. } => [*size, *size],
+            Kind::Rect { .. } => [0.0, 0.0],
+        };
+        let natural = |length, content: f32, padding| match length {
+            Length::Fixed(size) => size,
+            _ => content + padding,
+        };
+        Measured {
+            size: [
+                natural(self.width, content[0], self.padding.horizontal()),
+                natural(self.height, content[1], self.padding.vertical()),
+            ],
+            children,
+        }
+    }
+
+    fn children(&self) -> &[Widget] {
+        match &self.kind {
+            Kind::Row { children, .. } | Kind::Column { children, .. } | Kind::Stack(children) => {
+                children
+            }
+            _ => &[],
+        }
+    }
+
+    /// Computes the layout of the tree within `bounds` and returns what to draw, back to front.
+    pub fn layout<M: Measure>(&self, bounds: Rect, m: &M) -> Vec<Primitive> {
+        let mut out = Vec::new();
+        self.place(&self.measured(m), bounds, &mut out);
+        out
+    }
+
+    /// The tag of the innermost tagged widget under `point` when laid out within `bounds`.
+    pub fn hit<M: Measure>(&self, bounds: Rect, m: &M, point: [f32; 2]) -> Option<usize> {
+        self.layout(bounds, m)
+            .into_iter()
+            .filter_map(|primitive| match primitive {
+                Primitive::Area { rect, tag } if rect.contains(point) => Some(tag),
+                _ => None,
+            })
+            .next_back()
+    }
+
+    fn place(&self, measured: &Measured, slot: Rect, out: &mut Vec<Primitive>) {
+        let natural = measured.size;
+        let size = |length, natural: f32, available: f32| match length {
+            Length::Fill(_) => available,
+            _ => natural.min(available),
+        };
+        let width = size(self.width, natural[0], slot.width);
+        let height = size(self.height, natural[1], slot.height);
+        let bounds = Rect {
+            x: slot.x + self.align.0.offset(slot.width - width),
+            y: slot.y + self.align.1.offset(slot.height - height),
+            width,
+            height,
+        };
+        let inner = bounds.shrink(&self.padding);
+        if let Some(tag) = self.tag {
+            out.push(Primitive::Area { rect: bounds, tag });
+        }
+
+        match &self.kind {
+            Kind::Row { children, spacing } => {
+                let slots = distribute(children, measured, *spacing, inner.width, |w| w.width, 0);
+                for ((child, measured), (offset, length)) in
+                    children.iter().zip(&measured.children).zip(slots)
+                {
+                    let slot = Rect::new(inner.x + offset, inner.y, length, inner.height);
+                    child.place(measured, slot, out);
+                }
+            }
+            Kind::Column { children, spacing } => {
+                let slots = distribute(children, measured, *spacing, inner.height, |w| w.height, 1);
+                for ((child, measured), (offset, length)) in
+                    children.iter().zip(&measured.children).zip(slots)
+                {
+                    let slot = Rect::new(inner.x, inner.y + offset, inner.width, length);
+                    child.place(measured, slot, out);
+                }
+            }
+            Kind::Stack(children) => {
+                for (child, measured) in children.iter().zip(&measured.children) {
+                    child.place(measured, inner, out);
+                }
+            }
+            Kind::Text { text, style } => out.push(Primitive::Text {
+                pos: [inner.x, inner.y],
+                text: text.clone(),
+                style: *style,
+            }),
+            Kind::Icon { path, .. } => out.push(Primitive::Icon {
+                rect: inner,
+                path: path.clone(),
+            }),
+            Kind::Rect { color, radius } => out.push(Primitive::Rect {
+                rect: bounds,
+                color: *color,
+                radius: *radius,
+            }),
+        }
+    }
+}
+
+// The natural size of a widget, followed by those of its children in the same order.
+struct Measured {
+    size: [f32; 2],
+    children: Vec<Measured>,
+}
+
+/// Something to draw, in window pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Primitive {
+    Rect {
+        rect: Rect,
+        color: [f32; 4],
+        radius: f32,
+    },
+    Text {
+        pos: [f32; 2],
+        text: String,
+        style: TextStyle,
+    },
+    Icon {
+        rect: Rect,
+        path: PathBuf,
+    },
+    /// Where a tagged widget ended up, which is not drawn.
+    Area {
+        rect: Rect,
+        tag: usize,
+    },
+}
+
+fn gaps(count: usize, spacing: f32) -> f32 {
+    count.saturating_sub(1) as f32 * spacing
+}
+
+// Splits `available` along one axis of a row or column, measured as `measured`. Shrink and fixed children get their
+// natural size and whatever is left is shared between the fill children by weight. Returns the
+// offset and length of every child's slot.
+fn distribute<F>(
+    children: &[Widget],
+    measured: &Measured,
+    spacing: f32,
+    available: f32,
+    length: F,
+    axis: usize,
+) -> Vec<(f32, f32)>
+where
+    F: Fn(&Widget) -> Length,
+{
+    let sizes = children
+        .iter()
+        .zip(&measured.children)
+        .map(|(child, measured)| match length(child) {
+            Length::Fill(weight) => Err(weight),
+            _ => Ok(measured.size[axis]),
+        })
+        .collect::<Vec<_>>();
+    let fixed: f32 = sizes.iter().filter_map(|s| s.ok()).sum();
+    let weights: u32 = sizes.iter().filter_map(|s| s.err()).sum();
+    let free = (available - fixed - gaps(children.len(), spacing)).max(0.0);
+
+    let mut offset = 0.0;
+    sizes
+        .into_iter()
+        .map(|size| {
+            let length = match size {
+                Ok(size) => size,
+                Err(weight) if weights > 0 => free * weight as f32 / weights as f32,
+                Err(_) => 0.0,
+            };
+            let slot = (offset, length);
+            offset += length + spacing;
+            slot
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const STYLE: TextStyle = TextStyle {
+        size: 10.0,
+        color: [1.0; 4],
+    };
+
+    // Every character is 8 pixels wide and lines are as high as the text size.
+    #[derive(Default)]
+    struct Fixed {
+        calls: Cell<usize>,
+    }
+
+    impl Measure for Fixed {
+        fn measure(&self, text: &str, style: &TextStyle) -> [f32; 2] {
+            self.calls.set(self.calls.get() + 1);
+            [text.chars().count() as f32 * 8.0, style.size]
+        }
+    }
+
+    fn text(text: &str) -> Widget {
+        Widget::text(text, STYLE)
+    }
+
+    // Where the text and rectangles ended up, in the order they are drawn.
+    fn rects(widget: &Widget, bounds: Rect) -> Vec<Rect> {
+        widget
+            .layout(bounds, &Fixed::default())
+            .into_iter()
+            .filter_map(|primitive| match primitive {
+                Primitive::Text { pos, .. } => Some(Rect::new(pos[0], pos[1], 0.0, 0.0)),
+                Primitive::Rect { rect, .. } | Primitive::Icon { rect, .. } => Some(rect),
+                Primitive::Area { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn row_with_spacing_and_padding() {
+        let row = Widget::row(vec![text("ab"), Widget::icon("a.png", 12.0), text("c")])
+            .spacing(4.0)
+            .padding(Edges::symmetric(2.0, 3.0));
+        assert_eq!(
+            row.measure(&Fixed::default()),
+            [3.0 + 16.0 + 4.0 + 12.0 + 4.0 + 8.0 + 3.0, 16.0]
+        );
+        assert_eq!(
+            rects(&row, Rect::new(10.0, 20.0, 200.0, 100.0)),
+            [
+                Rect::new(13.0, 22.0, 0.0, 0.0),
+                Rect::new(33.0, 22.0, 12.0, 12.0),
+                Rect::new(49.0, 22.0, 0.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_shares_by_weight() {
+        let row = Widget::row(vec![
+            Widget::rect([0.0; 4], 0.0).width(Length::Fixed(20.0)),
+            Widget::rect([0.0; 4], 0.0),
+            Widget::rect([0.0; 4], 0.0).width(Length::Fill(3)),
+        ])
+        .spacing(10.0)
+        .width(Length::Fill(1))
+        .height(Length::Fill(1));
+        assert_eq!(
+            rects(&row, Rect::new(0.0, 0.0, 120.0, 30.0)),
+            [
+                Rect::new(0.0, 0.0, 20.0, 30.0),
+                Rect::new(30.0, 0.0, 20.0, 30.0),
+                Rect::new(60.0, 0.0, 60.0, 30.0),
+            ]
+        );
+
+        // Nothing is left for fill children when the others take up all the space
+        let column = Widget::column(vec![
+            text("a").height(Length::Fixed(50.0)),
+            Widget::rect([0.0; 4], 0.0),
+        ])
+        .width(Length::Fill(1))
+        .height(Length::Fill(1));
+        assert_eq!(
+            rects(&column, Rect::new(0.0, 0.0, 40.0, 30.0)),
+            [
+                Rect::new(0.0, 0.0, 0.0, 0.0),
+                Rect::new(0.0, 50.0, 40.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn column_alignment() {
+        let column = Widget::column(vec![
+            text("abcd").align(Align::Center, Align::Start),
+            text("ab").align(Align::End, Align::Start),
+            text("a"),
+        ])
+        .spacing(5.0)
+        .width(Length::Fill(1))
+        .height(Length::Fill(1));
+        assert_eq!(column.measure(&Fixed::default()), [32.0, 40.0]);
+        assert_eq!(
+            rects(&column, Rect::new(0.0, 0.0, 100.0, 100.0)),
+            [
+                Rect::new(34.0, 0.0, 0.0, 0.0),
+                Rect::new(84.0, 15.0, 0.0, 0.0),
+                Rect::new(0.0, 30.0, 0.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn stack_layers_children() {
+        let stack = Widget::stack(vec![
+            Widget::rect([0.0; 4], 4.0),
+            text("abc").align(Align::Center, Align::Center),
+            Widget::icon("a.png", 8.0).align(Align::End, Align::End),
+        ])
+        .padding(Edges::all(5.0))
+        .width(Length::Fixed(60.0))
+        .height(Length::Fixed(40.0))
+        .align(Align::Center, Align::Center)
+        .tag(7);
+        let bounds = Rect::new(0.0, 0.0, 100.0, 100.0);
+        assert_eq!(
+            rects(&stack, bounds),
+            [
+                Rect::new(25.0, 35.0, 50.0, 30.0),
+                Rect::new(38.0, 45.0, 0.0, 0.0),
+                Rect::new(67.0, 57.0, 8.0, 8.0),
+            ]
+        );
+        assert_eq!(stack.hit(bounds, &Fixed::default(), [30.0, 40.0]), Some(7));
+        assert_eq!(stack.hit(bounds, &Fixed::default(), [10.0, 40.0]), None);
+    }
+
+    #[test]
+    fn text_is_measured_once_per_layout() {
+        let tree = Widget::column(vec![
+            Widget::row(vec![text("a"), Widget::row(vec![text("b"), text("c")])]),
+            Widget::stack(vec![text("d")]),
+        ]);
+        let m = Fixed::default();
+        tree.layout(Rect::new(0.0, 0.0, 100.0, 100.0), &m);
+        assert_eq!(m.calls.get(), 4);
+    }
+}